@@ -1,10 +1,186 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_nonuniform_qualifier : require
 
-layout(location = 0) in vec3 fragColor;
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 viewProjection;
+    mat4 inverseView;
+    mat4 inverseProjection;
+    vec4 position;
+} camera;
+
+struct Material {
+    vec4 baseColor;
+    vec3 emissive;
+    float metallic;
+    float roughness;
+    uint baseColorTexture;
+    uint metallicRoughnessTexture;
+    uint normalTexture;
+    uint occlusionTexture;
+    uint emissiveTexture;
+    uint alphaMode;
+    float alphaCutoff;
+};
+
+layout(set = 1, binding = 1) readonly buffer Materials {
+    Material materials[];
+};
+
+struct Light {
+    vec3 position;
+    float range;
+    vec3 direction;
+    uint kind;
+    vec3 color;
+    float innerCos;
+    float outerCos;
+};
+
+layout(set = 1, binding = 2) readonly buffer Lights {
+    Light lights[];
+};
+
+layout(set = 2, binding = 0) uniform sampler2D textures[];
+
+layout(push_constant) uniform PushConstants {
+    uint lightCount;
+};
+
+layout(location = 0) in vec3 worldPosition;
+layout(location = 1) in vec3 worldNormal;
+layout(location = 2) in vec4 worldTangent;
+layout(location = 3) in vec2 fragUv;
+layout(location = 4) flat in uint material;
 
 layout(location = 0) out vec4 outColor;
 
+const uint NO_TEXTURE = 0xffffffffu;
+
+const uint ALPHA_BLEND = 2;
+
+const uint LIGHT_DIRECTIONAL = 0;
+const uint LIGHT_SPOT = 2;
+
+const float PI = 3.14159265359;
+
+// Stand-in for indirect light until there is image based lighting.
+const vec3 AMBIENT = vec3(0.03);
+
+vec4 sampleTexture(uint index, vec4 fallback) {
+    if (index == NO_TEXTURE) {
+        return fallback;
+    }
+    return texture(textures[nonuniformEXT(index)], fragUv);
+}
+
+// Trowbridge-Reitz normal distribution, alpha being the squared roughness.
+float distributionGgx(float nDotH, float alpha) {
+    float alpha2 = alpha * alpha;
+    float d = nDotH * nDotH * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+// Height correlated Smith masking-shadowing, divided by 4 nDotL nDotV.
+float visibilitySmithGgx(float nDotL, float nDotV, float alpha) {
+    float alpha2 = alpha * alpha;
+    float ggxV = nDotL * sqrt(nDotV * nDotV * (1.0 - alpha2) + alpha2);
+    float ggxL = nDotV * sqrt(nDotL * nDotL * (1.0 - alpha2) + alpha2);
+    return 0.5 / max(ggxV + ggxL, 1e-5);
+}
+
+vec3 fresnelSchlick(float vDotH, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - vDotH, 5.0);
+}
+
+// Radiance reaching `position` from `light`, with `l` the direction towards
+// the light.
+vec3 incomingLight(Light light, vec3 position, out vec3 l) {
+    if (light.kind == LIGHT_DIRECTIONAL) {
+        l = -light.direction;
+        return light.color;
+    }
+
+    vec3 toLight = light.position - position;
+    float distance2 = max(dot(toLight, toLight), 1e-4);
+    l = toLight * inversesqrt(distance2);
+
+    // Inverse square falloff, windowed to reach zero at the range.
+    float ratio = distance2 / (light.range * light.range);
+    float window = clamp(1.0 - ratio * ratio, 0.0, 1.0);
+    float attenuation = window * window / distance2;
+
+    if (light.kind == LIGHT_SPOT) {
+        attenuation *= smoothstep(light.outerCos, light.innerCos, dot(light.direction, -l));
+    }
+
+    return light.color * attenuation;
+}
+
+// Narkowicz's fit of the ACES filmic curve.
+vec3 tonemap(vec3 color) {
+    return clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), 0.0, 1.0);
+}
+
 void main() {
-    outColor = vec4(fragColor, 1.0);
+    Material m = materials[material];
+
+    vec4 baseColor = m.baseColor * sampleTexture(m.baseColorTexture, vec4(1.0));
+#ifdef ALPHA_MASK
+    if (baseColor.a < m.alphaCutoff) {
+        discard;
+    }
+#endif
+
+    // Occlusion, roughness and metallic may share one texture, in R, G and B.
+    vec4 metallicRoughness = sampleTexture(m.metallicRoughnessTexture, vec4(1.0));
+    float metallic = clamp(m.metallic * metallicRoughness.b, 0.0, 1.0);
+    float roughness = clamp(m.roughness * metallicRoughness.g, 0.04, 1.0);
+    float alpha = roughness * roughness;
+    float occlusion = sampleTexture(m.occlusionTexture, vec4(1.0)).r;
+    vec3 emissive = m.emissive * sampleTexture(m.emissiveTexture, vec4(1.0)).rgb;
+
+    // Only double sided materials have back faces drawn.
+    vec3 n = normalize(gl_FrontFacing ? worldNormal : -worldNormal);
+#ifdef NORMAL_MAP
+    // The texture may still be loading, the fallback keeps the normal as is.
+    vec3 t = normalize(worldTangent.xyz - n * dot(n, worldTangent.xyz));
+    vec3 b = cross(n, t) * worldTangent.w;
+    vec3 tangentNormal = sampleTexture(m.normalTexture, vec4(0.5, 0.5, 1.0, 1.0)).xyz * 2.0 - 1.0;
+    n = normalize(mat3(t, b, n) * tangentNormal);
+#endif
+
+    vec3 v = normalize(camera.position.xyz - worldPosition);
+    float nDotV = max(dot(n, v), 1e-4);
+
+    vec3 f0 = mix(vec3(0.04), baseColor.rgb, metallic);
+    vec3 diffuseColor = baseColor.rgb * (1.0 - metallic);
+
+    vec3 color = vec3(0.0);
+    for (uint i = 0; i < lightCount; i++) {
+        vec3 l;
+        vec3 radiance = incomingLight(lights[i], worldPosition, l);
+        float nDotL = dot(n, l);
+        if (nDotL <= 0.0) {
+            continue;
+        }
+
+        vec3 h = normalize(l + v);
+        float nDotH = max(dot(n, h), 0.0);
+        float vDotH = max(dot(v, h), 0.0);
+
+        vec3 f = fresnelSchlick(vDotH, f0);
+        vec3 specular = f * distributionGgx(nDotH, alpha) * visibilitySmithGgx(nDotL, nDotV, alpha);
+        vec3 diffuse = (1.0 - f) * diffuseColor / PI;
+
+        color += (diffuse + specular) * radiance * nDotL;
+    }
+
+    color += AMBIENT * baseColor.rgb * occlusion;
+    color += emissive;
+
+    // The swapchain is sRGB, the linear result is encoded when stored.
+    outColor = vec4(tonemap(color), m.alphaMode == ALPHA_BLEND ? baseColor.a : 1.0);
 }
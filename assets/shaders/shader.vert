@@ -1,19 +1,44 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
 
-layout(location = 0) out vec3 fragColor;
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 viewProjection;
+    mat4 inverseView;
+    mat4 inverseProjection;
+    vec4 position;
+} camera;
 
-vec2 positions[3] = vec2[](
-vec2(-0.5, -0.5),
-vec2(0.5, -0.5),
-vec2(0.0, 0.5));
+struct Instance {
+    mat4 model;
+    mat4 normal;
+    uint material;
+};
 
-vec3 colors[3] = vec3[](
-vec3(1.0, 0.0, 0.0),
-vec3(0.0, 1.0, 0.0),
-vec3(0.0, 0.0, 1.0));
+layout(set = 1, binding = 0) readonly buffer Instances {
+    Instance instances[];
+};
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec4 tangent;
+layout(location = 3) in vec2 uv;
+
+layout(location = 0) out vec3 worldPosition;
+layout(location = 1) out vec3 worldNormal;
+layout(location = 2) out vec4 worldTangent;
+layout(location = 3) out vec2 fragUv;
+layout(location = 4) flat out uint material;
 
 void main() {
-    gl_Position = vec4(positions[gl_VertexIndex], 0.0, 1.0);
-    fragColor = colors[gl_VertexIndex];
+    Instance instance = instances[gl_InstanceIndex];
+
+    vec4 world = instance.model * vec4(position, 1.0);
+    gl_Position = camera.viewProjection * world;
+    worldPosition = world.xyz;
+    worldNormal = mat3(instance.normal) * normal;
+    worldTangent = vec4(mat3(instance.model) * tangent.xyz, tangent.w);
+    fragUv = uv;
+    material = instance.material;
 }
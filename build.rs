@@ -8,10 +8,18 @@ use std::{
     process::{Command, Output},
 };
 
+/// Flags each shader is also compiled with, every combination of them to
+/// `name.FLAG[.FLAG…].spv` in alphabetical order, as loaded by `ShaderVariants`.
+const SHADER_VARIANTS: &[(&str, &[&str])] = &[("shader.frag", &["ALPHA_MASK", "NORMAL_MAP"])];
+
 fn main() {
     if !should_skip_shader_compilation() {
         compile_shaders();
     }
+
+    if var("CARGO_FEATURE_EMBED_SHADERS").is_ok() {
+        embed_shaders();
+    }
 }
 
 fn should_skip_shader_compilation() -> bool {
@@ -33,23 +41,76 @@ fn compile_shaders() {
         .for_each(|dir| {
             let path = dir.path();
             let name = path.file_name().unwrap().to_str().unwrap();
-            let output_name = format!("{}.spv", &name);
             println!("Found file {:?}.\nCompiling...", path.as_os_str());
 
-            let result = Command::new("glslangValidator")
-                .current_dir(&shader_dir_path)
-                .arg("-V")
-                .arg(&path)
-                .arg("-o")
-                .arg(output_name)
-                .arg("--target-env")
-                .arg("spirv1.5")
-                .output();
-
-            handle_program_result(result);
+            let mut flags = SHADER_VARIANTS
+                .iter()
+                .find(|(shader, _)| *shader == name)
+                .map(|(_, flags)| flags.to_vec())
+                .unwrap_or_default();
+            flags.sort_unstable();
+
+            for variant in 0..1 << flags.len() {
+                let enabled = flags
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| variant & 1 << index != 0)
+                    .map(|(_, flag)| *flag)
+                    .collect::<Vec<_>>();
+
+                let mut output_name = name.to_string();
+                for flag in &enabled {
+                    output_name.push('.');
+                    output_name.push_str(flag);
+                }
+                output_name.push_str(".spv");
+
+                let result = Command::new("glslangValidator")
+                    .current_dir(&shader_dir_path)
+                    .arg("-V")
+                    .arg(&path)
+                    .args(enabled.iter().map(|flag| format!("-D{}=1", flag)))
+                    .arg("-o")
+                    .arg(output_name)
+                    .arg("--target-env")
+                    .arg("spirv1.5")
+                    .output();
+
+                handle_program_result(result);
+            }
         })
 }
 
+fn embed_shaders() {
+    let shader_dir_path = get_shader_source_dir_path();
+
+    let entries = fs::read_dir(&shader_dir_path)
+        .unwrap()
+        .map(Result::unwrap)
+        .filter(|dir| dir.path().extension() == Some(OsStr::new("spv")))
+        .map(|dir| {
+            let path = dir.path();
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+            println!("cargo:rerun-if-changed={}", path.display());
+            format!(
+                "    (\"shaders/{}\", include_bytes!({:?})),\n",
+                name,
+                path.as_os_str()
+            )
+        })
+        .collect::<String>();
+
+    let output = PathBuf::from(var("OUT_DIR").unwrap()).join("embedded_shaders.rs");
+    fs::write(
+        output,
+        format!(
+            "pub static EMBEDDED_SHADERS: &[(&str, &[u8])] = &[\n{}];\n",
+            entries
+        ),
+    )
+    .unwrap();
+}
+
 fn get_shader_source_dir_path() -> PathBuf {
     let path = get_root_path().join("assets").join("shaders");
     println!("Shader source directory: {:?}", path.as_os_str());
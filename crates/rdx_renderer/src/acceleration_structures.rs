@@ -2,7 +2,6 @@ use crate::buffer::{BufferRegion, DeviceAddress};
 use crate::resources::AccelerationStructure;
 use crevice::internal::bytemuck;
 use erupt::vk;
-use glam::Mat4;
 
 #[derive(Clone)]
 pub struct AccelerationStructureInfo {
@@ -55,12 +54,43 @@ pub enum AccelerationStructureGeometry {
     },
 }
 
+/// An instance of a bottom level structure in a top level build, laid out
+/// as `VkAccelerationStructureInstanceKHR`.
 #[derive(Clone, Copy)]
 #[repr(align(16))]
 #[repr(C)]
 pub struct AccelerationStructureInstance {
-    pub transform: Mat4,
-    pub acceleration_structure_reference: DeviceAddress,
+    /// Rows of the object to world transform, without the last one.
+    pub transform: [[f32; 4]; 3],
+    /// Custom index in the low 24 bits, visibility mask in the high 8.
+    pub custom_index_and_mask: u32,
+    /// Hit group offset in the low 24 bits, `GeometryInstanceFlagsKHR` in
+    /// the high 8.
+    pub shader_binding_table_offset_and_flags: u32,
+    pub acceleration_structure_reference: u64,
+}
+
+impl AccelerationStructureInstance {
+    /// An instance of `bottom_level` transformed by the column major `model`.
+    /// Shaders read `custom_index` as `gl_InstanceCustomIndexEXT`, which
+    /// keeps 24 bits.
+    pub fn new(
+        model: [[f32; 4]; 4],
+        custom_index: u32,
+        mask: u8,
+        flags: vk::GeometryInstanceFlagsKHR,
+        bottom_level: &AccelerationStructure,
+    ) -> Self {
+        debug_assert!(custom_index < 1 << 24);
+
+        let row = |row: usize| [model[0][row], model[1][row], model[2][row], model[3][row]];
+        AccelerationStructureInstance {
+            transform: [row(0), row(1), row(2)],
+            custom_index_and_mask: custom_index | (u32::from(mask) << 24),
+            shader_binding_table_offset_and_flags: flags.bits() << 24,
+            acceleration_structure_reference: bottom_level.device_address(),
+        }
+    }
 }
 
 unsafe impl bytemuck::Zeroable for AccelerationStructureInstance {}
@@ -0,0 +1,179 @@
+use bevy::asset::AssetServerSettings;
+use parking_lot::RwLock;
+use std::borrow::Cow;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+static SETTINGS: RwLock<AssetSettings> = parking_lot::const_rwlock(AssetSettings {
+    root: None,
+    embedded: &[],
+});
+
+/// Where the renderer looks for shaders, and through
+/// [`AssetSettings::asset_server_settings`] where bevy's `AssetServer` loads
+/// models and textures from.
+///
+/// Insert it as a resource before adding the `RenderPlugin` to override the
+/// defaults. Relative asset paths are looked up in order under `root`, the
+/// `RDX_ASSET_ROOT` environment variable, `./assets`, `assets` next to the
+/// executable and finally the `assets` directory of the cargo package.
+/// Shaders not found on disk fall back to `embedded`, which the binary can
+/// fill with `include_bytes!` at build time.
+#[derive(Clone, Default)]
+pub struct AssetSettings {
+    pub root: Option<PathBuf>,
+    pub embedded: &'static [(&'static str, &'static [u8])],
+}
+
+impl AssetSettings {
+    /// Settings pointing the `AssetServer` at the first existing search
+    /// location, so models and textures resolve like shaders. Insert them
+    /// before adding bevy's `AssetPlugin`, which reads them once.
+    pub fn asset_server_settings(&self) -> AssetServerSettings {
+        let roots = roots(self.root.as_deref());
+        let root = roots
+            .iter()
+            .find(|root| root.is_dir())
+            .or_else(|| roots.first())
+            .cloned()
+            .unwrap_or_default();
+
+        AssetServerSettings {
+            asset_folder: root.to_string_lossy().into_owned(),
+            ..Default::default()
+        }
+    }
+}
+
+pub enum AssetError {
+    NotFound {
+        path: PathBuf,
+        searched: Vec<PathBuf>,
+    },
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::NotFound { path, searched } => {
+                write!(f, "asset {:?} not found, searched:", path.as_os_str())?;
+                for location in searched {
+                    write!(f, "\n    {}", location.display())?;
+                }
+                Ok(())
+            }
+            AssetError::Io { path, error } => {
+                write!(f, "failed to read asset {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+pub fn configure(settings: AssetSettings) {
+    *SETTINGS.write() = settings;
+}
+
+/// Finds `relative` on disk, returning the first existing candidate.
+pub fn resolve(relative: impl AsRef<Path>) -> Result<PathBuf, AssetError> {
+    let relative = relative.as_ref();
+    let searched = search_roots()
+        .into_iter()
+        .map(|root| root.join(relative))
+        .collect::<Vec<_>>();
+
+    match searched.iter().find(|candidate| candidate.exists()) {
+        Some(path) => Ok(path.clone()),
+        None => Err(AssetError::NotFound {
+            path: relative.to_path_buf(),
+            searched,
+        }),
+    }
+}
+
+/// Like [`resolve`] but falls back to the path under the preferred root, for
+/// callers that want a location even when nothing exists there yet.
+pub fn asset_path(relative: impl AsRef<Path>) -> PathBuf {
+    let relative = relative.as_ref();
+    resolve(relative).unwrap_or_else(|_| {
+        search_roots()
+            .into_iter()
+            .next()
+            .unwrap_or_default()
+            .join(relative)
+    })
+}
+
+pub fn read(relative: impl AsRef<Path>) -> Result<Cow<'static, [u8]>, AssetError> {
+    let relative = relative.as_ref();
+
+    match resolve(relative) {
+        Ok(path) => fs::read(&path)
+            .map(Cow::Owned)
+            .map_err(|error| AssetError::Io { path, error }),
+        Err(error) => embedded(relative).map(Cow::Borrowed).ok_or(error),
+    }
+}
+
+fn embedded(relative: &Path) -> Option<&'static [u8]> {
+    SETTINGS
+        .read()
+        .embedded
+        .iter()
+        .find(|(path, _)| Path::new(path) == relative)
+        .map(|(_, bytes)| *bytes)
+}
+
+fn search_roots() -> Vec<PathBuf> {
+    roots(SETTINGS.read().root.as_deref())
+}
+
+fn roots(root: Option<&Path>) -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+
+    if let Some(root) = root {
+        roots.push(root.to_path_buf());
+    }
+
+    if let Some(root) = env::var_os("RDX_ASSET_ROOT") {
+        roots.push(PathBuf::from(root));
+    }
+
+    if let Ok(current_dir) = env::current_dir() {
+        roots.push(current_dir.join("assets"));
+    }
+
+    if let Some(exe_dir) = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        roots.push(exe_dir.join("assets"));
+    }
+
+    if let Some(manifest_dir) = env::var_os("CARGO_MANIFEST_DIR") {
+        roots.push(PathBuf::from(manifest_dir).join("assets"));
+    }
+
+    roots.push(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join("..")
+            .join("assets"),
+    );
+
+    roots
+}
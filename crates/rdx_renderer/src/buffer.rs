@@ -1,14 +1,20 @@
 use crate::resources::Buffer;
 use crate::util::align_up;
+use crevice::internal::bytemuck;
 use erupt::vk;
 use gpu_alloc::UsageFlags;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
 use std::num::NonZeroU64;
+use std::ops::Range;
 
 pub struct BufferInfo {
     pub align: u64,
     pub size: u64,
     pub usage_flags: vk::BufferUsageFlags,
     pub allocation_flags: UsageFlags,
+    pub name: Option<Box<str>>,
 }
 
 impl BufferInfo {
@@ -42,12 +48,158 @@ impl BufferRegion {
     }
 }
 
-#[derive(Clone, Copy)]
+/// Synchronizes access to a buffer range. `family_transfer` moves ownership
+/// from the first queue family to the second; record the same barrier on both
+/// queues, releasing on the source and acquiring on the destination.
+pub struct BufferMemoryBarrier<'a> {
+    pub buffer: &'a Buffer,
+    pub offset: u64,
+    pub size: u64,
+    pub family_transfer: Option<Range<u32>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct DeviceAddress(pub NonZeroU64);
 
 impl DeviceAddress {
-    pub fn offset(&mut self, offset: u64) -> DeviceAddress {
+    pub fn offset(self, offset: u64) -> DeviceAddress {
         let value = self.0.get().checked_add(offset).unwrap();
         DeviceAddress(unsafe { NonZeroU64::new_unchecked(value) })
     }
 }
+
+/// Typed device address of a `T`, created with `Buffer::ptr`.
+///
+/// It is laid out as a single `uint64_t` so it can be written into push
+/// constants or uniform data and declared on the shader side as a
+/// `GL_EXT_buffer_reference` block.
+#[repr(transparent)]
+pub struct GpuPtr<T> {
+    address: u64,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> GpuPtr<T> {
+    pub(crate) fn new(address: DeviceAddress) -> Self {
+        GpuPtr {
+            address: address.0.get(),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn address(&self) -> DeviceAddress {
+        DeviceAddress(NonZeroU64::new(self.address).expect("Null gpu pointer"))
+    }
+
+    /// Reinterprets the pointee. The caller keeps `U` within the buffer.
+    pub fn cast<U>(self) -> GpuPtr<U> {
+        GpuPtr {
+            address: self.address,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for GpuPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for GpuPtr<T> {}
+
+impl<T> fmt::Debug for GpuPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GpuPtr({:#x})", self.address)
+    }
+}
+
+unsafe impl<T: 'static> bytemuck::Zeroable for GpuPtr<T> {}
+unsafe impl<T: 'static> bytemuck::Pod for GpuPtr<T> {}
+
+/// Typed device address of `len` consecutive `T`s, created with
+/// `Buffer::slice`.
+///
+/// Laid out as a `uint64_t` address followed by a `uint` length and padding,
+/// matching a shader struct of a buffer reference and its element count.
+#[repr(C)]
+pub struct GpuSlice<T> {
+    ptr: GpuPtr<T>,
+    len: u32,
+    _padding: u32,
+}
+
+impl<T> GpuSlice<T> {
+    pub(crate) fn new(address: DeviceAddress, len: u32) -> Self {
+        GpuSlice {
+            ptr: GpuPtr::new(address),
+            len,
+            _padding: 0,
+        }
+    }
+
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_ptr(&self) -> GpuPtr<T> {
+        self.ptr
+    }
+
+    pub fn address(&self) -> DeviceAddress {
+        self.ptr.address()
+    }
+
+    /// Pointer to element `index`, panicking when it is out of bounds.
+    pub fn get(&self, index: u32) -> GpuPtr<T> {
+        assert!(
+            index < self.len,
+            "index {} is out of bounds of a gpu slice of {} elements",
+            index,
+            self.len
+        );
+
+        GpuPtr::new(
+            self.address()
+                .offset(index as u64 * mem::size_of::<T>() as u64),
+        )
+    }
+
+    /// Sub-slice over the elements in `range`, panicking when it is out of
+    /// bounds.
+    pub fn slice(&self, range: Range<u32>) -> GpuSlice<T> {
+        assert!(
+            range.start <= range.end && range.end <= self.len,
+            "range {:?} is out of bounds of a gpu slice of {} elements",
+            range,
+            self.len
+        );
+
+        GpuSlice::new(
+            self.address()
+                .offset(range.start as u64 * mem::size_of::<T>() as u64),
+            range.end - range.start,
+        )
+    }
+}
+
+impl<T> Clone for GpuSlice<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for GpuSlice<T> {}
+
+impl<T> fmt::Debug for GpuSlice<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GpuSlice({:#x}, {})", self.ptr.address, self.len)
+    }
+}
+
+unsafe impl<T: 'static> bytemuck::Zeroable for GpuSlice<T> {}
+unsafe impl<T: 'static> bytemuck::Pod for GpuSlice<T> {}
@@ -0,0 +1,216 @@
+use crate::descriptor::{
+    DescriptorSetInfo, DescriptorSetLayoutBinding, DescriptorSetLayoutInfo, Descriptors,
+    WriteDescriptorSet,
+};
+use crate::device::Device;
+use crate::frame_allocator::FrameAllocator;
+use crate::resources::{DescriptorSet, DescriptorSetLayout};
+use bevy::math::{Mat4, Vec4};
+use bevy::transform::components::GlobalTransform;
+use crevice::std140::AsStd140;
+use erupt::vk;
+use std::mem;
+
+/// Renders the scene from this entity's `GlobalTransform`, looking down its
+/// local -Z axis with +Y up.
+///
+/// The first camera found in the world is used; without one the raster pass
+/// draws in clip space.
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub projection: Projection,
+    pub near: f32,
+    /// Distance to the far plane, may be `f32::INFINITY` with `reverse_z`.
+    pub far: f32,
+    /// Maps the near plane to depth 1 and the far plane to 0, which spreads
+    /// floating point precision far more evenly across the view.
+    pub reverse_z: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Projection {
+    /// Vertical field of view in radians.
+    Perspective { fov_y: f32 },
+    /// Height of the view volume in world units.
+    Orthographic { height: f32 },
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            projection: Projection::Perspective {
+                fov_y: std::f32::consts::FRAC_PI_4,
+            },
+            near: 0.1,
+            far: 1000.0,
+            reverse_z: false,
+        }
+    }
+}
+
+impl Camera {
+    /// Vulkan style projection with depth in `0..1`, for a target of the
+    /// given width over height.
+    pub fn projection_matrix(&self, aspect_ratio: f32) -> Mat4 {
+        let (near, far) = if self.reverse_z {
+            (self.far, self.near)
+        } else {
+            (self.near, self.far)
+        };
+
+        match self.projection {
+            Projection::Perspective { fov_y } if self.reverse_z && self.far.is_infinite() => {
+                Mat4::perspective_infinite_reverse_rh(fov_y, aspect_ratio, self.near)
+            }
+            Projection::Perspective { fov_y } => {
+                Mat4::perspective_rh(fov_y, aspect_ratio, near, far)
+            }
+            Projection::Orthographic { height } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * aspect_ratio;
+                Mat4::orthographic_rh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    near,
+                    far,
+                )
+            }
+        }
+    }
+}
+
+/// Per-view constants shared by the raster and ray generation shaders, in
+/// descriptor set layout `CameraBuffer::layout`.
+#[derive(AsStd140)]
+pub struct CameraUniform {
+    pub view: mint::ColumnMatrix4<f32>,
+    pub projection: mint::ColumnMatrix4<f32>,
+    pub view_projection: mint::ColumnMatrix4<f32>,
+    pub inverse_view: mint::ColumnMatrix4<f32>,
+    pub inverse_projection: mint::ColumnMatrix4<f32>,
+    /// World space position, `w` is 1.
+    pub position: mint::Vector4<f32>,
+}
+
+impl CameraUniform {
+    pub fn new(view: Mat4, projection: Mat4) -> Self {
+        let inverse_view = view.inverse();
+
+        CameraUniform {
+            view: view.to_cols_array_2d().into(),
+            projection: projection.to_cols_array_2d().into(),
+            view_projection: (projection * view).to_cols_array_2d().into(),
+            inverse_view: inverse_view.to_cols_array_2d().into(),
+            inverse_projection: projection.inverse().to_cols_array_2d().into(),
+            position: (inverse_view * Vec4::new(0.0, 0.0, 0.0, 1.0))
+                .to_array()
+                .into(),
+        }
+    }
+
+    pub fn from_camera(camera: &Camera, transform: &GlobalTransform, aspect_ratio: f32) -> Self {
+        CameraUniform::new(
+            transform.compute_matrix().inverse(),
+            camera.projection_matrix(aspect_ratio),
+        )
+    }
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        CameraUniform::new(Mat4::IDENTITY, Mat4::IDENTITY)
+    }
+}
+
+/// A descriptor set binding the camera uniform in the frame allocator, set 0
+/// of the raster pass and set 1 of ray generation shaders.
+pub struct CameraBuffer {
+    layout: DescriptorSetLayout,
+    set: DescriptorSet,
+    /// Where this frame's uniform was written.
+    offset: u32,
+    reverse_z: bool,
+}
+
+impl CameraBuffer {
+    pub fn new(device: &Device, frame_allocator: &FrameAllocator) -> Self {
+        let layout = device.create_descriptor_set_layout(DescriptorSetLayoutInfo {
+            bindings: vec![DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+                count: 1,
+                stages: vk::ShaderStageFlags::VERTEX
+                    | vk::ShaderStageFlags::FRAGMENT
+                    | vk::ShaderStageFlags::RAYGEN_KHR,
+                flags: vk::DescriptorBindingFlags::empty(),
+            }],
+            flags: vk::DescriptorSetLayoutCreateFlags::empty(),
+            name: Some("camera".into()),
+        });
+
+        let set = device.create_descriptor_set(DescriptorSetInfo {
+            layout: layout.clone(),
+        });
+        let size = mem::size_of::<<CameraUniform as AsStd140>::Std140Type>() as u64;
+        device.update_descriptor_sets(
+            &[WriteDescriptorSet {
+                set: &set,
+                binding: 0,
+                element: 0,
+                descriptors: Descriptors::UniformBufferDynamic(&[(
+                    frame_allocator.buffer().clone(),
+                    0,
+                    size,
+                )]),
+            }],
+            &[],
+        );
+
+        CameraBuffer {
+            layout,
+            set,
+            offset: 0,
+            reverse_z: false,
+        }
+    }
+
+    /// Writes this frame's view. `None` keeps clip space coordinates as is.
+    pub fn write(
+        &mut self,
+        frame_allocator: &mut FrameAllocator,
+        view: Option<(&Camera, &GlobalTransform)>,
+        aspect_ratio: f32,
+    ) {
+        let uniform = match view {
+            Some((camera, transform)) => {
+                self.reverse_z = camera.reverse_z;
+                CameraUniform::from_camera(camera, transform, aspect_ratio)
+            }
+            None => {
+                self.reverse_z = false;
+                CameraUniform::default()
+            }
+        };
+
+        self.offset = frame_allocator.write(&uniform.as_std140()).offset;
+    }
+
+    pub fn layout(&self) -> &DescriptorSetLayout {
+        &self.layout
+    }
+
+    pub fn set(&self) -> &DescriptorSet {
+        &self.set
+    }
+
+    pub fn dynamic_offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Whether the last written camera uses reversed depth.
+    pub fn reverse_z(&self) -> bool {
+        self.reverse_z
+    }
+}
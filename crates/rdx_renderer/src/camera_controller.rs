@@ -0,0 +1,222 @@
+use crate::camera::Camera;
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+/// Pixels of scrolling counted as one line of a mouse wheel.
+const PIXELS_PER_LINE: f32 = 16.0;
+
+/// Keeps pitch away from the poles, where yaw becomes ambiguous.
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+/// Interactive navigation for entities with a `Camera`, driven by bevy's
+/// `InputPlugin`.
+///
+/// Add `FlyController` or `OrbitController` next to a `Camera` to control it.
+/// `ViewChanged` is sent whenever any camera moves or changes projection.
+#[derive(Default)]
+pub struct CameraControllerPlugin;
+
+impl Plugin for CameraControllerPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<ViewChanged>()
+            .add_system_to_stage(CoreStage::Update, fly_controller.system())
+            .add_system_to_stage(CoreStage::Update, orbit_controller.system())
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                view_changed
+                    .system()
+                    .after(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// A camera moved, turned or changed projection, so anything accumulated
+/// over previous frames of its view is stale.
+#[derive(Clone, Copy, Debug)]
+pub struct ViewChanged {
+    pub camera: Entity,
+}
+
+/// WASD to move, Space and Left Shift to go up and down, hold the right
+/// mouse button to look around and Left Control to move faster.
+#[derive(Clone, Debug)]
+pub struct FlyController {
+    /// Units per second.
+    pub speed: f32,
+    /// Speed multiplier while Left Control is held.
+    pub boost: f32,
+    /// Radians per pixel of mouse motion.
+    pub sensitivity: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl Default for FlyController {
+    fn default() -> Self {
+        FlyController {
+            speed: 2.0,
+            boost: 4.0,
+            sensitivity: 0.003,
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+/// Hold the left mouse button to orbit around `target`, the middle button
+/// to pan it and scroll to zoom.
+#[derive(Clone, Debug)]
+pub struct OrbitController {
+    pub target: Vec3,
+    pub distance: f32,
+    /// Radians per pixel of mouse motion.
+    pub sensitivity: f32,
+    /// Fraction of `distance` covered per pixel when panning.
+    pub pan_speed: f32,
+    /// Fraction of `distance` covered per line scrolled.
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        OrbitController {
+            target: Vec3::ZERO,
+            distance: 5.0,
+            sensitivity: 0.005,
+            pan_speed: 0.002,
+            zoom_speed: 0.1,
+            min_distance: 0.05,
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+impl OrbitController {
+    fn rotation(&self) -> Quat {
+        Quat::from_rotation_y(self.yaw) * Quat::from_rotation_x(self.pitch)
+    }
+}
+
+fn fly_controller(
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    mut controllers: Query<(&mut FlyController, &mut Transform), With<Camera>>,
+) {
+    let mut look = Vec2::ZERO;
+    for event in mouse_motion_events.iter() {
+        look += event.delta;
+    }
+    if !buttons.pressed(MouseButton::Right) {
+        look = Vec2::ZERO;
+    }
+
+    let axis = |positive, negative| {
+        let mut value = 0.0;
+        if keys.pressed(positive) {
+            value += 1.0;
+        }
+        if keys.pressed(negative) {
+            value -= 1.0;
+        }
+        value
+    };
+    let movement = Vec3::new(
+        axis(KeyCode::D, KeyCode::A),
+        axis(KeyCode::Space, KeyCode::LShift),
+        axis(KeyCode::S, KeyCode::W),
+    );
+
+    for (mut controller, mut transform) in controllers.iter_mut() {
+        // Added controllers snap the camera to their orientation.
+        if look == Vec2::ZERO && movement == Vec3::ZERO && !controller.is_added() {
+            continue;
+        }
+
+        controller.yaw -= look.x * controller.sensitivity;
+        controller.pitch =
+            (controller.pitch - look.y * controller.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        transform.rotation =
+            Quat::from_rotation_y(controller.yaw) * Quat::from_rotation_x(controller.pitch);
+
+        if movement != Vec3::ZERO {
+            let mut speed = controller.speed;
+            if keys.pressed(KeyCode::LControl) {
+                speed *= controller.boost;
+            }
+            let offset = transform.rotation * movement.normalize();
+            transform.translation += offset * speed * time.delta_seconds();
+        }
+    }
+}
+
+fn orbit_controller(
+    buttons: Res<Input<MouseButton>>,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut controllers: Query<(&mut OrbitController, &mut Transform), With<Camera>>,
+) {
+    let mut motion = Vec2::ZERO;
+    for event in mouse_motion_events.iter() {
+        motion += event.delta;
+    }
+
+    let mut scroll = 0.0;
+    for event in mouse_wheel_events.iter() {
+        scroll += match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / PIXELS_PER_LINE,
+        };
+    }
+
+    let orbit = buttons.pressed(MouseButton::Left) && motion != Vec2::ZERO;
+    let pan = buttons.pressed(MouseButton::Middle) && motion != Vec2::ZERO;
+
+    for (mut controller, mut transform) in controllers.iter_mut() {
+        // Added controllers snap the camera to their orbit.
+        if !orbit && !pan && scroll == 0.0 && !controller.is_added() {
+            continue;
+        }
+
+        if orbit {
+            controller.yaw -= motion.x * controller.sensitivity;
+            controller.pitch =
+                (controller.pitch - motion.y * controller.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        if pan {
+            let pan = controller.rotation() * Vec3::new(-motion.x, motion.y, 0.0);
+            controller.target += pan * controller.pan_speed * controller.distance;
+        }
+
+        if scroll != 0.0 {
+            controller.distance = (controller.distance * (1.0 - scroll * controller.zoom_speed))
+                .max(controller.min_distance);
+        }
+
+        transform.rotation = controller.rotation();
+        transform.translation =
+            controller.target + transform.rotation * Vec3::new(0.0, 0.0, controller.distance);
+    }
+}
+
+fn view_changed(
+    cameras: Query<
+        Entity,
+        (
+            With<Camera>,
+            Or<(Changed<GlobalTransform>, Changed<Camera>)>,
+        ),
+    >,
+    mut view_changed_events: EventWriter<ViewChanged>,
+) {
+    for camera in cameras.iter() {
+        view_changed_events.send(ViewChanged { camera });
+    }
+}
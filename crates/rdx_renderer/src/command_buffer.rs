@@ -1,38 +1,111 @@
 use crate::device::Device;
 use crate::encoder::Command;
-use crate::render_pass::{ClearValue, DEFAULT_ATTACHMENT_COUNT};
+use crate::render_pass::{RenderingAttachment, DEFAULT_ATTACHMENT_COUNT};
+use crate::resources::{Framebuffer, RenderPass};
 use erupt::vk;
 use smallvec::SmallVec;
+use std::ffi::c_void;
+use std::ops::Range;
+
+/// What a secondary command buffer continues from the primary executing it.
+#[derive(Clone, Copy)]
+pub struct InheritanceInfo<'a> {
+    pub render_pass: &'a RenderPass,
+    pub subpass: u32,
+    /// Optional, but may help some drivers.
+    pub framebuffer: Option<&'a Framebuffer>,
+    /// Statistics of the pipeline statistics queries active in the primary,
+    /// which needs the `inheritedQueries` feature.
+    pub pipeline_statistics: vk::QueryPipelineStatisticFlags,
+}
+
+#[derive(Clone, Copy)]
+struct Inheritance {
+    render_pass: vk::RenderPass,
+    subpass: u32,
+    framebuffer: vk::Framebuffer,
+    pipeline_statistics: vk::QueryPipelineStatisticFlags,
+}
 
 pub struct CommandBuffer {
     handle: vk::CommandBuffer,
     queue_family_index: u32,
     recording: bool,
+    inheritance: Option<Inheritance>,
 }
 
+// SAFETY: recording needs `&mut self`, so one thread at a time records into
+// the buffer. Its pool is only used by the thread that allocated from it:
+// the queue's pool by the queue's owner, and `ThreadCommandPools` gives every
+// recording thread its own pool. Finished buffers are only read by
+// `vkCmdExecuteCommands` and `vkQueueSubmit`, which do not touch their pool.
+unsafe impl Send for CommandBuffer {}
+
 impl CommandBuffer {
     pub fn new(handle: vk::CommandBuffer, queue_family_index: u32) -> Self {
         CommandBuffer {
             handle,
             queue_family_index,
             recording: false,
+            inheritance: None,
+        }
+    }
+
+    /// A secondary command buffer recorded entirely inside a subpass.
+    pub fn secondary(
+        handle: vk::CommandBuffer,
+        queue_family_index: u32,
+        info: &InheritanceInfo<'_>,
+    ) -> Self {
+        CommandBuffer {
+            handle,
+            queue_family_index,
+            recording: false,
+            inheritance: Some(Inheritance {
+                render_pass: info.render_pass.handle(),
+                subpass: info.subpass,
+                framebuffer: info
+                    .framebuffer
+                    .map_or(vk::Framebuffer::null(), Framebuffer::handle),
+                pipeline_statistics: info.pipeline_statistics,
+            }),
         }
     }
 
+    pub fn is_secondary(&self) -> bool {
+        self.inheritance.is_some()
+    }
+
     pub fn handle(&self) -> vk::CommandBuffer {
         self.handle
     }
 
     pub fn write(&mut self, device: &Device, commands: &[Command<'_>]) {
+        let debug_utils = device.debug_utils_enabled();
         let device = device.handle();
         if !self.recording {
+            let inheritance_info = self.inheritance.map(|inheritance| {
+                vk::CommandBufferInheritanceInfoBuilder::new()
+                    .render_pass(inheritance.render_pass)
+                    .subpass(inheritance.subpass)
+                    .framebuffer(inheritance.framebuffer)
+                    .pipeline_statistics(inheritance.pipeline_statistics)
+            });
+
+            let mut begin_info = vk::CommandBufferBeginInfoBuilder::new()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            if let Some(inheritance_info) = &inheritance_info {
+                begin_info = begin_info
+                    .flags(
+                        vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT
+                            | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE,
+                    )
+                    .inheritance_info(inheritance_info);
+            }
+
             unsafe {
                 device
-                    .begin_command_buffer(
-                        self.handle,
-                        &vk::CommandBufferBeginInfoBuilder::new()
-                            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
-                    )
+                    .begin_command_buffer(self.handle, &begin_info)
                     .unwrap()
             }
             self.recording = true;
@@ -44,26 +117,15 @@ impl CommandBuffer {
                     render_pass,
                     framebuffer,
                     clears,
+                    contents,
                 } => unsafe {
                     let mut clears = clears.into_iter();
                     let clear_values = render_pass
                         .info()
                         .attachments
                         .iter()
-                        .map(|attachment| {
-                            let clear = clears.next().unwrap();
-                            match *clear {
-                                ClearValue::Color(r, g, b, a) => vk::ClearValue {
-                                    color: vk::ClearColorValue {
-                                        float32: [r, g, b, a],
-                                    },
-                                },
-                                ClearValue::DepthStencil(depth, stencil) => vk::ClearValue {
-                                    depth_stencil: vk::ClearDepthStencilValue { depth, stencil },
-                                },
-                            }
-                        })
-                        .collect::<SmallVec<[_; DEFAULT_ATTACHMENT_COUNT]>>();
+                        .map(|_| clears.next().unwrap().into())
+                        .collect::<SmallVec<[vk::ClearValue; DEFAULT_ATTACHMENT_COUNT]>>();
 
                     device.cmd_begin_render_pass(
                         self.handle,
@@ -75,10 +137,53 @@ impl CommandBuffer {
                                 extent: framebuffer.info().extent,
                             })
                             .clear_values(&clear_values),
-                        vk::SubpassContents::INLINE,
+                        contents,
                     )
                 },
+                Command::NextSubpass { contents } => unsafe {
+                    device.cmd_next_subpass(self.handle, contents)
+                },
                 Command::EndRenderPass => unsafe { device.cmd_end_render_pass(self.handle) },
+                Command::BeginRendering { ref info } => unsafe {
+                    let attachment =
+                        |attachment: &RenderingAttachment<'_>,
+                         resolve_mode: vk::ResolveModeFlagBits| {
+                            let builder = vk::RenderingAttachmentInfoKHRBuilder::new()
+                                .image_view(attachment.view.handle())
+                                .image_layout(attachment.layout)
+                                .load_op(attachment.load_op)
+                                .store_op(attachment.store_op)
+                                .clear_value((&attachment.clear).into());
+
+                            match attachment.resolve {
+                                Some((view, layout)) => builder
+                                    .resolve_mode(resolve_mode)
+                                    .resolve_image_view(view.handle())
+                                    .resolve_image_layout(layout),
+                                None => builder,
+                            }
+                        };
+
+                    let colors = info
+                        .colors
+                        .iter()
+                        .map(|color| attachment(color, vk::ResolveModeFlagBits::AVERAGE))
+                        .collect::<SmallVec<[_; DEFAULT_ATTACHMENT_COUNT]>>();
+                    let depth = info
+                        .depth
+                        .map(|depth| attachment(depth, vk::ResolveModeFlagBits::SAMPLE_ZERO));
+
+                    let mut rendering_info = vk::RenderingInfoKHRBuilder::new()
+                        .render_area(info.render_area)
+                        .layer_count(1)
+                        .color_attachments(&colors);
+                    if let Some(depth) = &depth {
+                        rendering_info = rendering_info.depth_attachment(depth);
+                    }
+
+                    device.cmd_begin_rendering_khr(self.handle, &rendering_info)
+                },
+                Command::EndRendering => unsafe { device.cmd_end_rendering_khr(self.handle) },
                 Command::BindGraphicsPipeline { pipeline } => unsafe {
                     device.cmd_bind_pipeline(
                         self.handle,
@@ -87,7 +192,25 @@ impl CommandBuffer {
                     )
                 },
                 Command::BindRayTracingPipeline { .. } => unimplemented!(),
-                Command::BindGraphicsDescriptorSets { .. } => unimplemented!(),
+                Command::BindGraphicsDescriptorSets {
+                    layout,
+                    first_set,
+                    descriptor_sets,
+                    dynamic_offsets,
+                } => unsafe {
+                    let descriptor_sets = descriptor_sets
+                        .iter()
+                        .map(|set| set.handle())
+                        .collect::<SmallVec<[_; 8]>>();
+                    device.cmd_bind_descriptor_sets(
+                        self.handle,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        layout.handle(),
+                        first_set,
+                        &descriptor_sets,
+                        dynamic_offsets,
+                    )
+                },
                 Command::BindRayTracingDescriptorSets { .. } => unimplemented!(),
                 Command::Draw {
                     ref vertices,
@@ -104,15 +227,188 @@ impl CommandBuffer {
                 Command::SetViewport { viewport } => unsafe {
                     device.cmd_set_viewport(self.handle, 0, &[viewport.into_builder()])
                 },
+                Command::PushConstants {
+                    layout,
+                    stages,
+                    offset,
+                    data,
+                } => unsafe {
+                    device.cmd_push_constants(
+                        self.handle,
+                        layout.handle(),
+                        stages,
+                        offset,
+                        data.len() as u32,
+                        data.as_ptr() as *const c_void,
+                    )
+                },
                 Command::SetScissor { scissor } => unsafe {
                     device.cmd_set_scissor(self.handle, 0, &[scissor.into_builder()])
                 },
-                Command::DrawIndexed { .. } => unimplemented!(),
+                Command::DrawIndexed {
+                    ref indices,
+                    vertex_offset,
+                    ref instances,
+                } => unsafe {
+                    device.cmd_draw_indexed(
+                        self.handle,
+                        indices.end - indices.start,
+                        instances.end - instances.start,
+                        indices.start,
+                        vertex_offset,
+                        instances.start,
+                    )
+                },
                 Command::UpdateBuffer { .. } => unimplemented!(),
-                Command::BindVertexBuffers { .. } => unimplemented!(),
-                Command::BindIndexBuffer { .. } => unimplemented!(),
+                Command::BindVertexBuffers { first, buffers } => unsafe {
+                    let (handles, offsets): (SmallVec<[_; 8]>, SmallVec<[_; 8]>) = buffers
+                        .iter()
+                        .map(|(buffer, offset)| (buffer.handle(), *offset))
+                        .unzip();
+                    device.cmd_bind_vertex_buffers(self.handle, first, &handles, &offsets)
+                },
+                Command::BindIndexBuffer {
+                    buffer,
+                    offset,
+                    index_type,
+                } => unsafe {
+                    device.cmd_bind_index_buffer(self.handle, buffer.handle(), offset, index_type)
+                },
                 Command::BuildAccelerationStructure { .. } => unimplemented!(),
                 Command::TraceRays { .. } => unimplemented!(),
+                Command::PushLabel { ref name } if debug_utils => unsafe {
+                    device.cmd_begin_debug_utils_label_ext(
+                        self.handle,
+                        &vk::DebugUtilsLabelEXTBuilder::new().label_name(name),
+                    )
+                },
+                Command::PopLabel if debug_utils => unsafe {
+                    device.cmd_end_debug_utils_label_ext(self.handle)
+                },
+                Command::InsertLabel { ref name } if debug_utils => unsafe {
+                    device.cmd_insert_debug_utils_label_ext(
+                        self.handle,
+                        &vk::DebugUtilsLabelEXTBuilder::new().label_name(name),
+                    )
+                },
+                Command::PushLabel { .. } | Command::PopLabel | Command::InsertLabel { .. } => {}
+                Command::WriteTimestamp {
+                    stage,
+                    ref pool,
+                    query,
+                } => unsafe {
+                    device.cmd_write_timestamp(self.handle, stage, pool.handle(), query)
+                },
+                Command::BeginQuery { ref pool, query } => unsafe {
+                    device.cmd_begin_query(
+                        self.handle,
+                        pool.handle(),
+                        query,
+                        vk::QueryControlFlags::empty(),
+                    )
+                },
+                Command::EndQuery { ref pool, query } => unsafe {
+                    device.cmd_end_query(self.handle, pool.handle(), query)
+                },
+                Command::ExecuteCommands { command_buffers } => unsafe {
+                    let command_buffers = command_buffers
+                        .iter()
+                        .map(|command_buffer| command_buffer.handle)
+                        .collect::<SmallVec<[_; 16]>>();
+                    device.cmd_execute_commands(self.handle, &command_buffers)
+                },
+                Command::CopyBuffer { src, dst, regions } => unsafe {
+                    let regions = regions
+                        .iter()
+                        .map(|region| region.into_builder())
+                        .collect::<SmallVec<[_; 16]>>();
+                    device.cmd_copy_buffer(self.handle, src.handle(), dst.handle(), &regions)
+                },
+                Command::CopyBufferToImage {
+                    src,
+                    dst,
+                    layout,
+                    regions,
+                } => unsafe {
+                    let regions = regions
+                        .iter()
+                        .map(|region| region.into_builder())
+                        .collect::<SmallVec<[_; 16]>>();
+                    device.cmd_copy_buffer_to_image(
+                        self.handle,
+                        src.handle(),
+                        dst.handle(),
+                        layout,
+                        &regions,
+                    )
+                },
+                Command::PipelineBarrier {
+                    src,
+                    dst,
+                    images,
+                    buffers,
+                } => unsafe {
+                    let family = self.queue_family_index;
+                    let family_indices = |transfer: &Option<Range<u32>>| match transfer {
+                        Some(transfer) => (transfer.start, transfer.end),
+                        None => (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED),
+                    };
+                    // A release only makes writes available and an acquire
+                    // only makes them visible, so the other side's mask is
+                    // empty.
+                    let access_masks = |transfer: &Option<Range<u32>>| match transfer {
+                        Some(transfer) if transfer.start == family => {
+                            (supported_access(src), vk::AccessFlags::empty())
+                        }
+                        Some(_) => (vk::AccessFlags::empty(), supported_access(dst)),
+                        None => (supported_access(src), supported_access(dst)),
+                    };
+
+                    let image_barriers = images
+                        .iter()
+                        .map(|barrier| {
+                            let (src_family, dst_family) = family_indices(&barrier.family_transfer);
+                            let (src_access, dst_access) = access_masks(&barrier.family_transfer);
+                            vk::ImageMemoryBarrierBuilder::new()
+                                .image(barrier.image.handle())
+                                .old_layout(
+                                    barrier.old_layout.unwrap_or(vk::ImageLayout::UNDEFINED),
+                                )
+                                .new_layout(barrier.new_layout)
+                                .src_queue_family_index(src_family)
+                                .dst_queue_family_index(dst_family)
+                                .src_access_mask(src_access)
+                                .dst_access_mask(dst_access)
+                                .subresource_range((&barrier.subresource).into())
+                        })
+                        .collect::<SmallVec<[_; 8]>>();
+
+                    let buffer_barriers = buffers
+                        .iter()
+                        .map(|barrier| {
+                            let (src_family, dst_family) = family_indices(&barrier.family_transfer);
+                            let (src_access, dst_access) = access_masks(&barrier.family_transfer);
+                            vk::BufferMemoryBarrierBuilder::new()
+                                .buffer(barrier.buffer.handle())
+                                .offset(barrier.offset)
+                                .size(barrier.size)
+                                .src_queue_family_index(src_family)
+                                .dst_queue_family_index(dst_family)
+                                .src_access_mask(src_access)
+                                .dst_access_mask(dst_access)
+                        })
+                        .collect::<SmallVec<[_; 8]>>();
+
+                    device.cmd_pipeline_barrier(
+                        self.handle,
+                        src,
+                        dst,
+                        None,
+                        &[],
+                        &buffer_barriers,
+                        &image_barriers,
+                    )
+                },
             }
         }
 
@@ -121,3 +417,77 @@ impl CommandBuffer {
         }
     }
 }
+
+/// Every access type that can happen in `stages`.
+fn supported_access(stages: vk::PipelineStageFlags) -> vk::AccessFlags {
+    let mut access = vk::AccessFlags::empty();
+
+    if stages.contains(vk::PipelineStageFlags::ALL_COMMANDS) {
+        return vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE;
+    }
+
+    if stages.contains(vk::PipelineStageFlags::ALL_GRAPHICS) {
+        access |= vk::AccessFlags::INDIRECT_COMMAND_READ
+            | vk::AccessFlags::INDEX_READ
+            | vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+            | vk::AccessFlags::UNIFORM_READ
+            | vk::AccessFlags::SHADER_READ
+            | vk::AccessFlags::SHADER_WRITE
+            | vk::AccessFlags::INPUT_ATTACHMENT_READ
+            | vk::AccessFlags::COLOR_ATTACHMENT_READ
+            | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+    }
+
+    if stages.contains(vk::PipelineStageFlags::DRAW_INDIRECT) {
+        access |= vk::AccessFlags::INDIRECT_COMMAND_READ;
+    }
+
+    if stages.contains(vk::PipelineStageFlags::VERTEX_INPUT) {
+        access |= vk::AccessFlags::INDEX_READ | vk::AccessFlags::VERTEX_ATTRIBUTE_READ;
+    }
+
+    let shader_stages = vk::PipelineStageFlags::VERTEX_SHADER
+        | vk::PipelineStageFlags::TESSELLATION_CONTROL_SHADER
+        | vk::PipelineStageFlags::TESSELLATION_EVALUATION_SHADER
+        | vk::PipelineStageFlags::GEOMETRY_SHADER
+        | vk::PipelineStageFlags::FRAGMENT_SHADER
+        | vk::PipelineStageFlags::COMPUTE_SHADER
+        | vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR;
+    if stages.intersects(shader_stages) {
+        access |= vk::AccessFlags::UNIFORM_READ
+            | vk::AccessFlags::SHADER_READ
+            | vk::AccessFlags::SHADER_WRITE;
+    }
+
+    if stages.contains(vk::PipelineStageFlags::FRAGMENT_SHADER) {
+        access |= vk::AccessFlags::INPUT_ATTACHMENT_READ;
+    }
+
+    if stages.intersects(
+        vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+    ) {
+        access |= vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+    }
+
+    if stages.contains(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT) {
+        access |= vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE;
+    }
+
+    if stages.contains(vk::PipelineStageFlags::TRANSFER) {
+        access |= vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::TRANSFER_WRITE;
+    }
+
+    if stages.contains(vk::PipelineStageFlags::HOST) {
+        access |= vk::AccessFlags::HOST_READ | vk::AccessFlags::HOST_WRITE;
+    }
+
+    if stages.contains(vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_KHR) {
+        access |= vk::AccessFlags::ACCELERATION_STRUCTURE_READ_KHR
+            | vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR;
+    }
+
+    access
+}
@@ -0,0 +1,178 @@
+use crate::command_buffer::{CommandBuffer, InheritanceInfo};
+use crate::device::Device;
+use crate::encoder::Encoder;
+use crate::timeline::GpuTimeline;
+use bevy::tasks::ComputeTaskPool;
+use erupt::vk;
+use parking_lot::Mutex;
+use std::collections::{HashMap, VecDeque};
+use std::thread::{self, ThreadId};
+
+/// A command pool and the secondary command buffers allocated from it, reused
+/// once the pool is reset.
+struct ThreadPool {
+    pool: vk::CommandPool,
+    buffers: Vec<vk::CommandBuffer>,
+    used: usize,
+}
+
+#[derive(Default)]
+struct ThreadPools {
+    /// Pools recorded into since the last submission, by thread.
+    recording: HashMap<ThreadId, ThreadPool>,
+    /// Pools waiting for the timeline value of the submission executing
+    /// their buffers, oldest first.
+    pending: VecDeque<(u64, Vec<ThreadPool>)>,
+    /// Reset pools, handed to whichever thread records next.
+    free: Vec<ThreadPool>,
+}
+
+/// One command pool per recording thread, for secondary command buffers.
+///
+/// Vulkan requires a pool and the buffers allocated from it to be used by
+/// one thread at a time, so every thread gets its own pool the first time it
+/// records in a frame. Secondary buffers must be executed by the next
+/// submission to the queue, their pools are reset and reused once it
+/// completes.
+pub struct ThreadCommandPools {
+    device: Device,
+    family_index: u32,
+    pools: Mutex<ThreadPools>,
+}
+
+impl ThreadCommandPools {
+    pub fn new(device: Device, family_index: u32) -> Self {
+        ThreadCommandPools {
+            device,
+            family_index,
+            pools: Mutex::new(ThreadPools::default()),
+        }
+    }
+
+    /// Creates an encoder for a secondary command buffer continuing `info`,
+    /// to be executed by a primary encoder with `execute_commands`.
+    pub fn create_secondary_encoder(&self, info: &InheritanceInfo<'_>) -> Encoder<'static> {
+        let command_buffer = self.allocate();
+
+        Encoder::new(CommandBuffer::secondary(
+            command_buffer,
+            self.family_index,
+            info,
+        ))
+    }
+
+    /// Records one secondary command buffer per item of `chunks` on the
+    /// compute task pool. The returned buffers keep the order of `chunks`.
+    pub fn record_parallel<'a, T, F>(
+        &self,
+        task_pool: &ComputeTaskPool,
+        info: &InheritanceInfo<'_>,
+        chunks: &'a [T],
+        record: F,
+    ) -> Vec<CommandBuffer>
+    where
+        T: Sync,
+        F: Fn(&mut Encoder<'a>, &'a T) + Sync,
+    {
+        let record = &record;
+
+        task_pool.scope(|scope| {
+            for chunk in chunks {
+                scope.spawn(async move {
+                    let mut encoder: Encoder<'a> = self.create_secondary_encoder(info);
+                    record(&mut encoder, chunk);
+                    encoder.finish(&self.device)
+                });
+            }
+        })
+    }
+
+    /// Hands the pools recorded into so far to the submission reaching
+    /// `value`.
+    pub fn submitted(&self, value: u64) {
+        let mut pools = self.pools.lock();
+        if !pools.recording.is_empty() {
+            let recorded = pools.recording.drain().map(|(_, pool)| pool).collect();
+            pools.pending.push_back((value, recorded));
+        }
+    }
+
+    /// Resets the pools whose submission `timeline` has reached.
+    pub fn reset_completed(&self, timeline: &GpuTimeline) {
+        let mut pools = self.pools.lock();
+
+        while let Some((value, _)) = pools.pending.front() {
+            if !timeline.is_complete(*value) {
+                break;
+            }
+
+            let (_, completed) = pools.pending.pop_front().unwrap();
+            for mut pool in completed {
+                unsafe {
+                    self.device
+                        .handle()
+                        .reset_command_pool(pool.pool, vk::CommandPoolResetFlags::empty())
+                        .unwrap()
+                }
+                pool.used = 0;
+                pools.free.push(pool);
+            }
+        }
+    }
+
+    pub fn cleanup(&mut self, device: &Device) {
+        let pools = self.pools.get_mut();
+        let recording = pools.recording.drain().map(|(_, pool)| pool);
+        let pending = pools.pending.drain(..).flat_map(|(_, pools)| pools);
+
+        for pool in recording.chain(pending).chain(pools.free.drain(..)) {
+            // Destroying the pool frees its command buffers.
+            unsafe { device.handle().destroy_command_pool(Some(pool.pool), None) }
+        }
+    }
+
+    /// Takes the next unused command buffer of this thread's pool.
+    fn allocate(&self) -> vk::CommandBuffer {
+        let mut pools = self.pools.lock();
+        let ThreadPools {
+            recording, free, ..
+        } = &mut *pools;
+
+        let pool = recording.entry(thread::current().id()).or_insert_with(|| {
+            free.pop().unwrap_or_else(|| ThreadPool {
+                pool: unsafe {
+                    self.device
+                        .handle()
+                        .create_command_pool(
+                            &vk::CommandPoolCreateInfoBuilder::new()
+                                .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+                                .queue_family_index(self.family_index),
+                            None,
+                        )
+                        .unwrap()
+                },
+                buffers: Vec::new(),
+                used: 0,
+            })
+        });
+
+        if pool.used == pool.buffers.len() {
+            let command_buffer = unsafe {
+                self.device
+                    .handle()
+                    .allocate_command_buffers(
+                        &vk::CommandBufferAllocateInfoBuilder::new()
+                            .command_pool(pool.pool)
+                            .level(vk::CommandBufferLevel::SECONDARY)
+                            .command_buffer_count(1),
+                    )
+                    .unwrap()
+                    .remove(0)
+            };
+            pool.buffers.push(command_buffer);
+        }
+
+        pool.used += 1;
+        pool.buffers[pool.used - 1]
+    }
+}
@@ -1,28 +1,153 @@
 use erupt::{cstr, vk, InstanceLoader};
-use std::ffi::{c_void, CStr};
+use parking_lot::Mutex;
+use std::ffi::{c_void, CStr, CString};
 use std::os::raw::c_char;
+use std::sync::Arc;
 
 pub const VALIDATION_LAYER: *const c_char = cstr!("VK_LAYER_KHRONOS_validation");
 
+/// Converts an object name or label for debug utils, replacing interior NUL
+/// characters, which C strings can't hold, with U+FFFD.
+pub fn debug_name(name: &str) -> CString {
+    CString::new(name.replace('\0', "\u{fffd}")).unwrap()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MessageSeverity {
+    Verbose,
+    Info,
+    Warning,
+    Error,
+}
+
+impl MessageSeverity {
+    /// This severity and everything more severe.
+    fn flags(self) -> vk::DebugUtilsMessageSeverityFlagsEXT {
+        let mut flags = vk::DebugUtilsMessageSeverityFlagsEXT::ERROR_EXT;
+        if self <= MessageSeverity::Warning {
+            flags |= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING_EXT;
+        }
+        if self <= MessageSeverity::Info {
+            flags |= vk::DebugUtilsMessageSeverityFlagsEXT::INFO_EXT;
+        }
+        if self <= MessageSeverity::Verbose {
+            flags |= vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE_EXT;
+        }
+        flags
+    }
+}
+
+/// Vulkan validation configuration, read once when the renderer is created.
+///
+/// Defaults to plain validation reporting warnings and errors in debug builds
+/// and nothing in release.
+#[derive(Clone)]
+pub struct ValidationSettings {
+    pub validation: bool,
+    pub gpu_assisted: bool,
+    pub synchronization: bool,
+    pub debug_printf: bool,
+    /// Names objects and labels command buffers even without validation.
+    pub debug_utils: bool,
+    pub severity: MessageSeverity,
+    /// Keep validation errors in [`ValidationErrors`] instead of only logging them.
+    pub capture_errors: bool,
+}
+
+impl Default for ValidationSettings {
+    fn default() -> Self {
+        ValidationSettings {
+            validation: cfg!(debug_assertions),
+            gpu_assisted: false,
+            synchronization: false,
+            debug_printf: false,
+            debug_utils: cfg!(debug_assertions),
+            severity: MessageSeverity::Warning,
+            capture_errors: false,
+        }
+    }
+}
+
+impl ValidationSettings {
+    pub fn debug_utils_enabled(&self) -> bool {
+        self.validation || self.debug_utils
+    }
+
+    pub fn validation_features(&self) -> Vec<vk::ValidationFeatureEnableEXT> {
+        let mut features = Vec::new();
+
+        if !self.validation {
+            return features;
+        }
+
+        if self.debug_printf {
+            if self.gpu_assisted {
+                tracing::warn!("debugPrintfEXT and GPU-assisted validation are exclusive, using debugPrintfEXT");
+            }
+            features.push(vk::ValidationFeatureEnableEXT::DEBUG_PRINTF_EXT);
+        } else if self.gpu_assisted {
+            features.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED_EXT);
+            features.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT_EXT);
+        }
+
+        if self.synchronization {
+            features.push(vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION_EXT);
+        }
+
+        features
+    }
+}
+
+/// Validation errors collected while `ValidationSettings::capture_errors` is set.
+#[derive(Clone, Default)]
+pub struct ValidationErrors {
+    messages: Arc<Mutex<Vec<String>>>,
+}
+
+impl ValidationErrors {
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.messages.lock())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.lock().is_empty()
+    }
+}
+
+struct MessengerState {
+    capture_errors: bool,
+    errors: ValidationErrors,
+}
+
 pub struct DebugMessenger {
     handle: vk::DebugUtilsMessengerEXT,
+    state: Box<MessengerState>,
 }
 
 impl DebugMessenger {
-    pub fn new(instance: &InstanceLoader) -> Self {
-        let handle = if cfg!(debug_assertions) {
+    pub fn new(instance: &InstanceLoader, settings: &ValidationSettings) -> Self {
+        let state = Box::new(MessengerState {
+            capture_errors: settings.capture_errors,
+            errors: ValidationErrors::default(),
+        });
+
+        let handle = if settings.validation {
+            // debugPrintfEXT output is reported as info.
+            let severity = if settings.debug_printf {
+                settings.severity.min(MessageSeverity::Info)
+            } else {
+                settings.severity
+            };
+
             let messenger_info = vk::DebugUtilsMessengerCreateInfoEXTBuilder::new()
-                .message_severity(
-                    vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE_EXT
-                        | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING_EXT
-                        | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR_EXT,
-                )
+                .message_severity(severity.flags())
                 .message_type(
                     vk::DebugUtilsMessageTypeFlagsEXT::GENERAL_EXT
                         | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION_EXT
                         | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE_EXT,
                 )
-                .pfn_user_callback(Some(debug_callback));
+                .pfn_user_callback(Some(debug_callback))
+                .user_data(&*state as *const MessengerState as *mut c_void);
 
             unsafe {
                 instance
@@ -33,7 +158,11 @@ impl DebugMessenger {
             vk::DebugUtilsMessengerEXT::null()
         };
 
-        DebugMessenger { handle }
+        DebugMessenger { handle, state }
+    }
+
+    pub fn errors(&self) -> &ValidationErrors {
+        &self.state.errors
     }
 
     pub fn destroy(&mut self, instance: &InstanceLoader) {
@@ -49,7 +178,7 @@ unsafe extern "system" fn debug_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagBitsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _p_user_data: *mut c_void,
+    p_user_data: *mut c_void,
 ) -> vk::Bool32 {
     let types = match message_type {
         vk::DebugUtilsMessageTypeFlagsEXT::GENERAL_EXT => "[General]",
@@ -75,5 +204,18 @@ unsafe extern "system" fn debug_callback(
         _ => tracing::warn!("{} {:?}", types, message),
     };
 
+    if message_severity == vk::DebugUtilsMessageSeverityFlagBitsEXT::ERROR_EXT
+        && !p_user_data.is_null()
+    {
+        let state = &*(p_user_data as *const MessengerState);
+        if state.capture_errors {
+            state
+                .errors
+                .messages
+                .lock()
+                .push(message.to_string_lossy().into_owned());
+        }
+    }
+
     vk::FALSE
 }
@@ -32,6 +32,23 @@ pub enum Descriptors<'a> {
     AccelerationStructure(&'a [AccelerationStructure]),
 }
 
+impl Descriptors<'_> {
+    pub fn descriptor_type(&self) -> vk::DescriptorType {
+        match self {
+            Descriptors::Sampler(_) => vk::DescriptorType::SAMPLER,
+            Descriptors::CombinedImageSampler(_) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            Descriptors::SampledImage(_) => vk::DescriptorType::SAMPLED_IMAGE,
+            Descriptors::StorageImage(_) => vk::DescriptorType::STORAGE_IMAGE,
+            Descriptors::UniformBuffer(_) => vk::DescriptorType::UNIFORM_BUFFER,
+            Descriptors::StorageBuffer(_) => vk::DescriptorType::STORAGE_BUFFER,
+            Descriptors::UniformBufferDynamic(_) => vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+            Descriptors::StorageBufferDynamic(_) => vk::DescriptorType::STORAGE_BUFFER_DYNAMIC,
+            Descriptors::InputAttachment(_) => vk::DescriptorType::INPUT_ATTACHMENT,
+            Descriptors::AccelerationStructure(_) => vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+        }
+    }
+}
+
 pub struct CopyDescriptorSet<'a> {
     pub src: &'a DescriptorSet,
     pub src_binding: u32,
@@ -46,6 +63,7 @@ pub struct CopyDescriptorSet<'a> {
 pub struct DescriptorSetLayoutInfo {
     pub bindings: Vec<DescriptorSetLayoutBinding>,
     pub flags: vk::DescriptorSetLayoutCreateFlags,
+    pub name: Option<Box<str>>,
 }
 
 #[derive(Clone)]
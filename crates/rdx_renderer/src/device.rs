@@ -1,32 +1,91 @@
-use crate::buffer::BufferInfo;
-use crate::descriptor::{DescriptorSetInfo, DescriptorSetLayoutInfo, DescriptorSizes};
+use crate::buffer::{BufferInfo, DeviceAddress};
+use crate::debug::debug_name;
+use crate::descriptor::{
+    CopyDescriptorSet, DescriptorSetInfo, DescriptorSetLayoutInfo, DescriptorSizes, Descriptors,
+    WriteDescriptorSet,
+};
+use crate::format;
 use crate::framebuffer::FramebufferInfo;
 use crate::image::{Image, ImageInfo, ImageView, ImageViewInfo};
-use crate::pipeline::{GraphicsPipelineInfo, PipelineLayoutInfo};
+use crate::memory::{HeapUsage, MemoryCategory, MemoryReport, MemorySettings, MemoryTracker};
+use crate::pipeline::{GraphicsPipelineInfo, PipelineLayoutInfo, PipelineTarget};
+use crate::query::QueryPoolInfo;
 use crate::render_pass::RenderPassInfo;
 use crate::resources::{
     Buffer, DescriptorSet, DescriptorSetLayout, Fence, Framebuffer, GraphicsPipeline,
-    MappableBuffer, PipelineLayout, RenderPass, Semaphore, ShaderModule,
+    MappableBuffer, PipelineLayout, QueryPool, RenderPass, Sampler, Semaphore, ShaderModule,
+};
+use crate::sampler::SamplerInfo;
+use crate::shader::{
+    compile_to_spirv, shader_source_dir, ShaderCompileError, ShaderLanguage, ShaderModuleInfo,
 };
-use crate::shader::{ShaderLanguage, ShaderModuleInfo};
 use crate::surface::Surface;
 use crate::swapchain::Swapchain;
-use crevice::internal::bytemuck::Pod;
+use crate::timeline::TimelinePoint;
+use crate::util::{align_down, align_up};
+use erupt::utils::VulkanResult;
 use erupt::vk1_0::ImageLayout;
-use erupt::{vk, DeviceLoader, InstanceLoader};
-use gpu_alloc::{GpuAllocator, UsageFlags};
+use erupt::{vk, DeviceLoader, ExtendableFromConst, ExtendableFromMut, InstanceLoader};
+use gpu_alloc::{GpuAllocator, MemoryBlock, UsageFlags};
 use gpu_alloc_erupt::EruptMemoryDevice;
 use parking_lot::Mutex;
 use slab::Slab;
 use smallvec::SmallVec;
-use std::ffi::{CStr, CString};
+use std::collections::HashMap;
+use std::ffi::{c_void, CStr, CString};
+use std::num::NonZeroU64;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
 
+/// Whether the device was lost, shared by every clone of a `Device`.
+#[derive(Default)]
+struct LostState {
+    lost: AtomicBool,
+}
+
+impl LostState {
+    fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Acquire)
+    }
+
+    fn mark_lost(&self) {
+        if !self.lost.swap(true, Ordering::AcqRel) {
+            tracing::error!("Vulkan device lost");
+        }
+    }
+
+    fn check<T>(&self, result: VulkanResult<T>) -> Option<T> {
+        if result.raw == vk::Result::ERROR_DEVICE_LOST {
+            self.mark_lost();
+            return None;
+        }
+
+        Some(result.unwrap())
+    }
+}
+
 pub struct DeviceInner {
     handle: DeviceLoader,
     instance: Arc<InstanceLoader>,
     physical_device: vk::PhysicalDevice,
+    debug_utils: bool,
+    memory_budget: bool,
+    dynamic_rendering: bool,
+    /// Whether `imageCubeArray` is enabled.
+    image_cube_array: bool,
+    memory_settings: MemorySettings,
+    /// Heap sizes and flags, queried once; only the budget changes.
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    /// `nonCoherentAtomSize - 1`, for flushing mapped ranges.
+    non_coherent_atom_mask: u64,
+    /// Heaps whose usage was above `MemorySettings::budget_warning` when last
+    /// reported, one bit per heap.
+    over_budget: AtomicU32,
     allocator: Mutex<GpuAllocator<vk::DeviceMemory>>,
+    memory_tracker: Mutex<MemoryTracker>,
+    format_properties: Mutex<HashMap<vk::Format, vk::FormatProperties>>,
+    lost: LostState,
     buffers: Mutex<Slab<vk::Buffer>>,
     swapchains: Mutex<Slab<vk::SwapchainKHR>>,
     semaphores: Mutex<Slab<vk::Semaphore>>,
@@ -42,6 +101,7 @@ pub struct DeviceInner {
     render_passes: Mutex<Slab<vk::RenderPass>>,
     shader_modules: Mutex<Slab<vk::ShaderModule>>,
     acceleration_structures: Mutex<Slab<vk::AccelerationStructureKHR>>,
+    query_pools: Mutex<Slab<vk::QueryPool>>,
 }
 
 #[derive(Clone)]
@@ -54,17 +114,42 @@ impl Device {
         instance: Arc<InstanceLoader>,
         device: DeviceLoader,
         physical_device: vk::PhysicalDevice,
+        debug_utils: bool,
+        memory_budget: bool,
+        dynamic_rendering: bool,
+        image_cube_array: bool,
+        memory_settings: &MemorySettings,
     ) -> Self {
         let allocator = Mutex::new(GpuAllocator::new(
-            gpu_alloc::Config::i_am_prototyping(),
+            memory_settings.allocator_config(),
             unsafe { gpu_alloc_erupt::device_properties(&instance, physical_device).unwrap() },
         ));
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let non_coherent_atom_mask = unsafe {
+            instance
+                .get_physical_device_properties(physical_device)
+                .limits
+                .non_coherent_atom_size
+                - 1
+        };
         Device {
             inner: Arc::new(DeviceInner {
                 handle: device,
                 instance,
                 physical_device,
+                debug_utils,
+                memory_budget,
+                dynamic_rendering,
+                image_cube_array,
+                memory_settings: memory_settings.clone(),
+                memory_properties,
+                non_coherent_atom_mask,
+                over_budget: AtomicU32::new(0),
                 allocator,
+                memory_tracker: Mutex::new(MemoryTracker::default()),
+                format_properties: Mutex::new(HashMap::new()),
+                lost: LostState::default(),
                 buffers: Mutex::new(Slab::with_capacity(1024)),
                 swapchains: Mutex::new(Slab::with_capacity(1024)),
                 semaphores: Mutex::new(Slab::with_capacity(1024)),
@@ -80,6 +165,7 @@ impl Device {
                 render_passes: Mutex::new(Slab::with_capacity(1024)),
                 shader_modules: Mutex::new(Slab::with_capacity(1024)),
                 acceleration_structures: Mutex::new(Slab::with_capacity(1024)),
+                query_pools: Mutex::new(Slab::with_capacity(1024)),
             }),
         }
     }
@@ -122,6 +208,12 @@ impl Device {
                     device.destroy_shader_module(Some(shader_module), None)
                 });
 
+            self.inner
+                .samplers
+                .lock()
+                .iter()
+                .for_each(|(_, &sampler)| device.destroy_sampler(Some(sampler), None));
+
             self.inner
                 .image_views
                 .lock()
@@ -134,6 +226,26 @@ impl Device {
                 .iter()
                 .for_each(|(_, &image)| device.destroy_image(Some(image), None));
 
+            self.inner
+                .descriptor_pools
+                .lock()
+                .iter()
+                .for_each(|(_, &pool)| device.destroy_descriptor_pool(Some(pool), None));
+
+            self.inner
+                .descriptor_set_layouts
+                .lock()
+                .iter()
+                .for_each(|(_, &layout)| device.destroy_descriptor_set_layout(Some(layout), None));
+
+            // Buffers still here were never destroyed by their owner; their
+            // memory is reported by `report_leaks`.
+            self.inner
+                .buffers
+                .lock()
+                .iter()
+                .for_each(|(_, &buffer)| device.destroy_buffer(Some(buffer), None));
+
             self.inner
                 .swapchains
                 .lock()
@@ -152,6 +264,20 @@ impl Device {
                 .iter()
                 .for_each(|(_, &fence)| device.destroy_fence(Some(fence), None));
 
+            self.inner
+                .query_pools
+                .lock()
+                .iter()
+                .for_each(|(_, &pool)| device.destroy_query_pool(Some(pool), None));
+
+            // Owners destroy their resources before this, so anything the
+            // slabs still held and the tracker still lists was leaked.
+            self.inner.memory_tracker.lock().report_leaks();
+
+            self.allocator()
+                .lock()
+                .cleanup(EruptMemoryDevice::wrap(self.handle()));
+
             self.handle().destroy_device(None)
         }
     }
@@ -168,10 +294,219 @@ impl Device {
         &self.inner.swapchains
     }
 
+    /// Whether `VK_KHR_dynamic_rendering` is enabled, allowing
+    /// `Encoder::begin_rendering` and `PipelineTarget::Dynamic`.
+    pub fn dynamic_rendering(&self) -> bool {
+        self.inner.dynamic_rendering
+    }
+
     fn allocator(&self) -> &Mutex<GpuAllocator<vk::DeviceMemory>> {
         &self.inner.allocator
     }
 
+    /// Features the physical device supports for `format`, queried once and
+    /// cached.
+    pub fn format_properties(&self, format: vk::Format) -> vk::FormatProperties {
+        *self
+            .inner
+            .format_properties
+            .lock()
+            .entry(format)
+            .or_insert_with(|| unsafe {
+                self.instance()
+                    .get_physical_device_format_properties(self.inner.physical_device, format)
+            })
+    }
+
+    pub fn supports_format_features(
+        &self,
+        format: vk::Format,
+        tiling: vk::ImageTiling,
+        features: vk::FormatFeatureFlags,
+    ) -> bool {
+        let properties = self.format_properties(format);
+        let supported = match tiling {
+            vk::ImageTiling::LINEAR => properties.linear_tiling_features,
+            _ => properties.optimal_tiling_features,
+        };
+        supported.contains(features)
+    }
+
+    /// First of `candidates` supporting `features` with optimal tiling.
+    pub fn find_format(
+        &self,
+        candidates: &[vk::Format],
+        features: vk::FormatFeatureFlags,
+    ) -> Option<vk::Format> {
+        candidates.iter().copied().find(|&format| {
+            self.supports_format_features(format, vk::ImageTiling::OPTIMAL, features)
+        })
+    }
+
+    /// Most precise depth format usable as a sampled depth attachment,
+    /// with a stencil aspect when `stencil` is set.
+    pub fn best_depth_format(&self, stencil: bool) -> vk::Format {
+        let candidates = if stencil {
+            format::DEPTH_STENCIL_FORMATS
+        } else {
+            format::DEPTH_FORMATS
+        };
+
+        self.find_format(
+            candidates,
+            vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE,
+        )
+        .or_else(|| self.find_format(candidates, vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT))
+        .expect("No supported depth format")
+    }
+
+    /// Floating point color format usable as a storage image and color
+    /// attachment, for HDR render targets written by compute passes.
+    pub fn hdr_storage_format(&self) -> Option<vk::Format> {
+        self.find_format(
+            format::HDR_FORMATS,
+            vk::FormatFeatureFlags::STORAGE_IMAGE
+                | vk::FormatFeatureFlags::COLOR_ATTACHMENT
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE,
+        )
+    }
+
+    /// Current usage per category and per heap, with the driver's budget when
+    /// `VK_EXT_memory_budget` is enabled. Heaps crossing
+    /// `MemorySettings::budget_warning` are logged once per crossing.
+    pub fn memory_report(&self) -> MemoryReport {
+        let memory_properties = &self.inner.memory_properties;
+        let budget_properties = self.memory_budget();
+
+        let heaps = memory_properties.memory_heaps[..memory_properties.memory_heap_count as usize]
+            .iter()
+            .enumerate()
+            .map(|(index, heap)| HeapUsage {
+                size: heap.size,
+                device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+                usage: budget_properties.map(|budget| budget.heap_usage[index]),
+                budget: budget_properties.map(|budget| budget.heap_budget[index]),
+            })
+            .collect::<Vec<_>>();
+
+        for (index, heap) in heaps.iter().enumerate() {
+            if let (Some(usage), Some(budget)) = (heap.usage, heap.budget) {
+                let bit = 1 << index;
+                let over =
+                    usage as f64 > budget as f64 * self.inner.memory_settings.budget_warning as f64;
+                let previous = if over {
+                    self.inner.over_budget.fetch_or(bit, Ordering::Relaxed)
+                } else {
+                    self.inner.over_budget.fetch_and(!bit, Ordering::Relaxed)
+                };
+
+                if over && previous & bit == 0 {
+                    tracing::warn!(
+                        "memory heap {} is at {} of {} bytes budget",
+                        index,
+                        usage,
+                        budget
+                    );
+                }
+            }
+        }
+
+        MemoryReport {
+            categories: self.inner.memory_tracker.lock().categories(),
+            heaps,
+        }
+    }
+
+    /// Queries the per-heap usage and budget, `None` without
+    /// `VK_EXT_memory_budget`.
+    fn memory_budget(&self) -> Option<vk::PhysicalDeviceMemoryBudgetPropertiesEXT> {
+        if !self.inner.memory_budget {
+            return None;
+        }
+
+        let mut budget_properties =
+            vk::PhysicalDeviceMemoryBudgetPropertiesEXTBuilder::new().build();
+        let properties =
+            vk::PhysicalDeviceMemoryProperties2Builder::new().extend_from(&mut budget_properties);
+        unsafe {
+            self.instance().get_physical_device_memory_properties2(
+                self.inner.physical_device,
+                Some(*properties),
+            );
+        }
+
+        Some(budget_properties)
+    }
+
+    fn track_allocation(
+        &self,
+        category: MemoryCategory,
+        object_type: vk::ObjectType,
+        handle: u64,
+        bytes: u64,
+        name: &Option<Box<str>>,
+    ) {
+        self.inner
+            .memory_tracker
+            .lock()
+            .track(category, object_type, handle, bytes, name);
+    }
+
+    /// Whether `VK_ERROR_DEVICE_LOST` was returned by a submit, present or
+    /// wait. Once lost, waits return immediately and submissions are skipped
+    /// until the renderer recreates the device.
+    pub fn is_lost(&self) -> bool {
+        self.inner.lost.is_lost()
+    }
+
+    /// Marks the device lost right away, as if the last call had returned
+    /// `VK_ERROR_DEVICE_LOST`, to exercise recovery without a driver reset.
+    pub fn inject_device_lost(&self) {
+        self.inner.lost.mark_lost();
+    }
+
+    /// Unwraps `result`, returning `None` and marking the device lost on
+    /// `VK_ERROR_DEVICE_LOST` instead of panicking.
+    pub fn check_lost<T>(&self, result: VulkanResult<T>) -> Option<T> {
+        self.inner.lost.check(result)
+    }
+
+    pub fn debug_utils_enabled(&self) -> bool {
+        self.inner.debug_utils
+    }
+
+    /// Names `object_handle` for validation messages and captures, a no-op
+    /// without `VK_EXT_debug_utils`.
+    pub fn set_object_name(&self, object_type: vk::ObjectType, object_handle: u64, name: &str) {
+        if !self.inner.debug_utils {
+            return;
+        }
+
+        let name = debug_name(name);
+        unsafe {
+            self.handle()
+                .set_debug_utils_object_name_ext(
+                    &vk::DebugUtilsObjectNameInfoEXTBuilder::new()
+                        .object_type(object_type)
+                        .object_handle(object_handle)
+                        .object_name(&name),
+                )
+                .unwrap()
+        }
+    }
+
+    fn set_optional_object_name(
+        &self,
+        object_type: vk::ObjectType,
+        object_handle: u64,
+        name: &Option<Box<str>>,
+    ) {
+        if let Some(name) = name {
+            self.set_object_name(object_type, object_handle, name);
+        }
+    }
+
     pub fn create_buffer(&self, info: BufferInfo, allocation_flags: UsageFlags) -> MappableBuffer {
         let buffer = unsafe {
             self.inner
@@ -217,12 +552,36 @@ impl Device {
                     &vk::BufferDeviceAddressInfoBuilder::new().buffer(buffer),
                 )
             };
-            Some(device_address)
+            NonZeroU64::new(device_address).map(DeviceAddress)
         } else {
             None
         };
 
         let buffer_index = self.inner.buffers.lock().insert(buffer);
+        self.set_optional_object_name(vk::ObjectType::BUFFER, buffer.0, &info.name);
+
+        let category = if info
+            .usage_flags
+            .contains(vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR)
+        {
+            MemoryCategory::AccelerationStructure
+        } else if !allocation_flags.contains(UsageFlags::UPLOAD) {
+            MemoryCategory::Buffer
+        } else if info
+            .usage_flags
+            .contains(vk::BufferUsageFlags::TRANSFER_SRC)
+        {
+            MemoryCategory::Staging
+        } else {
+            MemoryCategory::FrameData
+        };
+        self.track_allocation(
+            category,
+            vk::ObjectType::BUFFER,
+            buffer.0,
+            mem_block.size(),
+            &info.name,
+        );
 
         tracing::debug!("Created Buffer {:p}", buffer);
         MappableBuffer::new(
@@ -235,33 +594,32 @@ impl Device {
         )
     }
 
-    pub fn create_buffer_with_data<T: 'static>(&self, info: BufferInfo, data: &[T]) -> Buffer
-    where
-        T: Pod,
-    {
-        let mut buffer = self.create_buffer(info, UsageFlags::UPLOAD);
+    /// Makes host writes to `range` of the mapped `memory_block` visible to
+    /// the device. The range is widened to whole `nonCoherentAtomSize` atoms,
+    /// or to the end of the memory object when it reaches the block's end.
+    pub fn flush_mapped_range(
+        &self,
+        memory_block: &MemoryBlock<vk::DeviceMemory>,
+        range: Range<u64>,
+    ) {
+        let atom_mask = self.inner.non_coherent_atom_mask;
+        let block_end = memory_block.offset() + memory_block.size();
+        let start = align_down(atom_mask, memory_block.offset() + range.start);
+        let end = align_up(atom_mask, memory_block.offset() + range.end).unwrap();
+        let size = if end >= block_end {
+            vk::WHOLE_SIZE
+        } else {
+            end - start
+        };
 
         unsafe {
-            let ptr = buffer
-                .memory_block()
-                .map(
-                    EruptMemoryDevice::wrap(&self.inner.handle),
-                    0,
-                    std::mem::size_of_val(data),
-                )
-                .expect("Mapping to buffer failed");
-
-            std::ptr::copy_nonoverlapping(
-                data.as_ptr() as *const u8,
-                ptr.as_ptr(),
-                std::mem::size_of_val(data),
-            );
-
-            buffer
-                .memory_block()
-                .unmap(EruptMemoryDevice::wrap(&self.inner.handle));
+            self.handle()
+                .flush_mapped_memory_ranges(&[vk::MappedMemoryRangeBuilder::new()
+                    .memory(*memory_block.memory())
+                    .offset(start)
+                    .size(size)])
+                .unwrap();
         }
-        buffer.into()
     }
 
     pub fn create_swapchain(&self, surface: &Surface) -> Swapchain {
@@ -280,6 +638,67 @@ impl Device {
         Semaphore::new(semaphore)
     }
 
+    pub fn create_timeline_semaphore(&self, initial_value: u64) -> Semaphore {
+        let mut type_info = vk::SemaphoreTypeCreateInfoBuilder::new()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(initial_value);
+
+        let semaphore = unsafe {
+            self.handle()
+                .create_semaphore(
+                    &vk::SemaphoreCreateInfoBuilder::new().extend_from(&mut type_info),
+                    None,
+                )
+                .unwrap()
+        };
+
+        self.inner.semaphores.lock().insert(semaphore);
+
+        Semaphore::new(semaphore)
+    }
+
+    /// Reports every value as reached once the device is lost, so nothing
+    /// waits on work that will never finish.
+    pub fn semaphore_value(&self, semaphore: &Semaphore) -> u64 {
+        if self.is_lost() {
+            return u64::MAX;
+        }
+
+        let result = unsafe {
+            self.handle()
+                .get_semaphore_counter_value(semaphore.handle())
+        };
+        self.check_lost(result).unwrap_or(u64::MAX)
+    }
+
+    /// Waits until every point is reached, returning `false` on timeout.
+    pub fn wait_timeline(&self, points: &[TimelinePoint], timeout: u64) -> bool {
+        if self.is_lost() {
+            return true;
+        }
+
+        let (semaphores, values) = points
+            .iter()
+            .map(|point| (point.semaphore.handle(), point.value))
+            .unzip::<_, _, SmallVec<[_; 8]>, SmallVec<[_; 8]>>();
+
+        let result = unsafe {
+            self.handle().wait_semaphores(
+                &vk::SemaphoreWaitInfoBuilder::new()
+                    .semaphores(&semaphores)
+                    .values(&values),
+                timeout,
+            )
+        };
+
+        match result.raw {
+            vk::Result::SUCCESS => true,
+            vk::Result::TIMEOUT => false,
+            vk::Result::ERROR_DEVICE_LOST => self.check_lost(result).is_none(),
+            error => panic!("failed to wait for timeline semaphores: {:?}", error),
+        }
+    }
+
     pub fn create_fence(&self) -> Fence {
         let fence = unsafe {
             self.handle()
@@ -306,45 +725,119 @@ impl Device {
             .iter()
             .map(|fence| fence.handle())
             .collect::<SmallVec<[_; 16]>>();
-        unsafe {
-            self.handle()
-                .wait_for_fences(&fences, wait_all, !0)
-                .unwrap();
+        if self.is_lost() {
+            return;
         }
+
+        let result = unsafe { self.handle().wait_for_fences(&fences, wait_all, !0) };
+        self.check_lost(result);
     }
 
     pub fn wait_idle(&self) {
-        unsafe { self.handle().device_wait_idle().unwrap() }
+        if self.is_lost() {
+            return;
+        }
+
+        let result = unsafe { self.handle().device_wait_idle() };
+        self.check_lost(result);
+    }
+
+    pub fn create_query_pool(&self, info: QueryPoolInfo) -> QueryPool {
+        let pool = unsafe {
+            self.handle()
+                .create_query_pool(
+                    &vk::QueryPoolCreateInfoBuilder::new()
+                        .query_type(info.query_type)
+                        .query_count(info.count)
+                        .pipeline_statistics(info.pipeline_statistics),
+                    None,
+                )
+                .unwrap()
+        };
+
+        unsafe { self.handle().reset_query_pool(pool, 0, info.count) }
+
+        self.inner.query_pools.lock().insert(pool);
+        self.set_optional_object_name(vk::ObjectType::QUERY_POOL, pool.0, &info.name);
+
+        QueryPool::new(info, pool)
+    }
+
+    pub fn reset_query_pool(&self, pool: &QueryPool, queries: Range<u32>) {
+        unsafe {
+            self.handle().reset_query_pool(
+                pool.handle(),
+                queries.start,
+                queries.end - queries.start,
+            )
+        }
+    }
+
+    /// Reads back the results of `queries`, or `None` if any of them is not
+    /// available yet.
+    pub fn get_query_results(&self, pool: &QueryPool, queries: Range<u32>) -> Option<Vec<u64>> {
+        let values_per_query = pool.info().values_per_query();
+        let mut data = vec![0u64; (queries.end - queries.start) as usize * values_per_query];
+
+        let result = unsafe {
+            self.handle().get_query_pool_results(
+                pool.handle(),
+                queries.start,
+                queries.end - queries.start,
+                std::mem::size_of_val(&data[..]),
+                data.as_mut_ptr() as *mut c_void,
+                (values_per_query * std::mem::size_of::<u64>()) as u64,
+                vk::QueryResultFlags::_64,
+            )
+        };
+
+        if result.raw == vk::Result::SUCCESS {
+            Some(data)
+        } else {
+            None
+        }
     }
 
     pub fn create_descriptor_set_layout(
         &self,
         info: DescriptorSetLayoutInfo,
     ) -> DescriptorSetLayout {
+        let bindings = info
+            .bindings
+            .iter()
+            .map(|binding| {
+                vk::DescriptorSetLayoutBindingBuilder::new()
+                    .binding(binding.binding)
+                    .descriptor_count(binding.count)
+                    .descriptor_type(binding.descriptor_type)
+                    .stage_flags(binding.stages)
+            })
+            .collect::<SmallVec<[_; 16]>>();
+        let binding_flags = info
+            .bindings
+            .iter()
+            .map(|binding| binding.flags)
+            .collect::<SmallVec<[_; 16]>>();
+
+        let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfoBuilder::new()
+            .binding_flags(&binding_flags);
+        let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new()
+            .bindings(&bindings)
+            .flags(info.flags);
+        let create_info = if binding_flags.iter().any(|flags| !flags.is_empty()) {
+            create_info.extend_from(&mut binding_flags_info)
+        } else {
+            create_info
+        };
+
         let handle = unsafe {
             self.handle()
-                .create_descriptor_set_layout(
-                    &vk::DescriptorSetLayoutCreateInfoBuilder::new()
-                        .bindings(
-                            &info
-                                .bindings
-                                .iter()
-                                .map(|binding| {
-                                    vk::DescriptorSetLayoutBindingBuilder::new()
-                                        .binding(binding.binding)
-                                        .descriptor_count(binding.count)
-                                        .descriptor_type(binding.descriptor_type)
-                                        .stage_flags(binding.stages)
-                                })
-                                .collect::<SmallVec<[_; 16]>>(),
-                        )
-                        .flags(info.flags),
-                    None,
-                )
+                .create_descriptor_set_layout(&create_info, None)
                 .unwrap()
         };
 
         self.inner.descriptor_set_layouts.lock().insert(handle);
+        self.set_optional_object_name(vk::ObjectType::DESCRIPTOR_SET_LAYOUT, handle.0, &info.name);
 
         let sizes = DescriptorSizes::from_bindings(&info.bindings);
 
@@ -390,6 +883,121 @@ impl Device {
         DescriptorSet::new(info, handles[0], pool)
     }
 
+    pub fn update_descriptor_sets(
+        &self,
+        writes: &[WriteDescriptorSet<'_>],
+        copies: &[CopyDescriptorSet<'_>],
+    ) {
+        let infos = writes
+            .iter()
+            .map(|write| match write.descriptors {
+                Descriptors::Sampler(samplers) => DescriptorInfos::Images(
+                    samplers
+                        .iter()
+                        .map(|sampler| {
+                            vk::DescriptorImageInfoBuilder::new().sampler(sampler.handle())
+                        })
+                        .collect(),
+                ),
+                Descriptors::CombinedImageSampler(images) => DescriptorInfos::Images(
+                    images
+                        .iter()
+                        .map(|(view, layout, sampler)| {
+                            vk::DescriptorImageInfoBuilder::new()
+                                .image_view(view.handle())
+                                .image_layout(*layout)
+                                .sampler(sampler.handle())
+                        })
+                        .collect(),
+                ),
+                Descriptors::SampledImage(images)
+                | Descriptors::StorageImage(images)
+                | Descriptors::InputAttachment(images) => DescriptorInfos::Images(
+                    images
+                        .iter()
+                        .map(|(view, layout)| {
+                            vk::DescriptorImageInfoBuilder::new()
+                                .image_view(view.handle())
+                                .image_layout(*layout)
+                        })
+                        .collect(),
+                ),
+                Descriptors::UniformBuffer(buffers)
+                | Descriptors::StorageBuffer(buffers)
+                | Descriptors::UniformBufferDynamic(buffers)
+                | Descriptors::StorageBufferDynamic(buffers) => DescriptorInfos::Buffers(
+                    buffers
+                        .iter()
+                        .map(|(buffer, offset, size)| {
+                            vk::DescriptorBufferInfoBuilder::new()
+                                .buffer(buffer.handle())
+                                .offset(*offset)
+                                .range(*size)
+                        })
+                        .collect(),
+                ),
+                Descriptors::AccelerationStructure(acceleration_structures) => {
+                    DescriptorInfos::AccelerationStructures(
+                        acceleration_structures
+                            .iter()
+                            .map(|acceleration_structure| acceleration_structure.handle())
+                            .collect(),
+                    )
+                }
+            })
+            .collect::<SmallVec<[_; 16]>>();
+
+        let mut acceleration_structure_writes = infos
+            .iter()
+            .map(|infos| match infos {
+                DescriptorInfos::AccelerationStructures(handles) => {
+                    vk::WriteDescriptorSetAccelerationStructureKHRBuilder::new()
+                        .acceleration_structures(handles)
+                }
+                _ => vk::WriteDescriptorSetAccelerationStructureKHRBuilder::new(),
+            })
+            .collect::<SmallVec<[_; 16]>>();
+
+        let vk_writes = writes
+            .iter()
+            .zip(&infos)
+            .zip(&mut acceleration_structure_writes)
+            .map(|((write, infos), acceleration_structure_write)| {
+                let builder = vk::WriteDescriptorSetBuilder::new()
+                    .dst_set(write.set.handle())
+                    .dst_binding(write.binding)
+                    .dst_array_element(write.element)
+                    .descriptor_type(write.descriptors.descriptor_type());
+
+                match infos {
+                    DescriptorInfos::Images(images) => builder.image_info(images),
+                    DescriptorInfos::Buffers(buffers) => builder.buffer_info(buffers),
+                    DescriptorInfos::AccelerationStructures(handles) => {
+                        let mut builder = builder.extend_from(acceleration_structure_write);
+                        builder.descriptor_count = handles.len() as u32;
+                        builder
+                    }
+                }
+            })
+            .collect::<SmallVec<[_; 16]>>();
+
+        let vk_copies = copies
+            .iter()
+            .map(|copy| {
+                vk::CopyDescriptorSetBuilder::new()
+                    .src_set(copy.src.handle())
+                    .src_binding(copy.src_binding)
+                    .src_array_element(copy.src_element)
+                    .dst_set(copy.dst.handle())
+                    .dst_binding(copy.dst_binding)
+                    .dst_array_element(copy.dst_element)
+                    .descriptor_count(copy.count)
+            })
+            .collect::<SmallVec<[_; 4]>>();
+
+        unsafe { self.handle().update_descriptor_sets(&vk_writes, &vk_copies) }
+    }
+
     pub fn create_pipeline_layout(&self, info: PipelineLayoutInfo) -> PipelineLayout {
         let pipeline_layout = unsafe {
             self.handle()
@@ -420,17 +1028,35 @@ impl Device {
         };
 
         self.inner.pipeline_layouts.lock().insert(pipeline_layout);
+        self.set_optional_object_name(
+            vk::ObjectType::PIPELINE_LAYOUT,
+            pipeline_layout.0,
+            &info.name,
+        );
 
         PipelineLayout::new(info, pipeline_layout)
     }
 
     pub fn create_shader_module(&self, info: ShaderModuleInfo) -> ShaderModule {
-        let code = match info.language {
-            ShaderLanguage::GLSL => panic!("glsl is not supported"),
-            ShaderLanguage::SPIRV => &*info.code,
+        self.try_create_shader_module(info)
+            .unwrap_or_else(|error| panic!("Shader compilation failed: {}", error))
+    }
+
+    pub fn try_create_shader_module(
+        &self,
+        info: ShaderModuleInfo,
+    ) -> Result<ShaderModule, ShaderCompileError> {
+        let spv = match info.language {
+            ShaderLanguage::GLSL | ShaderLanguage::WGSL => compile_to_spirv(&info)?,
+            ShaderLanguage::SPIRV => {
+                erupt::utils::decode_spv(&info.code).map_err(|error| ShaderCompileError {
+                    file: shader_source_dir().join(&*info.name),
+                    line: None,
+                    message: format!("invalid SPIR-V: {}", error),
+                })?
+            }
         };
 
-        let spv = erupt::utils::decode_spv(&code).unwrap();
         let module = unsafe {
             self.handle()
                 .create_shader_module(&vk::ShaderModuleCreateInfoBuilder::new().code(&spv), None)
@@ -438,8 +1064,9 @@ impl Device {
         };
 
         self.inner.shader_modules.lock().insert(module);
+        self.set_object_name(vk::ObjectType::SHADER_MODULE, module.0, &info.name);
 
-        ShaderModule::new(info, module)
+        Ok(ShaderModule::new(info, module))
     }
 
     pub fn create_render_pass(&self, info: RenderPassInfo) -> RenderPass {
@@ -449,7 +1076,7 @@ impl Device {
             .map(|attachment| {
                 vk::AttachmentDescriptionBuilder::new()
                     .format(attachment.format)
-                    .samples(vk::SampleCountFlagBits::_1)
+                    .samples(vk::SampleCountFlagBits(attachment.samples.bits()))
                     .load_op(attachment.load_op)
                     .store_op(attachment.store_op)
                     .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
@@ -462,59 +1089,96 @@ impl Device {
             })
             .collect::<SmallVec<[_; 16]>>();
 
-        let mut subpass_attachments = Vec::new();
-        let subpass_offsets = {
-            info.subpasses
-                .iter()
-                .enumerate()
-                .map(|(i, subpass)| {
-                    let color_offset = subpass_attachments.len();
-                    subpass_attachments.extend(
-                        subpass
-                            .colors
-                            .iter()
-                            .enumerate()
-                            .map(|(color_i, &color)| {
-                                vk::AttachmentReferenceBuilder::new()
-                                    .attachment(color as _)
-                                    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                            })
-                            .collect::<SmallVec<[_; 16]>>(),
-                    );
-
-                    let depth_offset = subpass_attachments.len();
-                    if let Some(depth) = subpass.depth {
-                        subpass_attachments.push(
-                            vk::AttachmentReferenceBuilder::new()
-                                .attachment(depth as _)
-                                .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
-                        )
-                    }
-                    (color_offset, depth_offset)
-                })
-                .collect::<SmallVec<[_; 16]>>()
+        let reference = |attachment: usize, layout: vk::ImageLayout| {
+            vk::AttachmentReferenceBuilder::new()
+                .attachment(attachment as _)
+                .layout(layout)
         };
 
-        let subpasses = info
+        let subpass_references = info
             .subpasses
             .iter()
-            .zip(subpass_offsets)
-            .map(|(subpass, (color_offset, depth_offset))| {
-                let subpass_descriptor = vk::SubpassDescriptionBuilder::new()
+            .map(|subpass| {
+                assert!(
+                    subpass.resolves.is_empty() || subpass.resolves.len() == subpass.colors.len(),
+                    "subpass needs one resolve attachment per color attachment"
+                );
+
+                let colors = subpass
+                    .colors
+                    .iter()
+                    .map(|&color| reference(color, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL))
+                    .collect::<SmallVec<[_; 4]>>();
+                let resolves = subpass
+                    .resolves
+                    .iter()
+                    .map(|&resolve| reference(resolve, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL))
+                    .collect::<SmallVec<[_; 4]>>();
+                let inputs = subpass
+                    .inputs
+                    .iter()
+                    .map(|&input| {
+                        let layout = if format::aspect_flags(info.attachments[input].format)
+                            .contains(vk::ImageAspectFlags::DEPTH)
+                        {
+                            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+                        } else {
+                            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                        };
+                        reference(input, layout)
+                    })
+                    .collect::<SmallVec<[_; 4]>>();
+                let depth = subpass.depth.map(|depth| {
+                    reference(depth, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                });
+
+                (colors, resolves, inputs, depth)
+            })
+            .collect::<SmallVec<[_; 4]>>();
+
+        let subpasses = subpass_references
+            .iter()
+            .map(|(colors, resolves, inputs, depth)| {
+                let mut subpass_descriptor = vk::SubpassDescriptionBuilder::new()
                     .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-                    .color_attachments(&subpass_attachments[color_offset..depth_offset]);
+                    .color_attachments(colors)
+                    .input_attachments(inputs);
 
-                if subpass.depth.is_some() {
-                    subpass_descriptor.depth_stencil_attachment(&subpass_attachments[depth_offset])
-                } else {
-                    subpass_descriptor
+                if !resolves.is_empty() {
+                    subpass_descriptor = subpass_descriptor.resolve_attachments(resolves);
                 }
+
+                if let Some(depth) = depth {
+                    subpass_descriptor = subpass_descriptor.depth_stencil_attachment(depth);
+                }
+
+                subpass_descriptor
             })
-            .collect::<Vec<_>>();
+            .collect::<SmallVec<[_; 4]>>();
+
+        let dependencies = info
+            .dependencies
+            .iter()
+            .map(|dependency| {
+                vk::SubpassDependencyBuilder::new()
+                    .src_subpass(dependency.src.map_or(vk::SUBPASS_EXTERNAL, |src| src as _))
+                    .dst_subpass(dependency.dst.map_or(vk::SUBPASS_EXTERNAL, |dst| dst as _))
+                    .src_stage_mask(dependency.src_stages)
+                    .dst_stage_mask(dependency.dst_stages)
+                    .src_access_mask(dependency.src_access)
+                    .dst_access_mask(dependency.dst_access)
+                    .dependency_flags(if dependency.by_region {
+                        vk::DependencyFlags::BY_REGION
+                    } else {
+                        vk::DependencyFlags::empty()
+                    })
+            })
+            .collect::<SmallVec<[_; 4]>>();
 
         let render_pass_create_info = vk::RenderPassCreateInfoBuilder::new()
             .attachments(&attachments)
-            .subpasses(&subpasses);
+            .subpasses(&subpasses)
+            .dependencies(&dependencies);
 
         let render_pass = unsafe {
             self.handle()
@@ -523,6 +1187,7 @@ impl Device {
         };
 
         self.inner.render_passes.lock().insert(render_pass);
+        self.set_optional_object_name(vk::ObjectType::RENDER_PASS, render_pass.0, &info.name);
 
         RenderPass::new(info, render_pass)
     }
@@ -579,6 +1244,21 @@ impl Device {
         let color_blend_info;
         let multisample_info;
 
+        let (render_pass, subpass) = match &info.target {
+            PipelineTarget::RenderPass {
+                render_pass,
+                subpass,
+            } => (render_pass.handle(), *subpass),
+            PipelineTarget::Dynamic(_) => (vk::RenderPass::default(), 0),
+        };
+
+        let mut rendering_info = match &info.target {
+            PipelineTarget::Dynamic(formats) => vk::PipelineRenderingCreateInfoKHRBuilder::new()
+                .color_attachment_formats(&formats.colors)
+                .depth_attachment_format(formats.depth.unwrap_or(vk::Format::UNDEFINED)),
+            PipelineTarget::RenderPass { .. } => vk::PipelineRenderingCreateInfoKHRBuilder::new(),
+        };
+
         let pipeline_info = if let Some(rasterizer) = &info.rasterizer {
             dynamic_state_info = vk::PipelineDynamicStateCreateInfoBuilder::new()
                 .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
@@ -600,23 +1280,34 @@ impl Device {
                 .build();
             depth_stencil_info = vk::PipelineDepthStencilStateCreateInfoBuilder::new()
                 .depth_test_enable(true)
-                .depth_write_enable(true)
-                .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+                .depth_write_enable(rasterizer.depth_write)
+                .depth_compare_op(rasterizer.depth_compare)
                 .depth_bounds_test_enable(false)
                 .stencil_test_enable(false)
                 .front(stencil_op)
                 .back(stencil_op);
-            color_blend_attachments = [vk::PipelineColorBlendAttachmentStateBuilder::new()
-                .color_write_mask(
-                    vk::ColorComponentFlags::R
-                        | vk::ColorComponentFlags::G
-                        | vk::ColorComponentFlags::B
-                        | vk::ColorComponentFlags::A,
-                )];
+            color_blend_attachments = (0..info.target.color_count())
+                .map(|_| {
+                    vk::PipelineColorBlendAttachmentStateBuilder::new()
+                        .color_write_mask(
+                            vk::ColorComponentFlags::R
+                                | vk::ColorComponentFlags::G
+                                | vk::ColorComponentFlags::B
+                                | vk::ColorComponentFlags::A,
+                        )
+                        .blend_enable(rasterizer.alpha_blend)
+                        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+                        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                        .color_blend_op(vk::BlendOp::ADD)
+                        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                        .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                        .alpha_blend_op(vk::BlendOp::ADD)
+                })
+                .collect::<SmallVec<[_; 4]>>();
             color_blend_info = vk::PipelineColorBlendStateCreateInfoBuilder::new()
                 .attachments(&color_blend_attachments);
             multisample_info = vk::PipelineMultisampleStateCreateInfoBuilder::new()
-                .rasterization_samples(vk::SampleCountFlagBits::_1);
+                .rasterization_samples(vk::SampleCountFlagBits(info.target.samples().bits()));
 
             if let Some(fragment_shader) = &rasterizer.fragment_shader {
                 shader_stages.push(
@@ -632,8 +1323,8 @@ impl Device {
                 .vertex_input_state(&vertex_input_state)
                 .input_assembly_state(&input_assembly_state)
                 .layout(info.layout.handle())
-                .render_pass(info.render_pass.handle())
-                .subpass(info.subpass)
+                .render_pass(render_pass)
+                .subpass(subpass)
                 .rasterization_state(&rasterization_info)
                 .dynamic_state(&dynamic_state_info)
                 .viewport_state(&viewport_info)
@@ -646,8 +1337,13 @@ impl Device {
                 .vertex_input_state(&vertex_input_state)
                 .input_assembly_state(&input_assembly_state)
                 .layout(info.layout.handle())
-                .render_pass(info.render_pass.handle())
-                .subpass(info.subpass)
+                .render_pass(render_pass)
+                .subpass(subpass)
+        };
+
+        let pipeline_info = match info.target {
+            PipelineTarget::Dynamic(_) => pipeline_info.extend_from(&mut rendering_info),
+            PipelineTarget::RenderPass { .. } => pipeline_info,
         };
 
         let pipelines = unsafe {
@@ -658,22 +1354,39 @@ impl Device {
 
         let pipeline = pipelines[0];
         self.inner.pipelines.lock().insert(pipeline);
+        self.set_optional_object_name(vk::ObjectType::PIPELINE, pipeline.0, &info.name);
 
         GraphicsPipeline::new(info, pipeline)
     }
 
     pub fn create_image(&self, info: ImageInfo) -> Image {
+        assert!(info.is_valid(), "invalid image info");
+
+        let required = format::required_features(info.usage);
+        assert!(
+            self.supports_format_features(info.format, vk::ImageTiling::OPTIMAL, required),
+            "format {:?} does not support {:?} needed for usage {:?}",
+            info.format,
+            required,
+            info.usage
+        );
+
+        let mut flags = vk::ImageCreateFlags::empty();
+        if info.cube_compatible {
+            flags |= vk::ImageCreateFlags::CUBE_COMPATIBLE;
+        }
+        if info.mutable_format {
+            flags |= vk::ImageCreateFlags::MUTABLE_FORMAT;
+        }
+
         let image = unsafe {
             self.handle()
                 .create_image(
                     &vk::ImageCreateInfoBuilder::new()
-                        .image_type(vk::ImageType::_2D)
+                        .flags(flags)
+                        .image_type(info.extent.image_type())
                         .format(info.format)
-                        .extent(vk::Extent3D {
-                            width: info.extent.width,
-                            height: info.extent.height,
-                            depth: 1,
-                        })
+                        .extent(info.extent.into_3d())
                         .mip_levels(info.mip_levels)
                         .array_layers(info.array_layers)
                         .samples(info.samples)
@@ -704,6 +1417,14 @@ impl Device {
         };
 
         self.inner.images.lock().insert(image);
+        self.set_optional_object_name(vk::ObjectType::IMAGE, image.0, &info.name);
+        self.track_allocation(
+            MemoryCategory::Image,
+            vk::ObjectType::IMAGE,
+            image.0,
+            memory_block.size(),
+            &info.name,
+        );
 
         unsafe {
             self.handle()
@@ -715,32 +1436,71 @@ impl Device {
     }
 
     pub fn create_image_view(&self, info: ImageViewInfo) -> ImageView {
+        let image_info = info.image.info();
+        assert!(
+            info.format == image_info.format
+                || (image_info.mutable_format
+                    && format::describe(image_info.format)
+                        .and_then(|description| description.srgb_pair)
+                        == Some(info.format)),
+            "view format {:?} of a {:?} image",
+            info.format,
+            image_info.format
+        );
+        assert!(
+            format::aspect_flags(info.format).contains(info.subresource.aspect),
+            "aspect {:?} is not part of format {:?}",
+            info.subresource.aspect,
+            info.format
+        );
+        assert!(
+            info.view_type != vk::ImageViewType::CUBE_ARRAY || self.inner.image_cube_array,
+            "cube array views need the imageCubeArray feature"
+        );
+
         let view = unsafe {
             self.handle()
                 .create_image_view(
                     &vk::ImageViewCreateInfoBuilder::new()
                         .image(info.image.handle())
-                        .format(info.image.info().format)
+                        .format(info.format)
                         .view_type(info.view_type)
-                        .subresource_range(
-                            vk::ImageSubresourceRangeBuilder::new()
-                                .aspect_mask(info.subresource.aspect)
-                                .base_mip_level(info.subresource.first_level)
-                                .level_count(info.subresource.level_count)
-                                .base_array_layer(info.subresource.first_layer)
-                                .layer_count(info.subresource.layer_count)
-                                .build(),
-                        ),
+                        .subresource_range((&info.subresource).into()),
                     None,
                 )
                 .unwrap()
         };
 
         self.inner.image_views.lock().insert(view);
+        self.set_optional_object_name(vk::ObjectType::IMAGE_VIEW, view.0, &info.name);
 
         ImageView::new(info, view)
     }
 
+    pub fn create_sampler(&self, info: SamplerInfo) -> Sampler {
+        let sampler = unsafe {
+            self.handle()
+                .create_sampler(
+                    &vk::SamplerCreateInfoBuilder::new()
+                        .mag_filter(info.mag_filter)
+                        .min_filter(info.min_filter)
+                        .mipmap_mode(info.mipmap_mode)
+                        .address_mode_u(info.address_mode)
+                        .address_mode_v(info.address_mode)
+                        .address_mode_w(info.address_mode)
+                        .min_lod(0.0)
+                        .max_lod(vk::LOD_CLAMP_NONE),
+                    None,
+                )
+                .unwrap()
+        };
+
+        self.inner.samplers.lock().insert(sampler);
+        self.set_optional_object_name(vk::ObjectType::SAMPLER, sampler.0, &info.name);
+
+        Sampler::new(info, sampler)
+    }
+
     pub fn create_framebuffer(&self, info: FramebufferInfo) -> Framebuffer {
         let render_pass = info.render_pass.handle();
 
@@ -765,9 +1525,115 @@ impl Device {
         };
 
         self.inner.framebuffers.lock().insert(framebuffer);
+        self.set_optional_object_name(vk::ObjectType::FRAMEBUFFER, framebuffer.0, &info.name);
 
         Framebuffer::new(info, framebuffer)
     }
+
+    /// Destroys `buffer` and frees its memory. The GPU must be done with it;
+    /// resources replaced while frames are in flight are destroyed through
+    /// `GpuTimeline::defer`. Destroying a buffer twice does nothing.
+    pub fn destroy_buffer(&self, buffer: &Buffer) {
+        let memory_block = match unsafe { buffer.take_memory_block() } {
+            Some(memory_block) => memory_block,
+            None => return,
+        };
+
+        self.inner.buffers.lock().remove(buffer.index());
+        self.inner
+            .memory_tracker
+            .lock()
+            .untrack(vk::ObjectType::BUFFER, buffer.handle().0);
+
+        unsafe {
+            self.handle().destroy_buffer(Some(buffer.handle()), None);
+            self.allocator()
+                .lock()
+                .dealloc(EruptMemoryDevice::wrap(self.handle()), memory_block);
+        }
+    }
+
+    /// Destroys `image` and frees its memory, like `destroy_buffer`. Swapchain
+    /// images are owned by their swapchain and left alone.
+    pub fn destroy_image(&self, image: &Image) {
+        let memory_block = match image.take_memory_block() {
+            Some(memory_block) => memory_block,
+            None => return,
+        };
+
+        remove_handle(&self.inner.images, image.handle());
+        self.inner
+            .memory_tracker
+            .lock()
+            .untrack(vk::ObjectType::IMAGE, image.handle().0);
+
+        unsafe {
+            self.handle().destroy_image(Some(image.handle()), None);
+            self.allocator()
+                .lock()
+                .dealloc(EruptMemoryDevice::wrap(self.handle()), memory_block);
+        }
+    }
+
+    pub fn destroy_image_view(&self, view: &ImageView) {
+        if remove_handle(&self.inner.image_views, view.handle()) {
+            unsafe { self.handle().destroy_image_view(Some(view.handle()), None) }
+        }
+    }
+
+    pub fn destroy_framebuffer(&self, framebuffer: &Framebuffer) {
+        if remove_handle(&self.inner.framebuffers, framebuffer.handle()) {
+            unsafe {
+                self.handle()
+                    .destroy_framebuffer(Some(framebuffer.handle()), None)
+            }
+        }
+    }
+
+    pub fn destroy_graphics_pipeline(&self, pipeline: &GraphicsPipeline) {
+        if remove_handle(&self.inner.pipelines, pipeline.handle()) {
+            unsafe {
+                self.handle()
+                    .destroy_pipeline(Some(pipeline.handle()), None)
+            }
+        }
+    }
+
+    /// Pipelines created from `module` stay valid after it is destroyed.
+    pub fn destroy_shader_module(&self, module: &ShaderModule) {
+        if remove_handle(&self.inner.shader_modules, module.handle()) {
+            unsafe {
+                self.handle()
+                    .destroy_shader_module(Some(module.handle()), None)
+            }
+        }
+    }
+
+    pub fn destroy_sampler(&self, sampler: &Sampler) {
+        if remove_handle(&self.inner.samplers, sampler.handle()) {
+            unsafe { self.handle().destroy_sampler(Some(sampler.handle()), None) }
+        }
+    }
+}
+
+/// Removes `handle` from `slab`, returning whether it was there.
+fn remove_handle<T>(slab: &Mutex<Slab<T>>, handle: T) -> bool
+where
+    T: Copy + PartialEq,
+{
+    let mut slab = slab.lock();
+    let index = slab
+        .iter()
+        .find(|(_, entry)| **entry == handle)
+        .map(|(index, _)| index);
+
+    match index {
+        Some(index) => {
+            slab.remove(index);
+            true
+        }
+        None => false,
+    }
 }
 
 fn get_allocator_memory_usage(usage: &vk::ImageUsageFlags) -> UsageFlags {
@@ -777,3 +1643,50 @@ fn get_allocator_memory_usage(usage: &vk::ImageUsageFlags) -> UsageFlags {
         UsageFlags::empty()
     }
 }
+
+/// Per-write descriptor info arrays `update_descriptor_sets` points into.
+enum DescriptorInfos {
+    Images(SmallVec<[vk::DescriptorImageInfoBuilder<'static>; 4]>),
+    Buffers(SmallVec<[vk::DescriptorBufferInfoBuilder<'static>; 4]>),
+    AccelerationStructures(SmallVec<[vk::AccelerationStructureKHR; 4]>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_lost_results_mark_the_device_lost() {
+        let state = LostState::default();
+        assert!(!state.is_lost());
+
+        assert_eq!(state.check(VulkanResult::new_ok(7)), Some(7));
+        assert!(!state.is_lost());
+
+        assert_eq!(
+            state.check(VulkanResult::<u32>::new_err(vk::Result::ERROR_DEVICE_LOST)),
+            None
+        );
+        assert!(state.is_lost());
+
+        // Loss is sticky, later successes don't clear it.
+        assert_eq!(state.check(VulkanResult::new_ok(8)), Some(8));
+        assert!(state.is_lost());
+    }
+
+    #[test]
+    fn injected_loss_is_idempotent() {
+        let state = LostState::default();
+        state.mark_lost();
+        state.mark_lost();
+        assert!(state.is_lost());
+    }
+
+    #[test]
+    #[should_panic]
+    fn other_errors_still_panic() {
+        LostState::default().check(VulkanResult::<u32>::new_err(
+            vk::Result::ERROR_OUT_OF_DEVICE_MEMORY,
+        ));
+    }
+}
@@ -1,15 +1,19 @@
 use crate::acceleration_structures::AccelerationStructureBuildGeometryInfo;
+use crate::buffer::BufferMemoryBarrier;
 use crate::command_buffer::CommandBuffer;
+use crate::debug::debug_name;
 use crate::device::Device;
+use crate::image::{Image, ImageMemoryBarrier};
 use crate::pipeline::ShaderBindingTable;
-use crate::render_pass::ClearValue;
+use crate::render_pass::{ClearValue, RenderingInfo};
 use crate::resources::{
-    Buffer, DescriptorSet, Framebuffer, GraphicsPipeline, PipelineLayout, RayTracingPipeline,
-    RenderPass,
+    Buffer, DescriptorSet, Framebuffer, GraphicsPipeline, PipelineLayout, QueryPool,
+    RayTracingPipeline, RenderPass,
 };
-use crevice::internal::bytemuck::Pod;
+use crevice::internal::bytemuck::{self, Pod};
 use erupt::vk;
 use erupt::vk1_0::Viewport;
+use std::ffi::CString;
 use std::ops::{Deref, DerefMut, Range};
 
 pub struct Encoder<'a> {
@@ -77,9 +81,47 @@ impl<'a> EncoderInner<'a> {
             render_pass: pass,
             framebuffer,
             clears,
+            contents: vk::SubpassContents::INLINE,
         })
     }
 
+    /// Begins a render pass whose first subpass is recorded in secondary
+    /// command buffers passed to `execute_commands`.
+    pub fn begin_render_pass_secondary(
+        &mut self,
+        pass: &'a RenderPass,
+        framebuffer: &'a Framebuffer,
+        clears: &'a [ClearValue],
+    ) {
+        self.commands.push(Command::BeginRenderPass {
+            render_pass: pass,
+            framebuffer,
+            clears,
+            contents: vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+        })
+    }
+
+    pub fn execute_commands(&mut self, command_buffers: &'a [CommandBuffer]) {
+        self.commands
+            .push(Command::ExecuteCommands { command_buffers })
+    }
+
+    /// Begins rendering to the attachments in `info` without a render pass
+    /// object. Needs `VK_KHR_dynamic_rendering`, see `Device::dynamic_rendering`.
+    pub fn begin_rendering(&mut self, info: RenderingInfo<'a>) {
+        self.commands.push(Command::BeginRendering { info })
+    }
+
+    pub fn end_rendering(&mut self) {
+        self.commands.push(Command::EndRendering)
+    }
+
+    /// Advances to the next subpass. `contents` tells whether its commands
+    /// are recorded inline or come from secondary command buffers.
+    pub fn next_subpass(&mut self, contents: vk::SubpassContents) {
+        self.commands.push(Command::NextSubpass { contents })
+    }
+
     pub fn end_render_pass(&mut self) {
         self.commands.push(Command::EndRenderPass)
     }
@@ -89,6 +131,60 @@ impl<'a> EncoderInner<'a> {
             .push(Command::BindGraphicsPipeline { pipeline })
     }
 
+    pub fn bind_vertex_buffers(&mut self, first: u32, buffers: &'a [(Buffer, u64)]) {
+        self.commands
+            .push(Command::BindVertexBuffers { first, buffers })
+    }
+
+    pub fn bind_index_buffer(
+        &mut self,
+        buffer: &'a Buffer,
+        offset: u64,
+        index_type: vk::IndexType,
+    ) {
+        self.commands.push(Command::BindIndexBuffer {
+            buffer,
+            offset,
+            index_type,
+        })
+    }
+
+    /// `dynamic_offsets` holds one offset per dynamic descriptor, in binding
+    /// order, such as those returned by the frame allocator.
+    pub fn bind_graphics_descriptor_sets(
+        &mut self,
+        layout: &'a PipelineLayout,
+        first_set: u32,
+        descriptor_sets: &'a [DescriptorSet],
+        dynamic_offsets: &'a [u32],
+    ) {
+        self.commands.push(Command::BindGraphicsDescriptorSets {
+            layout,
+            first_set,
+            descriptor_sets,
+            dynamic_offsets,
+        })
+    }
+
+    /// Writes `data` to the push constant range at `offset`, for example a
+    /// `GpuPtr` or `GpuSlice` read through `GL_EXT_buffer_reference`.
+    pub fn push_constants<T>(
+        &mut self,
+        layout: &'a PipelineLayout,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        data: &'a T,
+    ) where
+        T: Pod,
+    {
+        self.commands.push(Command::PushConstants {
+            layout,
+            stages,
+            offset,
+            data: bytemuck::bytes_of(data),
+        })
+    }
+
     pub fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>) {
         self.commands.push(Command::Draw {
             vertices,
@@ -111,6 +207,79 @@ impl<'a> EncoderInner<'a> {
     pub fn set_scissor(&mut self, scissor: vk::Rect2D) {
         self.commands.push(Command::SetScissor { scissor })
     }
+
+    pub fn push_label(&mut self, name: &str) {
+        self.commands.push(Command::PushLabel {
+            name: debug_name(name),
+        })
+    }
+
+    pub fn pop_label(&mut self) {
+        self.commands.push(Command::PopLabel)
+    }
+
+    pub fn insert_label(&mut self, name: &str) {
+        self.commands.push(Command::InsertLabel {
+            name: debug_name(name),
+        })
+    }
+
+    pub fn write_timestamp(
+        &mut self,
+        stage: vk::PipelineStageFlagBits,
+        pool: QueryPool,
+        query: u32,
+    ) {
+        self.commands
+            .push(Command::WriteTimestamp { stage, pool, query })
+    }
+
+    pub fn begin_query(&mut self, pool: QueryPool, query: u32) {
+        self.commands.push(Command::BeginQuery { pool, query })
+    }
+
+    pub fn end_query(&mut self, pool: QueryPool, query: u32) {
+        self.commands.push(Command::EndQuery { pool, query })
+    }
+
+    pub fn copy_buffer(&mut self, src: &'a Buffer, dst: &'a Buffer, regions: &'a [vk::BufferCopy]) {
+        self.commands
+            .push(Command::CopyBuffer { src, dst, regions })
+    }
+
+    /// `dst` must be in `layout`, either `TRANSFER_DST_OPTIMAL` or `GENERAL`.
+    pub fn copy_buffer_to_image(
+        &mut self,
+        src: &'a Buffer,
+        dst: &'a Image,
+        layout: vk::ImageLayout,
+        regions: &'a [vk::BufferImageCopy],
+    ) {
+        self.commands.push(Command::CopyBufferToImage {
+            src,
+            dst,
+            layout,
+            regions,
+        })
+    }
+
+    /// Access masks are derived from the stages. Barriers with a
+    /// `family_transfer` are a release when recorded on the source family and
+    /// an acquire on the destination family.
+    pub fn pipeline_barrier(
+        &mut self,
+        src: vk::PipelineStageFlags,
+        dst: vk::PipelineStageFlags,
+        images: &'a [ImageMemoryBarrier<'a>],
+        buffers: &'a [BufferMemoryBarrier<'a>],
+    ) {
+        self.commands.push(Command::PipelineBarrier {
+            src,
+            dst,
+            images,
+            buffers,
+        })
+    }
 }
 
 pub enum Command<'a> {
@@ -118,9 +287,22 @@ pub enum Command<'a> {
         render_pass: &'a RenderPass,
         framebuffer: &'a Framebuffer,
         clears: &'a [ClearValue],
+        contents: vk::SubpassContents,
+    },
+    NextSubpass {
+        contents: vk::SubpassContents,
     },
     EndRenderPass,
 
+    BeginRendering {
+        info: RenderingInfo<'a>,
+    },
+    EndRendering,
+
+    ExecuteCommands {
+        command_buffers: &'a [CommandBuffer],
+    },
+
     BindGraphicsPipeline {
         pipeline: &'a GraphicsPipeline,
     },
@@ -143,6 +325,13 @@ pub enum Command<'a> {
         dynamic_offsets: &'a [u32],
     },
 
+    PushConstants {
+        layout: &'a PipelineLayout,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        data: &'a [u8],
+    },
+
     SetViewport {
         viewport: vk::Viewport,
     },
@@ -186,4 +375,50 @@ pub enum Command<'a> {
     TraceRays {
         shader_binding_table: &'a ShaderBindingTable,
     },
+
+    PushLabel {
+        name: CString,
+    },
+
+    PopLabel,
+
+    InsertLabel {
+        name: CString,
+    },
+
+    WriteTimestamp {
+        stage: vk::PipelineStageFlagBits,
+        pool: QueryPool,
+        query: u32,
+    },
+
+    BeginQuery {
+        pool: QueryPool,
+        query: u32,
+    },
+
+    EndQuery {
+        pool: QueryPool,
+        query: u32,
+    },
+
+    CopyBuffer {
+        src: &'a Buffer,
+        dst: &'a Buffer,
+        regions: &'a [vk::BufferCopy],
+    },
+
+    CopyBufferToImage {
+        src: &'a Buffer,
+        dst: &'a Image,
+        layout: vk::ImageLayout,
+        regions: &'a [vk::BufferImageCopy],
+    },
+
+    PipelineBarrier {
+        src: vk::PipelineStageFlags,
+        dst: vk::PipelineStageFlags,
+        images: &'a [ImageMemoryBarrier<'a>],
+        buffers: &'a [BufferMemoryBarrier<'a>],
+    },
 }
@@ -0,0 +1,274 @@
+use erupt::vk;
+
+/// Static layout information about a format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FormatDescription {
+    /// Bytes per texel, or per block for compressed formats.
+    pub block_size: u32,
+    /// Texels covered by one block, `(1, 1)` for uncompressed formats.
+    pub block_extent: (u32, u32),
+    pub aspect: vk::ImageAspectFlags,
+    /// The same layout with the other color encoding, linear for sRGB
+    /// formats and sRGB for linear ones.
+    pub srgb_pair: Option<vk::Format>,
+    pub srgb: bool,
+}
+
+impl FormatDescription {
+    const fn color(block_size: u32) -> Self {
+        FormatDescription {
+            block_size,
+            block_extent: (1, 1),
+            aspect: vk::ImageAspectFlags::COLOR,
+            srgb_pair: None,
+            srgb: false,
+        }
+    }
+
+    const fn depth_stencil(block_size: u32, aspect: vk::ImageAspectFlags) -> Self {
+        FormatDescription {
+            block_size,
+            block_extent: (1, 1),
+            aspect,
+            srgb_pair: None,
+            srgb: false,
+        }
+    }
+
+    const fn compressed(block_size: u32) -> Self {
+        FormatDescription {
+            block_size,
+            block_extent: (4, 4),
+            aspect: vk::ImageAspectFlags::COLOR,
+            srgb_pair: None,
+            srgb: false,
+        }
+    }
+
+    const fn linear(mut self, srgb: vk::Format) -> Self {
+        self.srgb_pair = Some(srgb);
+        self
+    }
+
+    const fn srgb(mut self, linear: vk::Format) -> Self {
+        self.srgb_pair = Some(linear);
+        self.srgb = true;
+        self
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.block_extent != (1, 1)
+    }
+
+    pub fn is_depth(&self) -> bool {
+        self.aspect.contains(vk::ImageAspectFlags::DEPTH)
+    }
+
+    pub fn is_stencil(&self) -> bool {
+        self.aspect.contains(vk::ImageAspectFlags::STENCIL)
+    }
+
+    /// Bytes needed for a tightly packed `width` x `height` region.
+    pub fn region_size(&self, width: u32, height: u32) -> u64 {
+        let (block_width, block_height) = self.block_extent;
+        let blocks_wide = (width + block_width - 1) / block_width;
+        let blocks_high = (height + block_height - 1) / block_height;
+        blocks_wide as u64 * blocks_high as u64 * self.block_size as u64
+    }
+}
+
+/// Describes the formats the renderer knows about, `None` for the rest.
+pub fn describe(format: vk::Format) -> Option<FormatDescription> {
+    use vk::Format as F;
+
+    let depth = vk::ImageAspectFlags::DEPTH;
+    let stencil = vk::ImageAspectFlags::STENCIL;
+
+    let description = match format {
+        F::R8_UNORM | F::R8_SNORM | F::R8_UINT | F::R8_SINT => FormatDescription::color(1),
+        F::R8_SRGB => FormatDescription::color(1).srgb(F::R8_UNORM),
+        F::R8G8_UNORM | F::R8G8_SNORM | F::R8G8_UINT | F::R8G8_SINT => FormatDescription::color(2),
+        F::R8G8B8A8_UNORM => FormatDescription::color(4).linear(F::R8G8B8A8_SRGB),
+        F::R8G8B8A8_SRGB => FormatDescription::color(4).srgb(F::R8G8B8A8_UNORM),
+        F::B8G8R8A8_UNORM => FormatDescription::color(4).linear(F::B8G8R8A8_SRGB),
+        F::B8G8R8A8_SRGB => FormatDescription::color(4).srgb(F::B8G8R8A8_UNORM),
+        F::R8G8B8A8_SNORM | F::R8G8B8A8_UINT | F::R8G8B8A8_SINT => FormatDescription::color(4),
+        F::A2B10G10R10_UNORM_PACK32 | F::A2R10G10B10_UNORM_PACK32 => FormatDescription::color(4),
+        F::B10G11R11_UFLOAT_PACK32 | F::E5B9G9R9_UFLOAT_PACK32 => FormatDescription::color(4),
+        F::R16_UNORM | F::R16_UINT | F::R16_SINT | F::R16_SFLOAT => FormatDescription::color(2),
+        F::R16G16_UNORM | F::R16G16_UINT | F::R16G16_SINT | F::R16G16_SFLOAT => {
+            FormatDescription::color(4)
+        }
+        F::R16G16B16A16_UNORM
+        | F::R16G16B16A16_UINT
+        | F::R16G16B16A16_SINT
+        | F::R16G16B16A16_SFLOAT => FormatDescription::color(8),
+        F::R32_UINT | F::R32_SINT | F::R32_SFLOAT => FormatDescription::color(4),
+        F::R32G32_UINT | F::R32G32_SINT | F::R32G32_SFLOAT => FormatDescription::color(8),
+        F::R32G32B32_UINT | F::R32G32B32_SINT | F::R32G32B32_SFLOAT => FormatDescription::color(12),
+        F::R32G32B32A32_UINT | F::R32G32B32A32_SINT | F::R32G32B32A32_SFLOAT => {
+            FormatDescription::color(16)
+        }
+        F::D16_UNORM => FormatDescription::depth_stencil(2, depth),
+        F::X8_D24_UNORM_PACK32 | F::D32_SFLOAT => FormatDescription::depth_stencil(4, depth),
+        F::S8_UINT => FormatDescription::depth_stencil(1, stencil),
+        F::D16_UNORM_S8_UINT => FormatDescription::depth_stencil(3, depth | stencil),
+        F::D24_UNORM_S8_UINT => FormatDescription::depth_stencil(4, depth | stencil),
+        F::D32_SFLOAT_S8_UINT => FormatDescription::depth_stencil(5, depth | stencil),
+        F::BC1_RGBA_UNORM_BLOCK => FormatDescription::compressed(8).linear(F::BC1_RGBA_SRGB_BLOCK),
+        F::BC1_RGBA_SRGB_BLOCK => FormatDescription::compressed(8).srgb(F::BC1_RGBA_UNORM_BLOCK),
+        F::BC3_UNORM_BLOCK => FormatDescription::compressed(16).linear(F::BC3_SRGB_BLOCK),
+        F::BC3_SRGB_BLOCK => FormatDescription::compressed(16).srgb(F::BC3_UNORM_BLOCK),
+        F::BC4_UNORM_BLOCK | F::BC4_SNORM_BLOCK => FormatDescription::compressed(8),
+        F::BC5_UNORM_BLOCK | F::BC5_SNORM_BLOCK => FormatDescription::compressed(16),
+        F::BC6H_UFLOAT_BLOCK | F::BC6H_SFLOAT_BLOCK => FormatDescription::compressed(16),
+        F::BC7_UNORM_BLOCK => FormatDescription::compressed(16).linear(F::BC7_SRGB_BLOCK),
+        F::BC7_SRGB_BLOCK => FormatDescription::compressed(16).srgb(F::BC7_UNORM_BLOCK),
+        _ => return None,
+    };
+
+    Some(description)
+}
+
+/// Image aspects of `format`, assuming color for unknown formats.
+pub fn aspect_flags(format: vk::Format) -> vk::ImageAspectFlags {
+    describe(format).map_or(vk::ImageAspectFlags::COLOR, |description| {
+        description.aspect
+    })
+}
+
+pub fn is_srgb(format: vk::Format) -> bool {
+    describe(format).map_or(false, |description| description.srgb)
+}
+
+/// Format features an optimally tiled image needs for `usage`.
+pub fn required_features(usage: vk::ImageUsageFlags) -> vk::FormatFeatureFlags {
+    let mut features = vk::FormatFeatureFlags::empty();
+
+    if usage.contains(vk::ImageUsageFlags::SAMPLED) {
+        features |= vk::FormatFeatureFlags::SAMPLED_IMAGE;
+    }
+    if usage.contains(vk::ImageUsageFlags::STORAGE) {
+        features |= vk::FormatFeatureFlags::STORAGE_IMAGE;
+    }
+    if usage.contains(vk::ImageUsageFlags::COLOR_ATTACHMENT) {
+        features |= vk::FormatFeatureFlags::COLOR_ATTACHMENT;
+    }
+    if usage.contains(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT) {
+        features |= vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT;
+    }
+    if usage.contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+        features |= vk::FormatFeatureFlags::TRANSFER_SRC;
+    }
+    if usage.contains(vk::ImageUsageFlags::TRANSFER_DST) {
+        features |= vk::FormatFeatureFlags::TRANSFER_DST;
+    }
+
+    features
+}
+
+pub const DEPTH_FORMATS: &[vk::Format] = &[
+    vk::Format::D32_SFLOAT,
+    vk::Format::X8_D24_UNORM_PACK32,
+    vk::Format::D16_UNORM,
+];
+
+pub const DEPTH_STENCIL_FORMATS: &[vk::Format] = &[
+    vk::Format::D32_SFLOAT_S8_UINT,
+    vk::Format::D24_UNORM_S8_UINT,
+    vk::Format::D16_UNORM_S8_UINT,
+];
+
+/// Half float first, then packed and full float formats.
+pub const HDR_FORMATS: &[vk::Format] = &[
+    vk::Format::R16G16B16A16_SFLOAT,
+    vk::Format::B10G11R11_UFLOAT_PACK32,
+    vk::Format::R32G32B32A32_SFLOAT,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_pairs_srgb_formats() {
+        let unorm = describe(vk::Format::R8G8B8A8_UNORM).unwrap();
+        let srgb = describe(vk::Format::R8G8B8A8_SRGB).unwrap();
+        assert_eq!(unorm.srgb_pair, Some(vk::Format::R8G8B8A8_SRGB));
+        assert_eq!(srgb.srgb_pair, Some(vk::Format::R8G8B8A8_UNORM));
+        assert!(!unorm.srgb && srgb.srgb);
+        assert_eq!(unorm.block_size, srgb.block_size);
+
+        let bc7 = describe(vk::Format::BC7_SRGB_BLOCK).unwrap();
+        assert!(bc7.is_compressed() && bc7.srgb);
+        assert_eq!(bc7.srgb_pair, Some(vk::Format::BC7_UNORM_BLOCK));
+
+        assert_eq!(describe(vk::Format::UNDEFINED), None);
+    }
+
+    #[test]
+    fn describe_depth_stencil_aspects() {
+        let d32 = describe(vk::Format::D32_SFLOAT).unwrap();
+        assert!(d32.is_depth() && !d32.is_stencil());
+
+        let s8 = describe(vk::Format::S8_UINT).unwrap();
+        assert!(!s8.is_depth() && s8.is_stencil());
+
+        let d24s8 = describe(vk::Format::D24_UNORM_S8_UINT).unwrap();
+        assert!(d24s8.is_depth() && d24s8.is_stencil());
+
+        assert_eq!(
+            aspect_flags(vk::Format::UNDEFINED),
+            vk::ImageAspectFlags::COLOR
+        );
+    }
+
+    #[test]
+    fn region_size_of_uncompressed_formats() {
+        let rgba8 = describe(vk::Format::R8G8B8A8_UNORM).unwrap();
+        assert_eq!(rgba8.region_size(4, 4), 64);
+        assert_eq!(rgba8.region_size(3, 5), 60);
+        assert_eq!(rgba8.region_size(1, 1), 4);
+
+        let rgb32 = describe(vk::Format::R32G32B32_SFLOAT).unwrap();
+        assert_eq!(rgb32.region_size(7, 1), 84);
+    }
+
+    #[test]
+    fn region_size_rounds_up_to_whole_blocks() {
+        let bc1 = describe(vk::Format::BC1_RGBA_UNORM_BLOCK).unwrap();
+        assert_eq!(bc1.region_size(4, 4), 8);
+        assert_eq!(bc1.region_size(1, 1), 8);
+        assert_eq!(bc1.region_size(5, 3), 16);
+
+        let bc7 = describe(vk::Format::BC7_UNORM_BLOCK).unwrap();
+        assert_eq!(bc7.region_size(16, 16), 256);
+        assert_eq!(bc7.region_size(17, 9), 5 * 3 * 16);
+    }
+
+    #[test]
+    fn required_features_follow_usage() {
+        assert_eq!(
+            required_features(vk::ImageUsageFlags::empty()),
+            vk::FormatFeatureFlags::empty()
+        );
+        assert_eq!(
+            required_features(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST),
+            vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::TRANSFER_DST
+        );
+        assert_eq!(
+            required_features(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT),
+            vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT
+        );
+        assert_eq!(
+            required_features(
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::STORAGE
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+            ),
+            vk::FormatFeatureFlags::COLOR_ATTACHMENT
+                | vk::FormatFeatureFlags::STORAGE_IMAGE
+                | vk::FormatFeatureFlags::TRANSFER_SRC
+        );
+    }
+}
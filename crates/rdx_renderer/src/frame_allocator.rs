@@ -0,0 +1,240 @@
+use crate::buffer::BufferInfo;
+use crate::device::Device;
+use crate::physical_device::PhysicalDeviceInfo;
+use crate::resources::{Buffer, MappableBuffer};
+use crate::timeline::TimelinePoint;
+use crate::util::align_up;
+use crevice::internal::bytemuck::{self, Pod};
+use erupt::vk;
+use gpu_alloc::{MemoryPropertyFlags, UsageFlags};
+use gpu_alloc_erupt::EruptMemoryDevice;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::ptr::NonNull;
+
+pub const DEFAULT_FRAME_ALLOCATOR_SIZE: u64 = 4 * 1024 * 1024;
+
+/// A sub-range of the frame allocator's buffer. Bind `buffer` once as a
+/// dynamic descriptor and pass `offset` as its dynamic offset.
+#[derive(Clone)]
+pub struct DynamicAllocation {
+    pub buffer: Buffer,
+    pub offset: u32,
+    pub size: u64,
+}
+
+struct FrameEnd {
+    point: TimelinePoint,
+    head: u64,
+}
+
+/// Bump allocator over a persistently mapped host-visible buffer for data
+/// written once per frame, such as camera, object and material constants, or
+/// top level acceleration structure instances.
+///
+/// Allocations are aligned for dynamic uniform and storage buffer offsets,
+/// and to 16 bytes for acceleration structure build inputs.
+/// Call `flush` before submitting work reading them. The buffer is used as a
+/// ring: `end_frame` records where the frame's data ends, and that space is
+/// only reused once the GPU reaches the frame's timeline point.
+pub struct FrameAllocator {
+    device: Device,
+    buffer: MappableBuffer,
+    mapping: NonNull<u8>,
+    coherent: bool,
+    size: u64,
+    align_mask: u64,
+    head: u64,
+    tail: u64,
+    frame_start: u64,
+    frames: VecDeque<FrameEnd>,
+    /// Ranges written since the last `flush`, when the memory isn't coherent.
+    unflushed: Vec<Range<u64>>,
+}
+
+unsafe impl Send for FrameAllocator {}
+unsafe impl Sync for FrameAllocator {}
+
+impl FrameAllocator {
+    pub fn new(device: &Device, info: &PhysicalDeviceInfo, size: u64) -> Self {
+        let limits = &info.device_properties.limits;
+        let align_mask = (limits
+            .min_uniform_buffer_offset_alignment
+            .max(limits.min_storage_buffer_offset_alignment)
+            - 1)
+            | 15;
+
+        let mut buffer = device.create_buffer(
+            BufferInfo {
+                align: align_mask,
+                size,
+                usage_flags: vk::BufferUsageFlags::UNIFORM_BUFFER
+                    | vk::BufferUsageFlags::STORAGE_BUFFER
+                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                    | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
+                allocation_flags: UsageFlags::UPLOAD | UsageFlags::DEVICE_ADDRESS,
+                name: Some("frame allocator".into()),
+            },
+            UsageFlags::UPLOAD | UsageFlags::HOST_ACCESS | UsageFlags::DEVICE_ADDRESS,
+        );
+
+        let (mapping, coherent) = unsafe {
+            let memory_block = buffer.memory_block();
+            let coherent = memory_block
+                .props()
+                .contains(MemoryPropertyFlags::HOST_COHERENT);
+            let mapping = memory_block
+                .map(EruptMemoryDevice::wrap(device.handle()), 0, size as usize)
+                .expect("Mapping frame allocator failed");
+            (mapping, coherent)
+        };
+
+        FrameAllocator {
+            device: device.clone(),
+            buffer,
+            mapping,
+            coherent,
+            size,
+            align_mask,
+            head: 0,
+            tail: 0,
+            frame_start: 0,
+            frames: VecDeque::new(),
+            unflushed: Vec::new(),
+        }
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn write<T>(&mut self, value: &T) -> DynamicAllocation
+    where
+        T: Pod,
+    {
+        self.write_slice(std::slice::from_ref(value))
+    }
+
+    pub fn write_slice<T>(&mut self, data: &[T]) -> DynamicAllocation
+    where
+        T: Pod,
+    {
+        let bytes = bytemuck::cast_slice::<_, u8>(data);
+        let offset = self.allocate(bytes.len() as u64);
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                self.mapping.as_ptr().add(offset as usize),
+                bytes.len(),
+            );
+        }
+
+        if !self.coherent {
+            let end = offset + bytes.len() as u64;
+            match self.unflushed.last_mut() {
+                Some(range) if range.end == offset => range.end = end,
+                _ => self.unflushed.push(offset..end),
+            }
+        }
+
+        DynamicAllocation {
+            buffer: (*self.buffer).clone(),
+            offset: offset as u32,
+            size: bytes.len() as u64,
+        }
+    }
+
+    /// Makes the writes since the last call visible to the device.
+    pub fn flush(&mut self) {
+        for range in self.unflushed.drain(..) {
+            unsafe {
+                self.device
+                    .flush_mapped_range(self.buffer.memory_block(), range);
+            }
+        }
+    }
+
+    /// Marks the end of the current frame's allocations. `point` must be
+    /// reached only after every submission reading them.
+    pub fn end_frame(&mut self, point: TimelinePoint) {
+        debug_assert!(self.unflushed.is_empty(), "frame allocations not flushed");
+
+        self.frames.push_back(FrameEnd {
+            point,
+            head: self.head,
+        });
+        self.frame_start = self.head;
+    }
+
+    fn allocate(&mut self, size: u64) -> u64 {
+        assert!(
+            size <= self.size,
+            "allocation of {} bytes does not fit the {} byte frame allocator",
+            size,
+            self.size
+        );
+
+        loop {
+            self.release_completed();
+
+            if let Some(offset) = self.try_allocate(size) {
+                return offset;
+            }
+
+            match self.frames.front() {
+                Some(frame) => {
+                    self.device.wait_timeline(&[frame.point.clone()], !0);
+                }
+                None => panic!("frame allocator exhausted within a single frame"),
+            }
+        }
+    }
+
+    fn try_allocate(&mut self, size: u64) -> Option<u64> {
+        if self.frames.is_empty() && self.head == self.frame_start && self.head == self.tail {
+            self.head = 0;
+            self.tail = 0;
+            self.frame_start = 0;
+        }
+
+        let start = align_up(self.align_mask, self.head)?;
+        let offset = if self.head >= self.tail {
+            if start + size <= self.size {
+                start
+            } else if size < self.tail {
+                0
+            } else {
+                return None;
+            }
+        } else if start + size < self.tail {
+            start
+        } else {
+            return None;
+        };
+
+        self.head = offset + size;
+        Some(offset)
+    }
+
+    fn release_completed(&mut self) {
+        while let Some(frame) = self.frames.front() {
+            if self.device.semaphore_value(&frame.point.semaphore) < frame.point.value {
+                break;
+            }
+
+            self.tail = frame.head;
+            self.frames.pop_front();
+        }
+    }
+
+    /// Destroys the buffer, once the device is idle.
+    pub fn cleanup(&mut self) {
+        unsafe {
+            self.buffer
+                .memory_block()
+                .unmap(EruptMemoryDevice::wrap(self.device.handle()));
+        }
+        self.device.destroy_buffer(&self.buffer);
+    }
+}
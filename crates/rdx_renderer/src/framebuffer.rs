@@ -9,4 +9,5 @@ pub struct FramebufferInfo {
     pub render_pass: RenderPass,
     pub views: SmallVec<[ImageView; DEFAULT_ATTACHMENT_COUNT]>,
     pub extent: vk::Extent2D,
+    pub name: Option<Box<str>>,
 }
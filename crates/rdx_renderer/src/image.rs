@@ -1,16 +1,112 @@
 use erupt::vk;
 use gpu_alloc::MemoryBlock;
+use parking_lot::Mutex;
 use std::hash::{Hash, Hasher};
 use std::ops::Range;
 use std::sync::Arc;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ImageExtent {
+    D1 { width: u32 },
+    D2 { width: u32, height: u32 },
+    D3 { width: u32, height: u32, depth: u32 },
+}
+
+impl ImageExtent {
+    pub fn image_type(self) -> vk::ImageType {
+        match self {
+            ImageExtent::D1 { .. } => vk::ImageType::_1D,
+            ImageExtent::D2 { .. } => vk::ImageType::_2D,
+            ImageExtent::D3 { .. } => vk::ImageType::_3D,
+        }
+    }
+
+    pub fn into_2d(self) -> vk::Extent2D {
+        match self {
+            ImageExtent::D1 { width } => vk::Extent2D { width, height: 1 },
+            ImageExtent::D2 { width, height } | ImageExtent::D3 { width, height, .. } => {
+                vk::Extent2D { width, height }
+            }
+        }
+    }
+
+    pub fn into_3d(self) -> vk::Extent3D {
+        match self {
+            ImageExtent::D1 { width } => vk::Extent3D {
+                width,
+                height: 1,
+                depth: 1,
+            },
+            ImageExtent::D2 { width, height } => vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            },
+            ImageExtent::D3 {
+                width,
+                height,
+                depth,
+            } => vk::Extent3D {
+                width,
+                height,
+                depth,
+            },
+        }
+    }
+}
+
+impl From<vk::Extent2D> for ImageExtent {
+    fn from(extent: vk::Extent2D) -> Self {
+        ImageExtent::D2 {
+            width: extent.width,
+            height: extent.height,
+        }
+    }
+}
+
+/// A depth of 1 is taken as a 2D extent.
+impl From<vk::Extent3D> for ImageExtent {
+    fn from(extent: vk::Extent3D) -> Self {
+        match extent.depth {
+            1 => ImageExtent::D2 {
+                width: extent.width,
+                height: extent.height,
+            },
+            depth => ImageExtent::D3 {
+                width: extent.width,
+                height: extent.height,
+                depth,
+            },
+        }
+    }
+}
+
 pub struct ImageInfo {
-    pub extent: vk::Extent2D,
+    pub extent: ImageExtent,
     pub format: vk::Format,
     pub mip_levels: u32,
     pub array_layers: u32,
     pub samples: vk::SampleCountFlagBits,
     pub usage: vk::ImageUsageFlags,
+    /// Allows cube and cube array views; needs a square 2D extent and a
+    /// multiple of 6 array layers.
+    pub cube_compatible: bool,
+    /// Allows views in the `srgb_pair` of the format as well.
+    pub mutable_format: bool,
+    pub name: Option<Box<str>>,
+}
+
+impl ImageInfo {
+    pub fn is_valid(&self) -> bool {
+        let cube_valid = !self.cube_compatible
+            || match self.extent {
+                ImageExtent::D2 { width, height } => width == height && self.array_layers % 6 == 0,
+                _ => false,
+            };
+        let layers_valid = self.array_layers == 1 || self.extent.image_type() != vk::ImageType::_3D;
+
+        cube_valid && layers_valid && self.mip_levels > 0 && self.array_layers > 0
+    }
 }
 
 #[derive(Clone)]
@@ -38,6 +134,18 @@ impl ImageSubresourceRange {
     }
 }
 
+impl From<&ImageSubresourceRange> for vk::ImageSubresourceRange {
+    fn from(range: &ImageSubresourceRange) -> Self {
+        vk::ImageSubresourceRange {
+            aspect_mask: range.aspect,
+            base_mip_level: range.first_level,
+            level_count: range.level_count,
+            base_array_layer: range.first_layer,
+            layer_count: range.layer_count,
+        }
+    }
+}
+
 pub struct ImageSubresourceLayers {
     pub aspect: vk::ImageAspectFlags,
     pub level: u32,
@@ -69,30 +177,101 @@ pub struct ImageMemoryBarrier<'a> {
 #[derive(Clone)]
 pub struct ImageViewInfo {
     pub view_type: vk::ImageViewType,
+    /// The image format, or its `srgb_pair` for `mutable_format` images.
+    pub format: vk::Format,
     pub subresource: ImageSubresourceRange,
     pub image: Image,
+    pub name: Option<Box<str>>,
 }
 
 impl ImageViewInfo {
+    /// A view of every mip level and layer, typed after the image: arrayed
+    /// when it has more than one layer.
     pub fn new(image: Image, image_aspect_flags: vk::ImageAspectFlags) -> Self {
         let info = image.info();
+        let arrayed = info.array_layers > 1;
+
+        let view_type = match (info.extent, arrayed) {
+            (ImageExtent::D1 { .. }, false) => vk::ImageViewType::_1D,
+            (ImageExtent::D1 { .. }, true) => vk::ImageViewType::_1D_ARRAY,
+            (ImageExtent::D2 { .. }, false) => vk::ImageViewType::_2D,
+            (ImageExtent::D2 { .. }, true) => vk::ImageViewType::_2D_ARRAY,
+            (ImageExtent::D3 { .. }, _) => vk::ImageViewType::_3D,
+        };
 
         ImageViewInfo {
-            view_type: vk::ImageViewType::_2D,
+            view_type,
+            format: info.format,
             subresource: ImageSubresourceRange::new(
                 image_aspect_flags,
-                0..info.array_layers,
+                0..info.mip_levels,
                 0..info.array_layers,
             ),
             image,
+            name: None,
+        }
+    }
+
+    /// A cube view of a cube-compatible image, or a cube array view when it
+    /// has more than 6 layers, which needs the `imageCubeArray` feature.
+    pub fn cube(image: Image, image_aspect_flags: vk::ImageAspectFlags) -> Self {
+        let info = image.info();
+        assert!(
+            info.cube_compatible,
+            "cube view of an image that is not cube compatible"
+        );
+
+        ImageViewInfo {
+            view_type: if info.array_layers > 6 {
+                vk::ImageViewType::CUBE_ARRAY
+            } else {
+                vk::ImageViewType::CUBE
+            },
+            ..ImageViewInfo::new(image, image_aspect_flags)
+        }
+    }
+
+    /// A 2D array view of `layers`, every mip level included.
+    pub fn layers(
+        image: Image,
+        image_aspect_flags: vk::ImageAspectFlags,
+        layers: Range<u32>,
+    ) -> Self {
+        assert!(layers.end <= image.info().array_layers);
+
+        ImageViewInfo {
+            view_type: vk::ImageViewType::_2D_ARRAY,
+            subresource: ImageSubresourceRange::new(
+                image_aspect_flags,
+                0..image.info().mip_levels,
+                layers,
+            ),
+            ..ImageViewInfo::new(image, image_aspect_flags)
+        }
+    }
+
+    /// A 2D view of a single layer, such as one shadow cascade or cube face.
+    pub fn layer(image: Image, image_aspect_flags: vk::ImageAspectFlags, layer: u32) -> Self {
+        ImageViewInfo {
+            view_type: vk::ImageViewType::_2D,
+            ..ImageViewInfo::layers(image, image_aspect_flags, layer..layer + 1)
         }
     }
+
+    /// Restricts the view to `levels`.
+    pub fn with_mip_levels(mut self, levels: Range<u32>) -> Self {
+        assert!(levels.end <= self.image.info().mip_levels);
+        self.subresource.first_level = levels.start;
+        self.subresource.level_count = levels.end - levels.start;
+        self
+    }
 }
 
 struct ImageInner {
     info: ImageInfo,
     handle: vk::Image,
-    memory_block: Option<MemoryBlock<vk::DeviceMemory>>,
+    /// `None` for swapchain images, and once taken by `Device::destroy_image`.
+    memory_block: Mutex<Option<MemoryBlock<vk::DeviceMemory>>>,
 }
 
 #[derive(Clone)]
@@ -127,7 +306,7 @@ impl Image {
             inner: Arc::new(ImageInner {
                 info,
                 handle,
-                memory_block,
+                memory_block: Mutex::new(memory_block),
             }),
         }
     }
@@ -138,6 +317,12 @@ impl Image {
     pub fn handle(&self) -> vk::Image {
         self.inner.handle
     }
+
+    /// Takes the image's memory to free it, `None` for swapchain images and
+    /// once already taken.
+    pub fn take_memory_block(&self) -> Option<MemoryBlock<vk::DeviceMemory>> {
+        self.inner.memory_block.lock().take()
+    }
 }
 
 #[derive(Clone)]
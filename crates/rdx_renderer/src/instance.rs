@@ -1,10 +1,14 @@
-use crate::debug::VALIDATION_LAYER;
+use crate::debug::{ValidationSettings, VALIDATION_LAYER};
 use erupt::utils::surface;
-use erupt::{vk, EntryLoader, InstanceLoader};
-use std::ffi::CString;
+use erupt::{vk, EntryLoader, ExtendableFromConst, ExtendableFromMut, InstanceLoader};
+use std::ffi::{CStr, CString};
 use winit::window::Window;
 
-pub fn create_instance(window: &Window, entry: &EntryLoader) -> InstanceLoader {
+pub fn create_instance(
+    window: &Window,
+    entry: &EntryLoader,
+    settings: &mut ValidationSettings,
+) -> InstanceLoader {
     let app_name = CString::new("RDX").unwrap();
     let engine_name = CString::new("Vulkan Engine").unwrap();
     let app_info = vk::ApplicationInfoBuilder::new()
@@ -14,10 +18,20 @@ pub fn create_instance(window: &Window, entry: &EntryLoader) -> InstanceLoader {
         .engine_version(vk::make_api_version(0, 1, 0, 0))
         .engine_name(&engine_name);
 
+    if settings.validation && !validation_layer_available(entry) {
+        tracing::warn!("validation requested but VK_LAYER_KHRONOS_validation is not installed");
+        settings.validation = false;
+    }
+
+    let validation_features = settings.validation_features();
+
     let mut instance_extensions = surface::enumerate_required_extensions(window).unwrap();
-    if cfg!(debug_assertions) {
+    if settings.debug_utils_enabled() {
         instance_extensions.push(vk::EXT_DEBUG_UTILS_EXTENSION_NAME);
     }
+    if !validation_features.is_empty() {
+        instance_extensions.push(vk::EXT_VALIDATION_FEATURES_EXTENSION_NAME);
+    }
 
     #[cfg(target_os = "windows")]
     {
@@ -25,14 +39,30 @@ pub fn create_instance(window: &Window, entry: &EntryLoader) -> InstanceLoader {
     }
 
     let mut instance_layers = Vec::new();
-    if cfg!(debug_assertions) {
+    if settings.validation {
         instance_layers.push(VALIDATION_LAYER);
     }
 
-    let instance_info = vk::InstanceCreateInfoBuilder::new()
+    let mut validation_features_info =
+        vk::ValidationFeaturesEXTBuilder::new().enabled_validation_features(&validation_features);
+
+    let mut instance_info = vk::InstanceCreateInfoBuilder::new()
         .application_info(&app_info)
         .enabled_extension_names(&instance_extensions)
         .enabled_layer_names(&instance_layers);
 
+    if !validation_features.is_empty() {
+        instance_info = instance_info.extend_from(&mut validation_features_info);
+    }
+
     unsafe { InstanceLoader::new(&entry, &instance_info, None).unwrap() }
 }
+
+fn validation_layer_available(entry: &EntryLoader) -> bool {
+    let layers = unsafe { entry.enumerate_instance_layer_properties(None).unwrap() };
+    let validation_layer = unsafe { CStr::from_ptr(VALIDATION_LAYER) };
+
+    layers
+        .iter()
+        .any(|layer| unsafe { CStr::from_ptr(layer.layer_name.as_ptr()) } == validation_layer)
+}
@@ -1,40 +1,115 @@
 use bevy::app::AppExit;
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics};
 use bevy::prelude::*;
+use bevy::tasks::ComputeTaskPool;
+use bevy::transform::TransformSystem;
 use bevy::window::{WindowCreated, WindowResized};
 use bevy::winit::WinitWindows;
 
 use crate::renderer::Renderer;
 
+pub use crate::renderer::{DeviceLost, InjectDeviceLost};
+
+pub use crate::assets::AssetSettings;
+pub use crate::buffer::{DeviceAddress, GpuPtr, GpuSlice};
+pub use crate::camera::{Camera, CameraUniform, Projection};
+pub use crate::camera_controller::{
+    CameraControllerPlugin, FlyController, OrbitController, ViewChanged,
+};
+pub use crate::debug::{MessageSeverity, ValidationErrors, ValidationSettings};
+pub use crate::format::{describe as describe_format, FormatDescription};
+pub use crate::light::Light;
+pub use crate::loader::{GltfBuffer, GltfBufferLoader, GltfLoader, ObjLoader, TextureLoader};
+pub use crate::material::{AlphaMode, Material, NO_TEXTURE};
+pub use crate::memory::{MemoryCategory, MemoryReport, MemorySettings};
+pub use crate::mesh::{Mesh, Vertex};
+pub use crate::scene::Visible;
+pub use crate::texture::Texture;
+pub use crate::typed_buffer::{StorageBuffer, StorageVec, UniformBuffer};
+
 mod acceleration_structures;
+mod assets;
 mod buffer;
+mod camera;
+mod camera_controller;
 mod command_buffer;
+mod command_pool;
 mod debug;
 mod descriptor;
 mod device;
 mod encoder;
+mod format;
+mod frame_allocator;
 mod framebuffer;
 mod image;
 mod instance;
+mod light;
+mod loader;
+mod material;
+mod memory;
+mod mesh;
 mod physical_device;
 mod pipeline;
+mod profiler;
+mod query;
 mod queue;
 mod render_context;
 mod render_pass;
 mod renderer;
 mod resources;
+mod sampler;
+mod scene;
 mod shader;
 mod surface;
 mod swapchain;
+mod texture;
+mod timeline;
+mod typed_buffer;
+mod upload;
 mod util;
 
 #[derive(Default)]
 pub struct RenderPlugin;
 
+/// Labels of the systems in `CoreStage::PostUpdate` reading the world for
+/// rendering, both after transform propagation.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, SystemLabel)]
+pub enum RenderSystem {
+    ExtractScene,
+    Draw,
+}
+
 impl Plugin for RenderPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system_to_stage(StartupStage::PreStartup, setup.system())
+        app.add_event::<DeviceLost>()
+            .add_event::<InjectDeviceLost>()
+            .add_asset::<Mesh>()
+            .add_asset::<Material>()
+            .add_asset::<Texture>()
+            .add_asset::<GltfBuffer>()
+            .register_type::<Handle<Mesh>>()
+            .register_type::<Handle<Material>>()
+            .init_asset_loader::<TextureLoader>()
+            .init_asset_loader::<ObjLoader>()
+            .init_asset_loader::<GltfLoader>()
+            .init_asset_loader::<GltfBufferLoader>()
+            .add_startup_system_to_stage(StartupStage::PreStartup, setup.system())
             .add_system_to_stage(CoreStage::PreUpdate, window_resize.system())
-            .add_system_to_stage(CoreStage::Update, draw.system())
+            .add_system_to_stage(CoreStage::PreUpdate, loader::reload_gltf_buffers.system())
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                scene::extract_scene
+                    .system()
+                    .label(RenderSystem::ExtractScene)
+                    .after(TransformSystem::TransformPropagate),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                draw.system()
+                    .label(RenderSystem::Draw)
+                    .after(RenderSystem::ExtractScene),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, gpu_diagnostics.system())
             .add_system_to_stage(CoreStage::Last, world_cleanup.system());
     }
 }
@@ -43,7 +118,15 @@ fn setup(
     mut commands: Commands,
     mut window_created_events: EventReader<WindowCreated>,
     winit_windows: Res<WinitWindows>,
+    asset_settings: Option<Res<AssetSettings>>,
+    validation_settings: Option<Res<ValidationSettings>>,
+    memory_settings: Option<Res<MemorySettings>>,
+    task_pool: Res<ComputeTaskPool>,
 ) {
+    if let Some(asset_settings) = asset_settings {
+        assets::configure((*asset_settings).clone());
+    }
+
     let window_id = window_created_events
         .iter()
         .next()
@@ -51,13 +134,89 @@ fn setup(
         .unwrap();
 
     let winit_window = winit_windows.get_window(window_id).unwrap();
-    let renderer = Renderer::new(winit_window);
+    let validation_settings = validation_settings
+        .map(|settings| (*settings).clone())
+        .unwrap_or_default();
+    let memory_settings = memory_settings
+        .map(|settings| (*settings).clone())
+        .unwrap_or_default();
+    let renderer = Renderer::new(
+        winit_window,
+        validation_settings,
+        memory_settings,
+        (*task_pool).clone(),
+    );
 
+    commands.insert_resource(renderer.validation_errors().clone());
     commands.insert_resource(renderer);
 }
 
-fn draw(mut renderer: ResMut<Renderer>) {
-    renderer.draw()
+fn draw(
+    mut renderer: ResMut<Renderer>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut device_lost_events: EventWriter<DeviceLost>,
+    mut inject_device_lost_events: EventReader<InjectDeviceLost>,
+) {
+    renderer.set_camera(cameras.iter().next());
+
+    if inject_device_lost_events.iter().count() > 0 {
+        renderer.inject_device_lost();
+    }
+
+    let generation = renderer.generation();
+    renderer.draw();
+
+    if renderer.generation() != generation {
+        device_lost_events.send(DeviceLost {
+            generation: renderer.generation(),
+        });
+    }
+}
+
+/// Publishes the GPU profiler timings, one diagnostic per scope and statistic,
+/// and the memory used per category.
+fn gpu_diagnostics(renderer: Res<Renderer>, diagnostics: Option<ResMut<Diagnostics>>) {
+    let mut diagnostics = match diagnostics {
+        Some(diagnostics) => diagnostics,
+        None => return,
+    };
+
+    let mut measure = |id: u128, name: String, suffix: &'static str, value: f64| {
+        let id = DiagnosticId::from_u128(id);
+
+        if diagnostics.get(id).is_none() {
+            diagnostics.add(Diagnostic::new(id, name, 20).with_suffix(suffix));
+        }
+        diagnostics.add_measurement(id, value);
+    };
+
+    for timing in renderer.gpu_timings() {
+        let scope = &timing.scope;
+        measure(
+            scope.diagnostic_id,
+            format!("gpu {}", scope.name),
+            "ms",
+            timing.milliseconds,
+        );
+
+        for (i, (statistic, value)) in timing.statistics.iter().enumerate() {
+            measure(
+                scope.diagnostic_id + 1 + i as u128,
+                format!("gpu {} {}", scope.name, statistic),
+                "",
+                *value as f64,
+            );
+        }
+    }
+
+    for (category, usage) in renderer.memory_report().categories {
+        measure(
+            category.diagnostic_id(),
+            format!("gpu memory {}", category.name()),
+            "MiB",
+            usage.bytes as f64 / (1024.0 * 1024.0),
+        );
+    }
 }
 
 fn window_resize(mut window_resized_event: EventReader<WindowResized>) {
@@ -0,0 +1,98 @@
+use bevy::prelude::*;
+use crevice::std430::AsStd430;
+
+/// A punctual light, as in glTF `KHR_lights_punctual`, placed by the
+/// entity's `GlobalTransform`. Directional and spot lights shine along the
+/// local -Z axis.
+///
+/// Colors are linear RGB, scaled by the intensity.
+#[derive(Clone, Copy, Debug)]
+pub enum Light {
+    /// Light from infinitely far away, like the sun.
+    Directional { color: [f32; 3], intensity: f32 },
+    /// Light emitted in all directions, fading to nothing at `range`.
+    Point {
+        color: [f32; 3],
+        intensity: f32,
+        range: f32,
+    },
+    /// A point light limited to a cone, full strength up to `inner_angle`
+    /// from its axis and falling off to zero at `outer_angle`, in radians.
+    Spot {
+        color: [f32; 3],
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Light::Directional {
+            color: [1.0, 1.0, 1.0],
+            intensity: 3.0,
+        }
+    }
+}
+
+/// A `Light` as stored in the scene's light buffer, in world space.
+#[derive(AsStd430)]
+pub struct LightData {
+    pub position: mint::Vector3<f32>,
+    pub range: f32,
+    /// Direction the light travels in.
+    pub direction: mint::Vector3<f32>,
+    /// 0 for directional, 1 for point and 2 for spot lights.
+    pub kind: u32,
+    pub color: mint::Vector3<f32>,
+    pub inner_cos: f32,
+    pub outer_cos: f32,
+}
+
+impl LightData {
+    pub fn new(light: &Light, transform: &GlobalTransform) -> Self {
+        let position: [f32; 3] = transform.translation.into();
+        let direction: [f32; 3] = (transform.rotation * Vec3::new(0.0, 0.0, -1.0))
+            .normalize()
+            .into();
+        let scale = |color: [f32; 3], intensity: f32| -> mint::Vector3<f32> {
+            let [r, g, b] = color;
+            [r * intensity, g * intensity, b * intensity].into()
+        };
+
+        let (kind, color, range, inner_cos, outer_cos) = match *light {
+            Light::Directional { color, intensity } => {
+                (0, scale(color, intensity), f32::INFINITY, 1.0, 1.0)
+            }
+            Light::Point {
+                color,
+                intensity,
+                range,
+            } => (1, scale(color, intensity), range, -1.0, -1.0),
+            Light::Spot {
+                color,
+                intensity,
+                range,
+                inner_angle,
+                outer_angle,
+            } => (
+                2,
+                scale(color, intensity),
+                range,
+                inner_angle.cos(),
+                outer_angle.cos(),
+            ),
+        };
+
+        LightData {
+            position: position.into(),
+            range,
+            direction: direction.into(),
+            kind,
+            color,
+            inner_cos,
+            outer_cos,
+        }
+    }
+}
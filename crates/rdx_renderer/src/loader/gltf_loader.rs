@@ -0,0 +1,564 @@
+use crate::loader::texture_loader;
+use crate::material::{AlphaMode, Material};
+use crate::mesh::{Mesh, Vertex};
+use crate::texture::Texture;
+use anyhow::{anyhow, bail, Context};
+use bevy::asset::{AssetLoader, AssetPath, Handle, LoadContext, LoadedAsset};
+use bevy::math::Mat4;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::scene::Scene;
+use bevy::utils::BoxedFuture;
+use gltf::mesh::Mode;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Loads glTF 2.0 files, `.gltf` with external or embedded buffers and
+/// `.glb`.
+///
+/// Every glTF scene becomes a `Scene` labeled `Scene{i}`, and the default
+/// scene, or the first one, is the default asset. Other labeled assets are
+/// `Mesh{m}/Primitive{p}`, `Material{i}`, `MaterialDefault` and `Texture{i}`
+/// for embedded images. Image files are loaded as dependencies, and so are
+/// external buffers, as `GltfBuffer`s whose changes rebuild the meshes.
+pub struct GltfLoader {
+    buffer_users: GltfBufferUsers,
+}
+
+impl FromWorld for GltfLoader {
+    fn from_world(world: &mut World) -> Self {
+        GltfLoader {
+            buffer_users: world
+                .get_resource_or_insert_with(GltfBufferUsers::default)
+                .clone(),
+        }
+    }
+}
+
+impl AssetLoader for GltfLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move { load_gltf(bytes, load_context, &self.buffer_users).await })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["gltf", "glb"]
+    }
+}
+
+/// The contents of an external glTF buffer file.
+#[derive(Debug, TypeUuid)]
+#[uuid = "9e3c5b1a-4f27-4d8e-b6a0-7c2d1f5e8a93"]
+pub struct GltfBuffer(pub Vec<u8>);
+
+/// Loads `.bin` files as `GltfBuffer`s.
+#[derive(Default)]
+pub struct GltfBufferLoader;
+
+impl AssetLoader for GltfBufferLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            load_context.set_default_asset(LoadedAsset::new(GltfBuffer(bytes.to_vec())));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bin"]
+    }
+}
+
+/// The glTF files using each external buffer, with their contents, to
+/// rebuild their meshes when the buffer changes.
+#[derive(Clone, Default)]
+pub(crate) struct GltfBufferUsers(Arc<Mutex<HashMap<PathBuf, HashMap<PathBuf, Arc<[u8]>>>>>);
+
+impl GltfBufferUsers {
+    /// Replaces the buffers used by the glTF file at `path`.
+    fn register(&self, path: &Path, bytes: &[u8], buffer_paths: &[PathBuf]) {
+        let mut users = self.0.lock();
+        for buffer_users in users.values_mut() {
+            buffer_users.remove(path);
+        }
+
+        let bytes = Arc::<[u8]>::from(bytes);
+        for buffer_path in buffer_paths {
+            users
+                .entry(buffer_path.clone())
+                .or_default()
+                .insert(path.to_path_buf(), bytes.clone());
+        }
+    }
+}
+
+/// Rebuilds the meshes and embedded textures of the glTF files using a
+/// changed `GltfBuffer`.
+pub(crate) fn reload_gltf_buffers(
+    asset_server: Res<AssetServer>,
+    buffer_users: Res<GltfBufferUsers>,
+    gltf_buffers: Res<Assets<GltfBuffer>>,
+    mut buffer_events: EventReader<AssetEvent<GltfBuffer>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut textures: ResMut<Assets<Texture>>,
+) {
+    for event in buffer_events.iter() {
+        let buffer_path = match event {
+            AssetEvent::Modified { handle } => match asset_server.get_handle_path(handle) {
+                Some(asset_path) => asset_path.path().to_path_buf(),
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        let users = buffer_users
+            .0
+            .lock()
+            .get(&buffer_path)
+            .cloned()
+            .unwrap_or_default();
+        for (path, bytes) in users {
+            let reloaded = reload_buffer_user(
+                &path,
+                &bytes,
+                &asset_server,
+                &gltf_buffers,
+                &mut meshes,
+                &mut textures,
+            );
+            if let Err(error) = reloaded {
+                tracing::warn!(
+                    "{}: reloading after {} changed failed, {:#}",
+                    path.display(),
+                    buffer_path.display(),
+                    error
+                );
+            }
+        }
+    }
+}
+
+fn reload_buffer_user(
+    path: &Path,
+    bytes: &[u8],
+    asset_server: &AssetServer,
+    gltf_buffers: &Assets<GltfBuffer>,
+    meshes: &mut Assets<Mesh>,
+    textures: &mut Assets<Texture>,
+) -> anyhow::Result<()> {
+    let gltf = gltf::Gltf::from_slice(bytes)?;
+    let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let mut buffers = Vec::new();
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => gltf
+                .blob
+                .clone()
+                .ok_or_else(|| anyhow!("missing binary chunk"))?,
+            gltf::buffer::Source::Uri(uri) => match decode_data_uri(uri) {
+                Some(data) => data?,
+                None => {
+                    let buffer_path = parent.join(percent_decode(uri));
+                    let handle: Handle<GltfBuffer> =
+                        asset_server.get_handle(AssetPath::new_ref(&buffer_path, None));
+                    gltf_buffers
+                        .get(&handle)
+                        .map(|buffer| buffer.0.clone())
+                        .ok_or_else(|| anyhow!("{} is not loaded", buffer_path.display()))?
+                }
+            },
+        };
+        buffers.push(data);
+    }
+    check_buffers(&gltf, &buffers)?;
+
+    for mesh in gltf.meshes() {
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                continue;
+            }
+            let asset = load_primitive(&primitive, &buffers).with_context(|| {
+                format!("mesh {} primitive {}", mesh.index(), primitive.index())
+            })?;
+            let label = primitive_label(&mesh, &primitive);
+            meshes.set(
+                asset_server.get_handle::<Mesh, _>(AssetPath::new_ref(path, Some(&label))),
+                asset,
+            );
+        }
+    }
+
+    for texture in gltf.textures() {
+        if let gltf::image::Source::View { view, mime_type } = texture.source().source() {
+            let asset = load_view_image(&view, mime_type, &buffers)
+                .with_context(|| format!("decoding texture {}", texture.index()))?;
+            let label = format!("Texture{}", texture.index());
+            textures.set(
+                asset_server.get_handle::<Texture, _>(AssetPath::new_ref(path, Some(&label))),
+                asset,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn load_gltf<'a, 'b>(
+    bytes: &'a [u8],
+    load_context: &'a mut LoadContext<'b>,
+    buffer_users: &GltfBufferUsers,
+) -> anyhow::Result<()> {
+    let gltf = gltf::Gltf::from_slice(bytes)?;
+    let path = load_context.path().to_path_buf();
+    let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let mut buffers = Vec::new();
+    let mut buffer_paths = Vec::new();
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => gltf
+                .blob
+                .clone()
+                .ok_or_else(|| anyhow!("missing binary chunk"))?,
+            gltf::buffer::Source::Uri(uri) => match decode_data_uri(uri) {
+                Some(data) => data?,
+                None => {
+                    let buffer_path = parent.join(percent_decode(uri));
+                    let data = load_context
+                        .read_asset_bytes(&buffer_path)
+                        .await
+                        .with_context(|| format!("reading {}", buffer_path.display()))?;
+                    buffer_paths.push(buffer_path);
+                    data
+                }
+            },
+        };
+        buffers.push(data);
+    }
+    check_buffers(&gltf, &buffers)?;
+
+    // Image files are assets of their own, embedded images are labeled ones.
+    let mut texture_paths = Vec::new();
+    for texture in gltf.textures() {
+        let texture_path = match texture.source().source() {
+            gltf::image::Source::View { view, mime_type } => {
+                let asset = load_view_image(&view, mime_type, &buffers)
+                    .with_context(|| format!("decoding texture {}", texture.index()))?;
+                let label = format!("Texture{}", texture.index());
+                load_context.set_labeled_asset(&label, LoadedAsset::new(asset));
+                AssetPath::new(path.clone(), Some(label))
+            }
+            gltf::image::Source::Uri { uri, mime_type } => match decode_data_uri(uri) {
+                Some(data) => {
+                    let asset = mime_type
+                        .ok_or_else(|| anyhow!("data uri without mime type"))
+                        .and_then(|mime_type| {
+                            texture_loader::decode(&data?, &mime_extension(mime_type)?)
+                        })
+                        .with_context(|| format!("decoding texture {}", texture.index()))?;
+                    let label = format!("Texture{}", texture.index());
+                    load_context.set_labeled_asset(&label, LoadedAsset::new(asset));
+                    AssetPath::new(path.clone(), Some(label))
+                }
+                None => AssetPath::new(parent.join(percent_decode(uri)), None),
+            },
+        };
+        texture_paths.push(texture_path);
+    }
+
+    for material in gltf.materials() {
+        let label = format!("Material{}", material.index().unwrap());
+        let pbr = material.pbr_metallic_roughness();
+        let base_color = pbr.base_color_texture().map(|info| info.texture().index());
+        let metallic_roughness = pbr
+            .metallic_roughness_texture()
+            .map(|info| info.texture().index());
+        let normal = material.normal_texture().map(|info| info.texture().index());
+        let occlusion = material
+            .occlusion_texture()
+            .map(|info| info.texture().index());
+        let emissive = material
+            .emissive_texture()
+            .map(|info| info.texture().index());
+
+        let dependencies = [base_color, metallic_roughness, normal, occlusion, emissive]
+            .iter()
+            .flatten()
+            .map(|&index| texture_paths[index].clone())
+            .filter(|texture_path| texture_path.label().is_none())
+            .collect::<Vec<_>>();
+        let texture = |index: Option<usize>| -> Option<Handle<Texture>> {
+            index.map(|index| load_context.get_handle(texture_paths[index].clone()))
+        };
+
+        let asset = Material {
+            base_color: pbr.base_color_factor(),
+            emissive: material.emissive_factor(),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            base_color_texture: texture(base_color),
+            metallic_roughness_texture: texture(metallic_roughness),
+            normal_texture: texture(normal),
+            occlusion_texture: texture(occlusion),
+            emissive_texture: texture(emissive),
+            alpha_mode: match material.alpha_mode() {
+                gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                gltf::material::AlphaMode::Mask => AlphaMode::Mask {
+                    cutoff: material.alpha_cutoff(),
+                },
+                gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+            },
+            double_sided: material.double_sided(),
+        };
+        load_context.set_labeled_asset(
+            &label,
+            LoadedAsset::new(asset).with_dependencies(dependencies),
+        );
+    }
+
+    let mut default_material = false;
+    for mesh in gltf.meshes() {
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                tracing::warn!(
+                    "{}: skipping mesh {} primitive {}, {:?} is not supported",
+                    load_context.path().display(),
+                    mesh.index(),
+                    primitive.index(),
+                    primitive.mode()
+                );
+                continue;
+            }
+            default_material |= primitive.material().index().is_none();
+
+            let asset = load_primitive(&primitive, &buffers).with_context(|| {
+                format!("mesh {} primitive {}", mesh.index(), primitive.index())
+            })?;
+            load_context
+                .set_labeled_asset(&primitive_label(&mesh, &primitive), LoadedAsset::new(asset));
+        }
+    }
+
+    if default_material {
+        load_context.set_labeled_asset("MaterialDefault", LoadedAsset::new(Material::default()));
+    }
+
+    for scene in gltf.scenes() {
+        let asset = load_scene(&scene, load_context);
+        load_context.set_labeled_asset(&format!("Scene{}", scene.index()), LoadedAsset::new(asset));
+    }
+
+    let scene = gltf
+        .default_scene()
+        .or_else(|| gltf.scenes().next())
+        .ok_or_else(|| anyhow!("no scenes"))?;
+    let asset = load_scene(&scene, load_context);
+    let dependencies = buffer_paths
+        .iter()
+        .map(|buffer_path| AssetPath::new(buffer_path.clone(), None))
+        .collect();
+    load_context.set_default_asset(LoadedAsset::new(asset).with_dependencies(dependencies));
+
+    buffer_users.register(&path, bytes, &buffer_paths);
+    Ok(())
+}
+
+fn load_scene(scene: &gltf::Scene, load_context: &LoadContext) -> Scene {
+    let mut world = World::default();
+    let roots = scene
+        .nodes()
+        .map(|node| load_node(&node, &mut world, load_context))
+        .collect::<Vec<_>>();
+    world
+        .spawn()
+        .insert_bundle((Transform::identity(), GlobalTransform::identity()))
+        .push_children(&roots);
+
+    Scene::new(world)
+}
+
+/// Spawns `node` and its children into `world`, returning its entity.
+fn load_node(node: &gltf::Node, world: &mut World, load_context: &LoadContext) -> Entity {
+    let transform = Mat4::from_cols_array_2d(&node.transform().matrix());
+
+    let mut children = Vec::new();
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                continue;
+            }
+
+            let mesh_label = primitive_label(&mesh, &primitive);
+            let material_label = match primitive.material().index() {
+                Some(index) => format!("Material{}", index),
+                None => "MaterialDefault".to_owned(),
+            };
+            let mesh: Handle<Mesh> =
+                load_context.get_handle(AssetPath::new_ref(load_context.path(), Some(&mesh_label)));
+            let material: Handle<Material> = load_context.get_handle(AssetPath::new_ref(
+                load_context.path(),
+                Some(&material_label),
+            ));
+
+            children.push(
+                world
+                    .spawn()
+                    .insert_bundle((
+                        mesh,
+                        material,
+                        Transform::identity(),
+                        GlobalTransform::identity(),
+                    ))
+                    .id(),
+            );
+        }
+    }
+
+    for child in node.children() {
+        children.push(load_node(&child, world, load_context));
+    }
+
+    world
+        .spawn()
+        .insert_bundle((
+            Transform::from_matrix(transform),
+            GlobalTransform::identity(),
+        ))
+        .push_children(&children)
+        .id()
+}
+
+/// Checks every buffer holds the bytes the file declares, which accessors
+/// and views are validated against.
+fn check_buffers(gltf: &gltf::Gltf, buffers: &[Vec<u8>]) -> anyhow::Result<()> {
+    for (buffer, data) in gltf.buffers().zip(buffers) {
+        if data.len() < buffer.length() {
+            bail!(
+                "buffer {} holds {} bytes instead of {}",
+                buffer.index(),
+                data.len(),
+                buffer.length()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Reads a triangle list primitive, checking its indices.
+fn load_primitive(primitive: &gltf::Primitive, buffers: &[Vec<u8>]) -> anyhow::Result<Mesh> {
+    let reader = primitive.reader(|buffer| Some(buffers[buffer.index()].as_slice()));
+    let positions = reader
+        .read_positions()
+        .ok_or_else(|| anyhow!("no positions"))?;
+    let mut vertices = positions
+        .map(|position| Vertex::new(position, [0.0; 3], [0.0; 2]))
+        .collect::<Vec<_>>();
+
+    let normals = reader.read_normals();
+    let has_normals = normals.is_some();
+    for (vertex, normal) in vertices.iter_mut().zip(normals.into_iter().flatten()) {
+        vertex.normal = normal;
+    }
+    if let Some(uvs) = reader.read_tex_coords(0) {
+        for (vertex, uv) in vertices.iter_mut().zip(uvs.into_f32()) {
+            vertex.uv = uv;
+        }
+    }
+    let tangents = reader.read_tangents();
+    let has_tangents = tangents.is_some();
+    for (vertex, tangent) in vertices.iter_mut().zip(tangents.into_iter().flatten()) {
+        vertex.tangent = tangent;
+    }
+
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+        None => (0..vertices.len() as u32).collect(),
+    };
+    if indices.len() % 3 != 0 {
+        bail!("{} indices do not form triangles", indices.len());
+    }
+    if let Some(index) = indices
+        .iter()
+        .find(|&&index| index as usize >= vertices.len())
+    {
+        bail!("index {} out of {} vertices", index, vertices.len());
+    }
+
+    let mut mesh = Mesh::new(vertices, indices);
+    if !has_normals {
+        mesh.compute_normals();
+    }
+    if !has_normals || !has_tangents {
+        mesh.compute_tangents();
+    }
+    Ok(mesh)
+}
+
+/// Decodes an image stored in a buffer view.
+fn load_view_image(
+    view: &gltf::buffer::View,
+    mime_type: &str,
+    buffers: &[Vec<u8>],
+) -> anyhow::Result<Texture> {
+    let data = buffers[view.buffer().index()]
+        .get(view.offset()..view.offset() + view.length())
+        .ok_or_else(|| anyhow!("buffer view {} out of its buffer", view.index()))?;
+    texture_loader::decode(data, &mime_extension(mime_type)?)
+}
+
+fn primitive_label(mesh: &gltf::Mesh, primitive: &gltf::Primitive) -> String {
+    format!("Mesh{}/Primitive{}", mesh.index(), primitive.index())
+}
+
+fn mime_extension(mime_type: &str) -> anyhow::Result<String> {
+    match mime_type {
+        "image/png" => Ok("png".to_owned()),
+        "image/jpeg" => Ok("jpg".to_owned()),
+        _ => bail!("unsupported image type {}", mime_type),
+    }
+}
+
+/// Decodes a base64 data URI, `None` for other URIs.
+fn decode_data_uri(uri: &str) -> Option<anyhow::Result<Vec<u8>>> {
+    let data = uri.strip_prefix("data:")?;
+    Some(
+        data.split_once(";base64,")
+            .ok_or_else(|| anyhow!("unsupported data uri"))
+            .and_then(|(_, data)| Ok(base64::decode(data)?)),
+    )
+}
+
+/// Decodes the `%XX` escapes glTF exporters use for spaces and other
+/// characters in relative URIs.
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,8 @@
+mod gltf_loader;
+mod obj_loader;
+mod texture_loader;
+
+pub(crate) use self::gltf_loader::reload_gltf_buffers;
+pub use self::gltf_loader::{GltfBuffer, GltfBufferLoader, GltfLoader};
+pub use self::obj_loader::ObjLoader;
+pub use self::texture_loader::TextureLoader;
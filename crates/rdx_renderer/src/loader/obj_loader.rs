@@ -0,0 +1,175 @@
+use crate::material::{AlphaMode, Material};
+use crate::mesh::{Mesh, Vertex};
+use crate::texture::Texture;
+use anyhow::Context;
+use bevy::asset::{AssetLoader, AssetPath, Handle, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::scene::Scene;
+use bevy::utils::BoxedFuture;
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Loads Wavefront OBJ files, with their MTL materials, as a `Scene` holding
+/// one entity per object.
+///
+/// Labeled assets are `Mesh{i}`, `Material{i}` and `MaterialDefault` for
+/// objects without a material. Diffuse textures are loaded as dependencies.
+#[derive(Default)]
+pub struct ObjLoader;
+
+impl AssetLoader for ObjLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move { load_obj(bytes, load_context).await })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["obj"]
+    }
+}
+
+async fn load_obj<'a, 'b>(
+    bytes: &'a [u8],
+    load_context: &'a mut LoadContext<'b>,
+) -> anyhow::Result<()> {
+    let parent = load_context
+        .path()
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+
+    // The material loader of tobj is synchronous, read the libraries first.
+    let mut libraries = HashMap::new();
+    for line in String::from_utf8_lossy(bytes).lines() {
+        if let Some(name) = line.trim().strip_prefix("mtllib") {
+            let name = PathBuf::from(name.trim());
+            let library = load_context
+                .read_asset_bytes(parent.join(&name))
+                .await
+                .with_context(|| format!("reading material library {}", name.display()))?;
+            libraries.insert(name, library);
+        }
+    }
+
+    let (models, materials) = tobj::load_obj_buf(
+        &mut BufReader::new(bytes),
+        &tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ..Default::default()
+        },
+        |path| match libraries.get(path) {
+            Some(library) => tobj::load_mtl_buf(&mut BufReader::new(&library[..])),
+            None => Err(tobj::LoadError::OpenFileFailed),
+        },
+    )?;
+    let materials = materials.unwrap_or_else(|error| {
+        tracing::warn!("{}: no materials, {}", load_context.path().display(), error);
+        Vec::new()
+    });
+
+    for (index, material) in materials.iter().enumerate() {
+        let mut dependencies = Vec::new();
+        let base_color_texture = if material.diffuse_texture.is_empty() {
+            None
+        } else {
+            let path = AssetPath::new(parent.join(&material.diffuse_texture), None);
+            let handle: Handle<Texture> = load_context.get_handle(path.clone());
+            dependencies.push(path);
+            Some(handle)
+        };
+
+        let [r, g, b] = material.diffuse;
+        let material = Material {
+            base_color: [r, g, b, material.dissolve],
+            // Blinn-Phong exponent to GGX roughness.
+            roughness: (2.0 / (material.shininess + 2.0)).sqrt(),
+            base_color_texture,
+            alpha_mode: if material.dissolve < 1.0 {
+                AlphaMode::Blend
+            } else {
+                AlphaMode::Opaque
+            },
+            ..Default::default()
+        };
+
+        load_context.set_labeled_asset(
+            &format!("Material{}", index),
+            LoadedAsset::new(material).with_dependencies(dependencies),
+        );
+    }
+
+    let material_label_for = |material_id: Option<usize>| match material_id {
+        Some(material) if material < materials.len() => format!("Material{}", material),
+        _ => "MaterialDefault".to_owned(),
+    };
+
+    if models
+        .iter()
+        .any(|model| material_label_for(model.mesh.material_id) == "MaterialDefault")
+    {
+        load_context.set_labeled_asset("MaterialDefault", LoadedAsset::new(Material::default()));
+    }
+
+    let mut world = World::default();
+    let mut children = Vec::with_capacity(models.len());
+
+    for (index, model) in models.iter().enumerate() {
+        let mesh = &model.mesh;
+        let vec3 = |data: &[f32], i: usize| match data.get(i * 3..i * 3 + 3) {
+            Some(&[x, y, z]) => [x, y, z],
+            _ => [0.0; 3],
+        };
+        let vertices = (0..mesh.positions.len() / 3)
+            .map(|i| {
+                // OBJ texture coordinates start at the bottom.
+                let uv = match mesh.texcoords.get(i * 2..i * 2 + 2) {
+                    Some(&[u, v]) => [u, 1.0 - v],
+                    _ => [0.0; 2],
+                };
+                Vertex::new(vec3(&mesh.positions, i), vec3(&mesh.normals, i), uv)
+            })
+            .collect();
+
+        let mut asset = Mesh::new(vertices, mesh.indices.clone());
+        if mesh.normals.is_empty() {
+            asset.compute_normals();
+        }
+        asset.compute_tangents();
+
+        let mesh_label = format!("Mesh{}", index);
+        load_context.set_labeled_asset(&mesh_label, LoadedAsset::new(asset));
+
+        let material_label = material_label_for(mesh.material_id);
+
+        let mesh: Handle<Mesh> =
+            load_context.get_handle(AssetPath::new_ref(load_context.path(), Some(&mesh_label)));
+        let material: Handle<Material> = load_context.get_handle(AssetPath::new_ref(
+            load_context.path(),
+            Some(&material_label),
+        ));
+
+        let child = world
+            .spawn()
+            .insert_bundle((
+                mesh,
+                material,
+                Transform::identity(),
+                GlobalTransform::identity(),
+            ))
+            .id();
+        children.push(child);
+    }
+
+    world
+        .spawn()
+        .insert_bundle((Transform::identity(), GlobalTransform::identity()))
+        .push_children(&children);
+
+    load_context.set_default_asset(LoadedAsset::new(Scene::new(world)));
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use crate::texture::Texture;
+use anyhow::anyhow;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::utils::BoxedFuture;
+use erupt::vk;
+use image::codecs::hdr::HdrDecoder;
+use image::ImageFormat;
+
+/// Loads PNG and JPEG images as RGBA8 textures, and Radiance HDR images as
+/// linear RGBA32F.
+///
+/// 8 bit images are stored in the sRGB encoding, but materials read them as
+/// sRGB or linear data depending on the slot they are used in.
+#[derive(Default)]
+pub struct TextureLoader;
+
+impl AssetLoader for TextureLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let extension = load_context
+                .path()
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+
+            let texture = decode(bytes, &extension)?;
+            load_context.set_default_asset(LoadedAsset::new(texture));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["png", "jpg", "jpeg", "hdr"]
+    }
+}
+
+/// Decodes an image file, `extension` naming its format.
+pub(crate) fn decode(bytes: &[u8], extension: &str) -> anyhow::Result<Texture> {
+    if extension == "hdr" {
+        let decoder = HdrDecoder::new(bytes)?;
+        let metadata = decoder.metadata();
+        let data = decoder
+            .read_image_hdr()?
+            .into_iter()
+            .flat_map(|texel| {
+                let [r, g, b] = texel.0;
+                [r, g, b, 1.0]
+            })
+            .flat_map(f32::to_ne_bytes)
+            .collect();
+
+        return Ok(Texture::new(
+            metadata.width,
+            metadata.height,
+            vk::Format::R32G32B32A32_SFLOAT,
+            data,
+        ));
+    }
+
+    let format = ImageFormat::from_extension(extension)
+        .ok_or_else(|| anyhow!("unsupported image extension {:?}", extension))?;
+    let image = image::load_from_memory_with_format(bytes, format)?.into_rgba8();
+
+    Ok(Texture::from_rgba8(
+        image.width(),
+        image.height(),
+        image.into_raw(),
+        true,
+    ))
+}
@@ -0,0 +1,145 @@
+use crate::texture::Texture;
+use bevy::asset::{Handle, HandleId};
+use bevy::reflect::TypeUuid;
+use crevice::std430::AsStd430;
+
+/// Texture index in `MaterialData` of a missing or not yet loaded texture.
+pub const NO_TEXTURE: u32 = !0;
+
+/// How the alpha of the base color is used, as in glTF.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    Opaque,
+    /// Fragments with an alpha below `cutoff` are discarded.
+    Mask { cutoff: f32 },
+    /// Blended over what is behind, drawn after opaque geometry from back to
+    /// front without writing depth.
+    Blend,
+}
+
+impl AlphaMode {
+    fn shader_value(self) -> (u32, f32) {
+        match self {
+            AlphaMode::Opaque => (0, 0.0),
+            AlphaMode::Mask { cutoff } => (1, cutoff),
+            AlphaMode::Blend => (2, 0.0),
+        }
+    }
+}
+
+/// Surface parameters of the entities holding a `Handle<Material>`.
+///
+/// Textures multiply their factor, following the glTF metallic-roughness
+/// model: metallic is read from the blue channel and roughness from green.
+/// Base color and emissive textures are decoded as sRGB.
+#[derive(Clone, Debug, TypeUuid)]
+#[uuid = "0b9d3e85-71c4-4f6a-8e2d-5a1f9c3b7e40"]
+pub struct Material {
+    /// Linear RGBA.
+    pub base_color: [f32; 4],
+    /// Linear RGB radiance.
+    pub emissive: [f32; 3],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub base_color_texture: Option<Handle<Texture>>,
+    pub metallic_roughness_texture: Option<Handle<Texture>>,
+    /// Tangent space normals.
+    pub normal_texture: Option<Handle<Texture>>,
+    /// Ambient occlusion in the red channel.
+    pub occlusion_texture: Option<Handle<Texture>>,
+    pub emissive_texture: Option<Handle<Texture>>,
+    pub alpha_mode: AlphaMode,
+    /// Whether back faces are drawn, with flipped normals.
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            emissive: [0.0, 0.0, 0.0],
+            metallic: 0.0,
+            roughness: 0.5,
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+impl Material {
+    pub fn from_color(base_color: [f32; 4]) -> Self {
+        Material {
+            base_color,
+            ..Default::default()
+        }
+    }
+
+    /// Whether any of the textures is `texture`.
+    pub fn uses_texture(&self, texture: HandleId) -> bool {
+        self.textures()
+            .iter()
+            .any(|handle| handle.as_ref().map_or(false, |handle| handle.id == texture))
+    }
+
+    fn textures(&self) -> [&Option<Handle<Texture>>; 5] {
+        [
+            &self.base_color_texture,
+            &self.metallic_roughness_texture,
+            &self.normal_texture,
+            &self.occlusion_texture,
+            &self.emissive_texture,
+        ]
+    }
+}
+
+/// A `Material` as stored in the scene's material buffer. Textures are
+/// indices into the scene's texture array, `NO_TEXTURE` when absent.
+#[derive(AsStd430)]
+pub struct MaterialData {
+    pub base_color: mint::Vector4<f32>,
+    pub emissive: mint::Vector3<f32>,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub base_color_texture: u32,
+    pub metallic_roughness_texture: u32,
+    pub normal_texture: u32,
+    pub occlusion_texture: u32,
+    pub emissive_texture: u32,
+    /// 0 for opaque, 1 for mask and 2 for blend.
+    pub alpha_mode: u32,
+    pub alpha_cutoff: f32,
+}
+
+impl MaterialData {
+    /// `texture_index` maps a loaded texture to the index of its view
+    /// decoding sRGB, when the flag is set, or reading linear data.
+    pub fn new(material: &Material, texture_index: impl Fn(HandleId, bool) -> Option<u32>) -> Self {
+        let index = |texture: &Option<Handle<Texture>>, srgb: bool| {
+            texture
+                .as_ref()
+                .and_then(|texture| texture_index(texture.id, srgb))
+                .unwrap_or(NO_TEXTURE)
+        };
+        let (alpha_mode, alpha_cutoff) = material.alpha_mode.shader_value();
+
+        MaterialData {
+            base_color: material.base_color.into(),
+            emissive: material.emissive.into(),
+            metallic: material.metallic,
+            roughness: material.roughness,
+            base_color_texture: index(&material.base_color_texture, true),
+            metallic_roughness_texture: index(&material.metallic_roughness_texture, false),
+            normal_texture: index(&material.normal_texture, false),
+            occlusion_texture: index(&material.occlusion_texture, false),
+            emissive_texture: index(&material.emissive_texture, true),
+            alpha_mode,
+            alpha_cutoff,
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use erupt::vk;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Block sizes and dedicated allocation thresholds for the GPU allocator.
+///
+/// Insert it as a resource before adding the `RenderPlugin` to tune the
+/// allocator; the defaults suit a scene with a few hundred megabytes of data.
+#[derive(Clone, Debug)]
+pub struct MemorySettings {
+    /// Requests at least this large always get their own `VkDeviceMemory`.
+    pub dedicated_threshold: u64,
+    /// Like `dedicated_threshold` for resources that prefer dedicated memory.
+    pub preferred_dedicated_threshold: u64,
+    /// Like `dedicated_threshold` for transient attachments.
+    pub transient_dedicated_threshold: u64,
+    pub starting_free_list_chunk: u64,
+    pub final_free_list_chunk: u64,
+    pub minimal_buddy_size: u64,
+    pub initial_buddy_dedicated_size: u64,
+    /// Log a warning when a heap's usage crosses this fraction of its budget.
+    pub budget_warning: f32,
+}
+
+impl Default for MemorySettings {
+    fn default() -> Self {
+        MemorySettings {
+            dedicated_threshold: 32 * 1024 * 1024,
+            preferred_dedicated_threshold: 4 * 1024 * 1024,
+            transient_dedicated_threshold: 128 * 1024 * 1024,
+            starting_free_list_chunk: 8 * 1024 * 1024,
+            final_free_list_chunk: 128 * 1024 * 1024,
+            minimal_buddy_size: 1,
+            initial_buddy_dedicated_size: 8 * 1024 * 1024,
+            budget_warning: 0.9,
+        }
+    }
+}
+
+impl MemorySettings {
+    pub fn allocator_config(&self) -> gpu_alloc::Config {
+        gpu_alloc::Config {
+            dedicated_threshold: self.dedicated_threshold,
+            preferred_dedicated_threshold: self.preferred_dedicated_threshold,
+            transient_dedicated_threshold: self.transient_dedicated_threshold,
+            starting_free_list_chunk: self.starting_free_list_chunk,
+            final_free_list_chunk: self.final_free_list_chunk,
+            minimal_buddy_size: self.minimal_buddy_size,
+            initial_buddy_dedicated_size: self.initial_buddy_dedicated_size,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MemoryCategory {
+    Buffer,
+    Image,
+    AccelerationStructure,
+    Staging,
+    /// Host-visible buffers rewritten every frame, such as the frame
+    /// allocator and the typed buffers.
+    FrameData,
+}
+
+impl MemoryCategory {
+    pub const ALL: [MemoryCategory; 5] = [
+        MemoryCategory::Buffer,
+        MemoryCategory::Image,
+        MemoryCategory::AccelerationStructure,
+        MemoryCategory::Staging,
+        MemoryCategory::FrameData,
+    ];
+
+    /// Random id of the category's memory usage diagnostic.
+    pub fn diagnostic_id(self) -> u128 {
+        match self {
+            MemoryCategory::Buffer => 0x13d41c690eb5483f8f8e4598d00e3b77,
+            MemoryCategory::Image => 0x86ada53c42d34a81b075482ca398c6b8,
+            MemoryCategory::AccelerationStructure => 0x3ef3e24d1f6d4681b0209f59d9c0b6b3,
+            MemoryCategory::Staging => 0x79f8367b86f24726ab8a3ec967cab4e7,
+            MemoryCategory::FrameData => 0x7ff19ea6897f4e8b800de107a9d2b761,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MemoryCategory::Buffer => "buffers",
+            MemoryCategory::Image => "images",
+            MemoryCategory::AccelerationStructure => "acceleration structures",
+            MemoryCategory::Staging => "staging",
+            MemoryCategory::FrameData => "frame data",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CategoryUsage {
+    pub count: u64,
+    pub bytes: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct HeapUsage {
+    pub size: u64,
+    pub device_local: bool,
+    /// Usage and budget reported by `VK_EXT_memory_budget`, when enabled.
+    pub usage: Option<u64>,
+    pub budget: Option<u64>,
+}
+
+/// Snapshot of the device's memory use, from `Device::memory_report`.
+#[derive(Clone, Debug)]
+pub struct MemoryReport {
+    pub categories: Vec<(MemoryCategory, CategoryUsage)>,
+    pub heaps: Vec<HeapUsage>,
+}
+
+impl MemoryReport {
+    pub fn total_bytes(&self) -> u64 {
+        self.categories.iter().map(|(_, usage)| usage.bytes).sum()
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "gpu memory: {}", Megabytes(self.total_bytes()))?;
+
+        for (category, usage) in &self.categories {
+            write!(
+                f,
+                "\n    {}: {} in {} allocations",
+                category.name(),
+                Megabytes(usage.bytes),
+                usage.count
+            )?;
+        }
+
+        for (index, heap) in self.heaps.iter().enumerate() {
+            write!(
+                f,
+                "\n    heap {}{}: {}",
+                index,
+                if heap.device_local {
+                    " (device local)"
+                } else {
+                    ""
+                },
+                Megabytes(heap.size)
+            )?;
+
+            if let (Some(usage), Some(budget)) = (heap.usage, heap.budget) {
+                write!(
+                    f,
+                    ", {} used of {} budget",
+                    Megabytes(usage),
+                    Megabytes(budget)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Megabytes(u64);
+
+impl fmt::Display for Megabytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1} MiB", self.0 as f64 / (1024.0 * 1024.0))
+    }
+}
+
+struct TrackedAllocation {
+    category: MemoryCategory,
+    bytes: u64,
+    name: Option<Box<str>>,
+}
+
+/// Per-category accounting of the device's allocations, keyed by the
+/// resource handle so live resources can be listed by debug name.
+#[derive(Default)]
+pub(crate) struct MemoryTracker {
+    allocations: HashMap<(vk::ObjectType, u64), TrackedAllocation>,
+}
+
+impl MemoryTracker {
+    pub fn track(
+        &mut self,
+        category: MemoryCategory,
+        object_type: vk::ObjectType,
+        handle: u64,
+        bytes: u64,
+        name: &Option<Box<str>>,
+    ) {
+        self.allocations.insert(
+            (object_type, handle),
+            TrackedAllocation {
+                category,
+                bytes,
+                name: name.clone(),
+            },
+        );
+    }
+
+    pub fn untrack(&mut self, object_type: vk::ObjectType, handle: u64) {
+        self.allocations.remove(&(object_type, handle));
+    }
+
+    pub fn categories(&self) -> Vec<(MemoryCategory, CategoryUsage)> {
+        MemoryCategory::ALL
+            .iter()
+            .map(|&category| {
+                let usage = self
+                    .allocations
+                    .values()
+                    .filter(|allocation| allocation.category == category)
+                    .fold(CategoryUsage::default(), |usage, allocation| {
+                        CategoryUsage {
+                            count: usage.count + 1,
+                            bytes: usage.bytes + allocation.bytes,
+                        }
+                    });
+                (category, usage)
+            })
+            .collect()
+    }
+
+    /// Logs every allocation still tracked, meant to be called at shutdown.
+    pub fn report_leaks(&self) {
+        if self.allocations.is_empty() {
+            return;
+        }
+
+        tracing::warn!(
+            "{} gpu allocations still live at shutdown",
+            self.allocations.len()
+        );
+
+        let mut allocations = self.allocations.values().collect::<Vec<_>>();
+        allocations.sort_by(|a, b| b.bytes.cmp(&a.bytes));
+
+        for allocation in allocations {
+            tracing::warn!(
+                "    {} {}: {}",
+                allocation.category.name(),
+                allocation.name.as_deref().unwrap_or("<unnamed>"),
+                Megabytes(allocation.bytes)
+            );
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::buffer::BufferInfo;
+use crate::device::Device;
+use crate::resources::{AccelerationStructure, Buffer};
+use crate::upload::UploadManager;
+use bevy::math::Vec3;
+use bevy::reflect::TypeUuid;
+use crevice::internal::bytemuck;
+use erupt::vk;
+use gpu_alloc::UsageFlags;
+use std::mem;
+
+/// Triangle list geometry, rendered by entities with a `Handle<Mesh>` and a
+/// `Handle<Material>`.
+#[derive(Clone, Debug, TypeUuid)]
+#[uuid = "6f2a1c47-3d0e-4b8a-9a51-2c9e7d4b0f13"]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    /// Three indices per triangle, counter-clockwise when front facing.
+    pub indices: Vec<u32>,
+}
+
+/// Layout of the vertex buffer at binding 0.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    /// Tangent space basis for normal mapping, `w` being the handedness of
+    /// the bitangent `cross(normal, tangent) * w`.
+    pub tangent: [f32; 4],
+    pub uv: [f32; 2],
+}
+
+unsafe impl bytemuck::Zeroable for Vertex {}
+unsafe impl bytemuck::Pod for Vertex {}
+
+impl Vertex {
+    /// A vertex without a tangent, see `Mesh::compute_tangents`.
+    pub const fn new(position: [f32; 3], normal: [f32; 3], uv: [f32; 2]) -> Self {
+        Vertex {
+            position,
+            normal,
+            tangent: [0.0; 4],
+            uv,
+        }
+    }
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        assert_eq!(indices.len() % 3, 0, "Mesh indices must form triangles");
+        assert!(
+            indices
+                .iter()
+                .all(|&index| (index as usize) < vertices.len()),
+            "Mesh index out of bounds"
+        );
+
+        Mesh { vertices, indices }
+    }
+
+    /// Replaces the normals by the area weighted average of the normals of
+    /// the triangles sharing each vertex.
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![Vec3::ZERO; self.vertices.len()];
+
+        for triangle in self.indices.chunks_exact(3) {
+            let position = |i: usize| Vec3::from(self.vertices[triangle[i] as usize].position);
+            let (a, b, c) = (position(0), position(1), position(2));
+            let normal = (b - a).cross(c - a);
+            for &index in triangle {
+                normals[index as usize] += normal;
+            }
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            let length = normal.length();
+            if length > 0.0 {
+                vertex.normal = (normal / length).into();
+            }
+        }
+    }
+
+    /// Replaces the tangents by ones following the direction of increasing
+    /// U, averaged over the triangles sharing each vertex and orthogonalized
+    /// against its normal. Normals must be set first.
+    pub fn compute_tangents(&mut self) {
+        let mut tangents = vec![Vec3::ZERO; self.vertices.len()];
+        let mut bitangents = vec![Vec3::ZERO; self.vertices.len()];
+
+        for triangle in self.indices.chunks_exact(3) {
+            let vertex = |i: usize| &self.vertices[triangle[i] as usize];
+            let (a, b, c) = (vertex(0), vertex(1), vertex(2));
+            let edge1 = Vec3::from(b.position) - Vec3::from(a.position);
+            let edge2 = Vec3::from(c.position) - Vec3::from(a.position);
+            let (du1, dv1) = (b.uv[0] - a.uv[0], b.uv[1] - a.uv[1]);
+            let (du2, dv2) = (c.uv[0] - a.uv[0], c.uv[1] - a.uv[1]);
+
+            let determinant = du1 * dv2 - du2 * dv1;
+            if determinant.abs() <= f32::EPSILON {
+                continue;
+            }
+            let r = 1.0 / determinant;
+            let tangent = (edge1 * dv2 - edge2 * dv1) * r;
+            let bitangent = (edge2 * du1 - edge1 * du2) * r;
+
+            for &index in triangle {
+                tangents[index as usize] += tangent;
+                bitangents[index as usize] += bitangent;
+            }
+        }
+
+        for ((vertex, tangent), bitangent) in self.vertices.iter_mut().zip(tangents).zip(bitangents)
+        {
+            let normal = Vec3::from(vertex.normal);
+            let mut tangent = tangent - normal * normal.dot(tangent);
+            if tangent.length_squared() <= f32::EPSILON {
+                // No usable texture coordinates, any perpendicular will do.
+                let axis = if normal.x.abs() < 0.9 {
+                    Vec3::new(1.0, 0.0, 0.0)
+                } else {
+                    Vec3::new(0.0, 1.0, 0.0)
+                };
+                tangent = normal.cross(axis);
+            }
+            let tangent = tangent.normalize();
+
+            let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            vertex.tangent = [tangent.x, tangent.y, tangent.z, handedness];
+        }
+    }
+
+    /// A unit quad in the XY plane, facing +Z.
+    pub fn quad() -> Self {
+        let normal = [0.0, 0.0, 1.0];
+        let mut mesh = Mesh::new(
+            vec![
+                Vertex::new([-0.5, -0.5, 0.0], normal, [0.0, 1.0]),
+                Vertex::new([0.5, -0.5, 0.0], normal, [1.0, 1.0]),
+                Vertex::new([0.5, 0.5, 0.0], normal, [1.0, 0.0]),
+                Vertex::new([-0.5, 0.5, 0.0], normal, [0.0, 0.0]),
+            ],
+            vec![0, 1, 2, 0, 2, 3],
+        );
+        mesh.compute_tangents();
+        mesh
+    }
+}
+
+/// A mesh uploaded to device local vertex and index buffers.
+pub struct GpuMesh {
+    pub vertices: [(Buffer, u64); 1],
+    pub indices: Buffer,
+    pub index_count: u32,
+    /// Bottom level structure for ray tracing, once one is built for the
+    /// mesh. Instances of meshes without one are left out of the TLAS.
+    pub blas: Option<AccelerationStructure>,
+}
+
+impl GpuMesh {
+    pub fn new(uploads: &mut UploadManager, mesh: &Mesh, name: &str) -> Self {
+        let vertices = uploads.create_buffer_with_data(
+            BufferInfo {
+                align: mem::align_of::<Vertex>() as u64 - 1,
+                size: mem::size_of_val(&mesh.vertices[..]) as u64,
+                usage_flags: vk::BufferUsageFlags::VERTEX_BUFFER,
+                allocation_flags: UsageFlags::empty(),
+                name: Some(format!("{} vertices", name).into()),
+            },
+            &mesh.vertices,
+        );
+
+        let indices = uploads.create_buffer_with_data(
+            BufferInfo {
+                align: mem::align_of::<u32>() as u64 - 1,
+                size: mem::size_of_val(&mesh.indices[..]) as u64,
+                usage_flags: vk::BufferUsageFlags::INDEX_BUFFER,
+                allocation_flags: UsageFlags::empty(),
+                name: Some(format!("{} indices", name).into()),
+            },
+            &mesh.indices,
+        );
+
+        GpuMesh {
+            vertices: [(vertices, 0)],
+            indices,
+            index_count: mesh.indices.len() as u32,
+            blas: None,
+        }
+    }
+
+    pub fn destroy(&self, device: &Device) {
+        device.destroy_buffer(&self.vertices[0].0);
+        device.destroy_buffer(&self.indices);
+    }
+}
@@ -1,8 +1,11 @@
-use crate::debug::VALIDATION_LAYER;
+use crate::debug::{ValidationSettings, VALIDATION_LAYER};
 use crate::device::Device;
-use crate::queue::Queue;
+use crate::format;
+use crate::memory::MemorySettings;
+use crate::queue::{Queue, Queues};
 use crate::surface::Surface;
 use erupt::{vk, DeviceLoader, ExtendableFromConst, ExtendableFromMut, InstanceLoader};
+use smallvec::SmallVec;
 use std::ffi::CStr;
 use std::sync::Arc;
 
@@ -13,23 +16,39 @@ pub struct PhysicalDevice {
 
 pub struct PhysicalDeviceInfo {
     pub queue_index: u32,
+    /// Meaningful low bits of timestamps written on `queue_index`, 0 when it
+    /// does not support timestamps.
+    pub timestamp_valid_bits: u32,
+    /// Family with transfer but neither graphics nor compute support, if any.
+    pub transfer_queue_index: Option<u32>,
+    /// Family with compute but no graphics support, if any.
+    pub compute_queue_index: Option<u32>,
     pub surface_format: vk::SurfaceFormatKHR,
     pub present_mode: vk::PresentModeKHR,
     pub device_properties: vk::PhysicalDeviceProperties,
+    pub features: vk::PhysicalDeviceFeatures,
     pub surface_capabilities: vk::SurfaceCapabilitiesKHR,
     pub raytracing_properties: vk::PhysicalDeviceRayTracingPipelinePropertiesKHR,
     pub accel_properties: vk::PhysicalDeviceAccelerationStructurePropertiesKHR,
+    /// Whether `VK_EXT_memory_budget` is available.
+    pub memory_budget: bool,
+    /// Whether `VK_KHR_dynamic_rendering` is available with its
+    /// `dynamicRendering` feature.
+    pub dynamic_rendering: bool,
+    /// Whether `VK_KHR_shader_non_semantic_info` is available.
+    pub shader_non_semantic_info: bool,
 }
 
 unsafe impl Send for PhysicalDeviceInfo {}
 unsafe impl Sync for PhysicalDeviceInfo {}
 
 impl PhysicalDevice {
+    /// The first device meeting the renderer's requirements, if any.
     pub fn select_one(
         instance: &InstanceLoader,
         surface: &Surface,
         device_extensions: &[*const i8],
-    ) -> Self {
+    ) -> Option<Self> {
         let devices = unsafe { instance.enumerate_physical_devices(None).unwrap() };
 
         devices
@@ -49,7 +68,6 @@ impl PhysicalDevice {
                 }
             })
             .next()
-            .unwrap_or_else(|| panic!("No supported devices found"))
     }
 
     fn supports_requirements(
@@ -58,11 +76,11 @@ impl PhysicalDevice {
         surface: &Surface,
         device_extensions: &[*const i8],
     ) -> Option<PhysicalDeviceInfo> {
-        let queue_family =
+        let queue_families =
             unsafe { instance.get_physical_device_queue_family_properties(physical_device, None) };
         let queue_family =
-            match queue_family
-                .into_iter()
+            match queue_families
+                .iter()
                 .enumerate()
                 .position(|(i, queue_family_properties)| {
                     let supports_surface = unsafe {
@@ -83,6 +101,23 @@ impl PhysicalDevice {
                 None => return None,
             };
 
+        let transfer_queue_index = queue_families
+            .iter()
+            .position(|queue_family_properties| {
+                let flags = queue_family_properties.queue_flags;
+                flags.contains(vk::QueueFlags::TRANSFER)
+                    && !flags.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+            })
+            .map(|index| index as u32);
+
+        let compute_queue_index = queue_families
+            .iter()
+            .position(|queue_family_properties| {
+                let flags = queue_family_properties.queue_flags;
+                flags.contains(vk::QueueFlags::COMPUTE) && !flags.contains(vk::QueueFlags::GRAPHICS)
+            })
+            .map(|index| index as u32);
+
         let formats = unsafe {
             instance
                 .get_physical_device_surface_formats_khr(physical_device, surface.handle(), None)
@@ -94,6 +129,12 @@ impl PhysicalDevice {
                 surface_format.format == vk::Format::B8G8R8A8_SRGB
                     && surface_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR_KHR
             })
+            .or_else(|| {
+                formats.iter().find(|surface_format| {
+                    format::is_srgb(surface_format.format)
+                        && surface_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR_KHR
+                })
+            })
             .or_else(|| formats.get(0))
         {
             Some(surface_format) => *surface_format,
@@ -130,6 +171,32 @@ impl PhysicalDevice {
             return None;
         }
 
+        let memory_budget = supported_device_extensions.iter().any(|properties| unsafe {
+            CStr::from_ptr(properties.extension_name.as_ptr())
+                == CStr::from_ptr(vk::EXT_MEMORY_BUDGET_EXTENSION_NAME)
+        });
+
+        let dynamic_rendering = supported_device_extensions.iter().any(|properties| unsafe {
+            CStr::from_ptr(properties.extension_name.as_ptr())
+                == CStr::from_ptr(vk::KHR_DYNAMIC_RENDERING_EXTENSION_NAME)
+        });
+
+        // The extension may be exposed with the feature unsupported.
+        let dynamic_rendering = dynamic_rendering && {
+            let mut dynamic_rendering_features =
+                vk::PhysicalDeviceDynamicRenderingFeaturesKHRBuilder::new().build();
+            let features2 = vk::PhysicalDeviceFeatures2Builder::new()
+                .extend_from(&mut dynamic_rendering_features);
+            unsafe { instance.get_physical_device_features2(physical_device, Some(*features2)) };
+            dynamic_rendering_features.dynamic_rendering != 0
+        };
+
+        let shader_non_semantic_info =
+            supported_device_extensions.iter().any(|properties| unsafe {
+                CStr::from_ptr(properties.extension_name.as_ptr())
+                    == CStr::from_ptr(vk::KHR_SHADER_NON_SEMANTIC_INFO_EXTENSION_NAME)
+            });
+
         let mut accel_properties =
             vk::PhysicalDeviceAccelerationStructurePropertiesKHRBuilder::new().build();
         let mut raytracing_properties =
@@ -149,14 +216,23 @@ impl PhysicalDevice {
                 .unwrap()
         };
 
+        let features = unsafe { instance.get_physical_device_features(physical_device) };
+
         Some(PhysicalDeviceInfo {
             queue_index: queue_family,
+            timestamp_valid_bits: queue_families[queue_family as usize].timestamp_valid_bits,
+            transfer_queue_index,
+            compute_queue_index,
             surface_format,
             present_mode,
             device_properties,
+            features,
             surface_capabilities,
             accel_properties,
             raytracing_properties,
+            memory_budget,
+            dynamic_rendering,
+            shader_non_semantic_info,
         })
     }
 
@@ -168,22 +244,40 @@ impl PhysicalDevice {
         &self,
         instance: Arc<InstanceLoader>,
         device_extensions: &[*const i8],
-    ) -> (Device, Queue) {
-        let queue_info = [vk::DeviceQueueCreateInfoBuilder::new()
-            .queue_family_index(self.info.queue_index)
-            .queue_priorities(&[1.0])];
-        let features = vk::PhysicalDeviceFeaturesBuilder::new();
+        settings: &ValidationSettings,
+        memory_settings: &MemorySettings,
+    ) -> (Device, Queues) {
+        let queue_priorities = [1.0];
+        let queue_info = std::iter::once(self.info.queue_index)
+            .chain(self.info.transfer_queue_index)
+            .chain(self.info.compute_queue_index)
+            .map(|queue_family_index| {
+                vk::DeviceQueueCreateInfoBuilder::new()
+                    .queue_family_index(queue_family_index)
+                    .queue_priorities(&queue_priorities)
+            })
+            .collect::<SmallVec<[_; 3]>>();
+        let features = vk::PhysicalDeviceFeaturesBuilder::new()
+            .pipeline_statistics_query(self.info.features.pipeline_statistics_query != 0)
+            .inherited_queries(self.info.features.inherited_queries != 0)
+            .image_cube_array(self.info.features.image_cube_array != 0);
 
         let mut device_layers = Vec::new();
 
-        if cfg!(debug_assertions) {
+        if settings.validation {
             device_layers.push(VALIDATION_LAYER)
         }
 
         let mut buffer_device_address_features =
             vk::PhysicalDeviceBufferDeviceAddressFeaturesBuilder::new().buffer_device_address(true);
         let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeaturesBuilder::new()
-            .runtime_descriptor_array(true);
+            .runtime_descriptor_array(true)
+            .shader_sampled_image_array_non_uniform_indexing(true)
+            .descriptor_binding_partially_bound(true)
+            .descriptor_binding_sampled_image_update_after_bind(true)
+            .descriptor_binding_update_unused_while_pending(true);
+        let mut timeline_semaphore_features =
+            vk::PhysicalDeviceTimelineSemaphoreFeaturesBuilder::new().timeline_semaphore(true);
         let mut reset_query_features =
             vk::PhysicalDeviceHostQueryResetFeaturesBuilder::new().host_query_reset(true);
         let mut acceleration_structure_features =
@@ -200,17 +294,43 @@ impl PhysicalDevice {
             .enabled_layer_names(&device_layers)
             .extend_from(&mut buffer_device_address_features)
             .extend_from(&mut indexing_features)
+            .extend_from(&mut timeline_semaphore_features)
             .extend_from(&mut reset_query_features)
             .extend_from(&mut acceleration_structure_features)
             .extend_from(&mut ray_tracing_features);
 
+        let mut dynamic_rendering_features =
+            vk::PhysicalDeviceDynamicRenderingFeaturesKHRBuilder::new().dynamic_rendering(true);
+        let device_info = if self.info.dynamic_rendering {
+            device_info.extend_from(&mut dynamic_rendering_features)
+        } else {
+            device_info
+        };
+
         let device =
             unsafe { DeviceLoader::new(&instance, self.handle, &device_info, None).unwrap() };
-        let device = Device::new(instance.clone(), device, self.handle);
+        let device = Device::new(
+            instance.clone(),
+            device,
+            self.handle,
+            settings.debug_utils_enabled(),
+            self.info.memory_budget,
+            self.info.dynamic_rendering,
+            self.info.features.image_cube_array != 0,
+            memory_settings,
+        );
 
-        let queue = unsafe { device.handle().get_device_queue(self.info.queue_index, 0) };
-        let queue = Queue::new(queue, device.clone(), self.info.queue_index);
+        let get_queue = |family_index| {
+            let queue = unsafe { device.handle().get_device_queue(family_index, 0) };
+            Queue::new(queue, device.clone(), family_index)
+        };
+
+        let queues = Queues {
+            graphics: get_queue(self.info.queue_index),
+            transfer: self.info.transfer_queue_index.map(get_queue),
+            compute: self.info.compute_queue_index.map(get_queue),
+        };
 
-        (device, queue)
+        (device, queues)
     }
 }
@@ -1,6 +1,8 @@
+use crate::render_pass::DEFAULT_ATTACHMENT_COUNT;
 use crate::resources::{PipelineLayout, RenderPass};
 use crate::shader::Shader;
 use erupt::vk;
+use smallvec::SmallVec;
 
 #[derive(Clone)]
 pub struct GraphicsPipelineInfo {
@@ -10,8 +12,60 @@ pub struct GraphicsPipelineInfo {
     pub vertex_shader: Shader,
     pub rasterizer: Option<Rasterizer>,
     pub layout: PipelineLayout,
-    pub render_pass: RenderPass,
-    pub subpass: u32,
+    pub target: PipelineTarget,
+    pub name: Option<Box<str>>,
+}
+
+/// What a graphics pipeline renders into.
+#[derive(Clone)]
+pub enum PipelineTarget {
+    RenderPass {
+        render_pass: RenderPass,
+        subpass: u32,
+    },
+    /// Attachments of a `begin_rendering` scope, with `VK_KHR_dynamic_rendering`.
+    Dynamic(RenderingFormats),
+}
+
+#[derive(Clone)]
+pub struct RenderingFormats {
+    pub colors: SmallVec<[vk::Format; DEFAULT_ATTACHMENT_COUNT]>,
+    pub depth: Option<vk::Format>,
+    pub samples: vk::SampleCountFlags,
+}
+
+impl PipelineTarget {
+    pub fn color_count(&self) -> usize {
+        match self {
+            PipelineTarget::RenderPass {
+                render_pass,
+                subpass,
+            } => render_pass.info().subpasses[*subpass as usize].colors.len(),
+            PipelineTarget::Dynamic(formats) => formats.colors.len(),
+        }
+    }
+
+    pub fn samples(&self) -> vk::SampleCountFlags {
+        match self {
+            PipelineTarget::RenderPass {
+                render_pass,
+                subpass,
+            } => {
+                // Every attachment of a subpass has the same sample count.
+                let info = render_pass.info();
+                let subpass = &info.subpasses[*subpass as usize];
+                subpass
+                    .colors
+                    .first()
+                    .copied()
+                    .or(subpass.depth)
+                    .map_or(vk::SampleCountFlags::_1, |attachment| {
+                        info.attachments[attachment].samples
+                    })
+            }
+            PipelineTarget::Dynamic(formats) => formats.samples,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -35,6 +89,13 @@ pub struct Rasterizer {
     pub front_face: vk::FrontFace,
     pub cull_mode: vk::CullModeFlags,
     pub polygon_mode: vk::PolygonMode,
+    /// `GREATER_OR_EQUAL` with a reverse-Z projection.
+    pub depth_compare: vk::CompareOp,
+    /// Disabled for transparent geometry, which is tested against the depth
+    /// of opaque geometry without occluding anything itself.
+    pub depth_write: bool,
+    /// Blends color attachments as `src * src_alpha + dst * (1 - src_alpha)`.
+    pub alpha_blend: bool,
 
     pub fragment_shader: Option<Shader>,
 }
@@ -5,6 +5,7 @@ pub use self::ray_tracing::*;
 use crate::image::Image;
 use crate::render_context::RenderContext;
 use crate::resources::{DescriptorSetLayout, Semaphore};
+use crate::shader::ShaderReloads;
 use erupt::vk;
 
 mod graphics_pipeline;
@@ -19,12 +20,15 @@ pub trait Pipeline {
         target_signal: &Semaphore,
         render_context: &mut RenderContext,
     );
+
+    fn reload_shaders(&mut self, reloads: &mut ShaderReloads, render_context: &RenderContext);
 }
 
 #[derive(Clone)]
 pub struct PipelineLayoutInfo {
     pub sets: Vec<DescriptorSetLayout>,
     pub push_constants: Vec<PushConstant>,
+    pub name: Option<Box<str>>,
 }
 
 #[derive(Clone)]
@@ -1,15 +1,17 @@
+use crate::device::Device;
 use crate::image::Image;
 use crate::pipeline::Pipeline;
 use crate::render_context::RenderContext;
 use crate::renderer::{raster_pass, Pass, RasterPass};
-use crate::resources::{Fence, Semaphore};
+use crate::resources::Semaphore;
+use crate::shader::ShaderReloads;
 use erupt::vk;
 use erupt::vk1_0::{Extent2D, Format};
 
 pub struct RasterPipeline {
     raster_pass: RasterPass,
     frame: u64,
-    fences: [Fence; 2],
+    frames_in_flight: [u64; 2],
 }
 
 impl RasterPipeline {
@@ -21,9 +23,14 @@ impl RasterPipeline {
         RasterPipeline {
             raster_pass: RasterPass::new(render_context, surface_format, extent),
             frame: 0,
-            fences: [render_context.create_fence(), render_context.create_fence()],
+            frames_in_flight: [0; 2],
         }
     }
+
+    /// Destroys the passes' resources, once the device is idle.
+    pub fn cleanup(&mut self, device: &Device) {
+        self.raster_pass.cleanup(device);
+    }
 }
 
 impl Pipeline for RasterPipeline {
@@ -34,11 +41,12 @@ impl Pipeline for RasterPipeline {
         target_signal: &Semaphore,
         render_context: &mut RenderContext,
     ) {
-        let fence = &self.fences[(self.frame % 2) as usize];
-        if self.frame > 1 {
-            render_context.wait_fences(&[fence], true);
-            render_context.reset_fences(&[fence]);
-        }
+        let frame_in_flight = (self.frame % 2) as usize;
+        render_context
+            .queue
+            .timeline()
+            .wait(self.frames_in_flight[frame_in_flight]);
+
         self.raster_pass.draw(
             raster_pass::Input {
                 target: target.clone(),
@@ -49,10 +57,15 @@ impl Pipeline for RasterPipeline {
                 target_wait.clone(),
             )],
             &[target_signal.clone()],
-            Some(fence),
             render_context,
         );
+        self.frames_in_flight[frame_in_flight] =
+            render_context.queue.timeline().last_submitted().value;
 
         self.frame += 1;
     }
+
+    fn reload_shaders(&mut self, reloads: &mut ShaderReloads, render_context: &RenderContext) {
+        self.raster_pass.reload_shaders(reloads, render_context);
+    }
 }
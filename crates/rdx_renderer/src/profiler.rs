@@ -0,0 +1,279 @@
+use crate::device::Device;
+use crate::encoder::Encoder;
+use crate::physical_device::PhysicalDeviceInfo;
+use crate::query::QueryPoolInfo;
+use crate::resources::QueryPool;
+use erupt::vk;
+
+/// Frames a query pool stays untouched before its results are read back.
+const PROFILER_FRAME_COUNT: usize = 3;
+const MAX_SCOPES_PER_FRAME: u32 = 64;
+
+const PIPELINE_STATISTICS: &[(vk::QueryPipelineStatisticFlags, &str)] = &[
+    (
+        vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS,
+        "vertex invocations",
+    ),
+    (
+        vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES,
+        "primitives",
+    ),
+    (
+        vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS,
+        "fragment invocations",
+    ),
+];
+
+/// A profiled scope, named in command buffer labels and diagnostics.
+#[derive(Clone, Copy, Debug)]
+pub struct ProfilerScopeInfo {
+    pub name: &'static str,
+    /// Random base of the scope's diagnostic ids: the timing uses it as is,
+    /// pipeline statistic `i` adds `i + 1`.
+    pub diagnostic_id: u128,
+}
+
+#[derive(Clone, Copy)]
+pub struct ProfilerScope {
+    index: Option<u32>,
+    statistics: bool,
+}
+
+/// Resolved timings of one profiled scope.
+#[derive(Clone)]
+pub struct GpuTiming {
+    pub scope: ProfilerScopeInfo,
+    pub milliseconds: f64,
+    pub statistics: Vec<(&'static str, u64)>,
+}
+
+struct ProfilerFrame {
+    timestamps: QueryPool,
+    statistics: Option<QueryPool>,
+    scopes: Vec<ProfilerScopeInfo>,
+    depth: u32,
+    statistics_scopes: Vec<u32>,
+}
+
+/// Measures GPU time of encoder scopes with timestamp queries.
+///
+/// Results lag `PROFILER_FRAME_COUNT` frames behind so reading them back never
+/// stalls. Without timestamp support on the graphics queue scopes are only
+/// labelled.
+pub struct GpuProfiler {
+    frames: Vec<ProfilerFrame>,
+    frame: usize,
+    /// Whether the current frame's queries are reset and can be written.
+    recording: bool,
+    timestamp_period: f64,
+    /// Valid timestamp bits, `None` without timestamp support.
+    timestamp_mask: Option<u64>,
+    timings: Vec<GpuTiming>,
+}
+
+impl GpuProfiler {
+    pub fn new(device: &Device, info: &PhysicalDeviceInfo) -> Self {
+        // Draws in secondary command buffers are only counted by queries they
+        // inherit.
+        let pipeline_statistics =
+            info.features.pipeline_statistics_query != 0 && info.features.inherited_queries != 0;
+
+        let frames = (0..PROFILER_FRAME_COUNT)
+            .map(|_| ProfilerFrame {
+                timestamps: device.create_query_pool(QueryPoolInfo {
+                    query_type: vk::QueryType::TIMESTAMP,
+                    count: MAX_SCOPES_PER_FRAME * 2,
+                    pipeline_statistics: vk::QueryPipelineStatisticFlags::empty(),
+                    name: Some("profiler timestamps".into()),
+                }),
+                statistics: if pipeline_statistics {
+                    Some(device.create_query_pool(QueryPoolInfo {
+                        query_type: vk::QueryType::PIPELINE_STATISTICS,
+                        count: MAX_SCOPES_PER_FRAME,
+                        pipeline_statistics: pipeline_statistic_flags(),
+                        name: Some("profiler pipeline statistics".into()),
+                    }))
+                } else {
+                    None
+                },
+                scopes: Vec::new(),
+                depth: 0,
+                statistics_scopes: Vec::new(),
+            })
+            .collect();
+
+        let timestamp_mask = match info.timestamp_valid_bits {
+            0 => None,
+            64 => Some(!0),
+            bits => Some((1 << bits) - 1),
+        };
+
+        GpuProfiler {
+            frames,
+            frame: 0,
+            recording: true,
+            timestamp_period: info.device_properties.limits.timestamp_period as f64,
+            timestamp_mask,
+            timings: Vec::new(),
+        }
+    }
+
+    /// Statistics counted by the pipeline statistics queries, for secondary
+    /// command buffers executed inside a scope to inherit.
+    pub fn pipeline_statistics(&self) -> vk::QueryPipelineStatisticFlags {
+        match self.frames[self.frame].statistics {
+            Some(_) => pipeline_statistic_flags(),
+            None => vk::QueryPipelineStatisticFlags::empty(),
+        }
+    }
+
+    /// Latest resolved timings, in the order the scopes were opened.
+    pub fn timings(&self) -> &[GpuTiming] {
+        &self.timings
+    }
+
+    /// Reads back the results of the frame that last used the next query pool
+    /// and resets it for recording. Queries still in flight are neither read
+    /// nor reset, the frame is then not profiled.
+    pub fn begin_frame(&mut self, device: &Device) {
+        self.frame = (self.frame + 1) % PROFILER_FRAME_COUNT;
+        let timestamp_period = self.timestamp_period;
+        let timestamp_mask = self.timestamp_mask.unwrap_or(0);
+        let frame = &mut self.frames[self.frame];
+
+        let scope_count = frame.scopes.len() as u32;
+        if scope_count > 0 {
+            let timestamps = device.get_query_results(&frame.timestamps, 0..scope_count * 2);
+            let statistics = match &frame.statistics {
+                Some(pool) if !frame.statistics_scopes.is_empty() => {
+                    Some(device.get_query_results(pool, 0..frame.statistics_scopes.len() as u32))
+                }
+                _ => None,
+            };
+
+            let (timestamps, statistics) = match (timestamps, statistics) {
+                (Some(timestamps), None) => (timestamps, None),
+                (Some(timestamps), Some(Some(statistics))) => (timestamps, Some(statistics)),
+                _ => {
+                    self.recording = false;
+                    return;
+                }
+            };
+
+            self.timings = frame
+                .scopes
+                .iter()
+                .enumerate()
+                .map(|(i, scope)| {
+                    // Only the valid bits count, the difference wraps with them.
+                    let ticks =
+                        timestamps[i * 2 + 1].wrapping_sub(timestamps[i * 2]) & timestamp_mask;
+                    let statistics = frame
+                        .statistics_scopes
+                        .iter()
+                        .position(|&statistics_scope| statistics_scope == i as u32)
+                        .and_then(|query| {
+                            let values = statistics.as_ref()?;
+                            let offset = query * PIPELINE_STATISTICS.len();
+                            Some(
+                                PIPELINE_STATISTICS
+                                    .iter()
+                                    .zip(&values[offset..offset + PIPELINE_STATISTICS.len()])
+                                    .map(|((_, name), &value)| (*name, value))
+                                    .collect(),
+                            )
+                        })
+                        .unwrap_or_default();
+
+                    GpuTiming {
+                        scope: *scope,
+                        milliseconds: ticks as f64 * timestamp_period / 1_000_000.0,
+                        statistics,
+                    }
+                })
+                .collect();
+
+            device.reset_query_pool(&frame.timestamps, 0..scope_count * 2);
+            if let Some(pool) = &frame.statistics {
+                if !frame.statistics_scopes.is_empty() {
+                    device.reset_query_pool(pool, 0..frame.statistics_scopes.len() as u32);
+                }
+            }
+        }
+
+        frame.scopes.clear();
+        frame.statistics_scopes.clear();
+        frame.depth = 0;
+        self.recording = true;
+    }
+
+    /// Opens a named scope, also labelled in the command buffer. Outermost
+    /// scopes collect pipeline statistics when the device supports them.
+    pub fn begin_scope(
+        &mut self,
+        encoder: &mut Encoder<'_>,
+        scope: &ProfilerScopeInfo,
+    ) -> ProfilerScope {
+        encoder.push_label(scope.name);
+
+        let frame = &mut self.frames[self.frame];
+        let index = frame.scopes.len() as u32;
+        if !self.recording || self.timestamp_mask.is_none() || index >= MAX_SCOPES_PER_FRAME {
+            return ProfilerScope {
+                index: None,
+                statistics: false,
+            };
+        }
+        frame.scopes.push(*scope);
+
+        encoder.write_timestamp(
+            vk::PipelineStageFlagBits::TOP_OF_PIPE,
+            frame.timestamps.clone(),
+            index * 2,
+        );
+
+        let statistics = match &frame.statistics {
+            Some(pool) if frame.depth == 0 => {
+                encoder.begin_query(pool.clone(), frame.statistics_scopes.len() as u32);
+                frame.statistics_scopes.push(index);
+                true
+            }
+            _ => false,
+        };
+
+        frame.depth += 1;
+
+        ProfilerScope {
+            index: Some(index),
+            statistics,
+        }
+    }
+
+    pub fn end_scope(&mut self, encoder: &mut Encoder<'_>, scope: ProfilerScope) {
+        if let Some(index) = scope.index {
+            let frame = &mut self.frames[self.frame];
+            frame.depth -= 1;
+
+            if scope.statistics {
+                if let Some(pool) = &frame.statistics {
+                    encoder.end_query(pool.clone(), frame.statistics_scopes.len() as u32 - 1);
+                }
+            }
+
+            encoder.write_timestamp(
+                vk::PipelineStageFlagBits::BOTTOM_OF_PIPE,
+                frame.timestamps.clone(),
+                index * 2 + 1,
+            );
+        }
+
+        encoder.pop_label();
+    }
+}
+
+fn pipeline_statistic_flags() -> vk::QueryPipelineStatisticFlags {
+    PIPELINE_STATISTICS.iter().fold(
+        vk::QueryPipelineStatisticFlags::empty(),
+        |flags, (flag, _)| flags | *flag,
+    )
+}
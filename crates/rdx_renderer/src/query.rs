@@ -0,0 +1,21 @@
+use erupt::vk;
+
+#[derive(Clone)]
+pub struct QueryPoolInfo {
+    pub query_type: vk::QueryType,
+    pub count: u32,
+    pub pipeline_statistics: vk::QueryPipelineStatisticFlags,
+    pub name: Option<Box<str>>,
+}
+
+impl QueryPoolInfo {
+    /// Number of `u64` values written per query.
+    pub fn values_per_query(&self) -> usize {
+        match self.query_type {
+            vk::QueryType::PIPELINE_STATISTICS => {
+                self.pipeline_statistics.bits().count_ones() as usize
+            }
+            _ => 1,
+        }
+    }
+}
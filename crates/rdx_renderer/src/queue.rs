@@ -1,17 +1,41 @@
 use crate::command_buffer::CommandBuffer;
+use crate::command_pool::ThreadCommandPools;
 use crate::device::Device;
 use crate::encoder::Encoder;
 use crate::resources::{Fence, Semaphore};
 use crate::swapchain::SwapchainImage;
+use crate::timeline::{GpuTimeline, TimelinePoint};
 use erupt::vk;
 use erupt::vk::{PipelineStageFlags, PresentInfoKHRBuilder};
+use erupt::{ExtendableFromConst, ExtendableFromMut};
 use smallvec::SmallVec;
 
+/// Every queue created with the device. The transfer and compute queues exist
+/// only when the device exposes dedicated families for them.
+pub struct Queues {
+    pub graphics: Queue,
+    pub transfer: Option<Queue>,
+    pub compute: Option<Queue>,
+}
+
+/// Semaphores and fence of a `Queue::submit`. Binary semaphores take no
+/// value, so timeline waits and signals are listed separately.
+#[derive(Clone, Copy, Default)]
+pub struct SubmitInfo<'a> {
+    pub wait: &'a [(PipelineStageFlags, Semaphore)],
+    pub timeline_wait: &'a [(PipelineStageFlags, TimelinePoint)],
+    pub signal: &'a [Semaphore],
+    pub timeline_signal: &'a [TimelinePoint],
+    pub fence: Option<&'a Fence>,
+}
+
 pub struct Queue {
     handle: vk::Queue,
     pool: vk::CommandPool,
     device: Device,
     family_index: u32,
+    timeline: GpuTimeline,
+    secondary_pools: ThreadCommandPools,
 }
 
 impl Queue {
@@ -19,11 +43,27 @@ impl Queue {
         Queue {
             handle,
             pool: vk::CommandPool::null(),
+            timeline: GpuTimeline::new(&device),
+            secondary_pools: ThreadCommandPools::new(device.clone(), family_index),
             device,
             family_index,
         }
     }
 
+    pub fn family_index(&self) -> u32 {
+        self.family_index
+    }
+
+    /// Signalled by every submission to this queue.
+    pub fn timeline(&self) -> &GpuTimeline {
+        &self.timeline
+    }
+
+    /// Pools for recording secondary command buffers from several threads.
+    pub fn secondary_pools(&self) -> &ThreadCommandPools {
+        &self.secondary_pools
+    }
+
     pub fn create_enconder(&mut self) -> Encoder<'static> {
         if self.pool.is_null() {
             self.pool = unsafe {
@@ -57,55 +97,95 @@ impl Queue {
         Encoder::new(command_buffer)
     }
 
-    pub fn submit(
-        &mut self,
-        wait: &[(PipelineStageFlags, Semaphore)],
-        command_buffer: CommandBuffer,
-        signal: &[Semaphore],
-        fence: Option<&Fence>,
-    ) {
-        let (wait_stages, wait_semaphores) = wait
-            .iter()
-            .map(|(stage, semaphore)| (*stage, semaphore.handle()))
-            .unzip::<_, _, SmallVec<[_; 8]>, SmallVec<[_; 8]>>();
-
-        let signal_semaphores = signal
-            .iter()
-            .map(|semaphore| semaphore.handle())
-            .collect::<SmallVec<[_; 8]>>();
-
-        unsafe {
-            self.device
-                .handle()
-                .queue_submit(
-                    self.handle,
-                    &[vk::SubmitInfoBuilder::new()
-                        .wait_semaphores(&wait_semaphores)
-                        .wait_dst_stage_mask(&wait_stages)
-                        .signal_semaphores(&signal_semaphores)
-                        .command_buffers(&[command_buffer.handle()])],
-                    fence.map(|fence| fence.handle()),
-                )
-                .unwrap()
+    /// Submits `command_buffer` and signals the queue timeline, returning the
+    /// point reached once it completes.
+    pub fn submit(&mut self, command_buffer: CommandBuffer, info: SubmitInfo<'_>) -> TimelinePoint {
+        let SubmitInfo {
+            wait,
+            timeline_wait,
+            signal,
+            timeline_signal,
+            fence,
+        } = info;
+        let point = self.timeline.next_point();
+
+        let mut wait_stages = SmallVec::<[_; 8]>::new();
+        let mut wait_semaphores = SmallVec::<[_; 8]>::new();
+        let mut wait_values = SmallVec::<[_; 8]>::new();
+        for (stage, semaphore) in wait {
+            wait_stages.push(*stage);
+            wait_semaphores.push(semaphore.handle());
+            wait_values.push(0);
+        }
+        for (stage, point) in timeline_wait {
+            wait_stages.push(*stage);
+            wait_semaphores.push(point.semaphore.handle());
+            wait_values.push(point.value);
         }
+
+        let mut signal_semaphores = SmallVec::<[_; 8]>::new();
+        let mut signal_values = SmallVec::<[_; 8]>::new();
+        for semaphore in signal {
+            signal_semaphores.push(semaphore.handle());
+            signal_values.push(0);
+        }
+        for point in timeline_signal.iter().chain(std::iter::once(&point)) {
+            signal_semaphores.push(point.semaphore.handle());
+            signal_values.push(point.value);
+        }
+
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfoBuilder::new()
+            .wait_semaphore_values(&wait_values)
+            .signal_semaphore_values(&signal_values);
+
+        // A lost device keeps its pools until it is destroyed.
+        if self.device.is_lost() {
+            return point;
+        }
+
+        // Secondary buffers recorded so far are executed by this submission.
+        self.secondary_pools.reset_completed(&self.timeline);
+        self.secondary_pools.submitted(point.value);
+
+        let result = unsafe {
+            self.device.handle().queue_submit(
+                self.handle,
+                &[vk::SubmitInfoBuilder::new()
+                    .wait_semaphores(&wait_semaphores)
+                    .wait_dst_stage_mask(&wait_stages)
+                    .signal_semaphores(&signal_semaphores)
+                    .command_buffers(&[command_buffer.handle()])
+                    .extend_from(&mut timeline_info)],
+                fence.map(|fence| fence.handle()),
+            )
+        };
+        self.device.check_lost(result);
+
+        point
     }
 
     pub fn present(&mut self, swapchain_image: SwapchainImage) {
-        unsafe {
-            self.device
-                .handle()
-                .queue_present_khr(
-                    self.handle,
-                    &PresentInfoKHRBuilder::new()
-                        .swapchains(&[swapchain_image.handle()])
-                        .wait_semaphores(&[swapchain_image.info().signal.handle()])
-                        .image_indices(&[swapchain_image.index()]),
-                )
-                .unwrap();
+        if self.device.is_lost() {
+            return;
         }
+
+        let result = unsafe {
+            self.device.handle().queue_present_khr(
+                self.handle,
+                &PresentInfoKHRBuilder::new()
+                    .swapchains(&[swapchain_image.handle()])
+                    .wait_semaphores(&[swapchain_image.info().signal.handle()])
+                    .image_indices(&[swapchain_image.index()]),
+            )
+        };
+        self.device.check_lost(result);
     }
 
     pub fn cleanup(&mut self, device: &Device) {
+        // The device is idle or lost, every point reads as reached and this
+        // runs all remaining deferred work.
+        self.timeline.poll();
+        self.secondary_pools.cleanup(device);
         unsafe { device.handle().destroy_command_pool(Some(self.pool), None) }
     }
 }
@@ -1,10 +1,26 @@
+use crate::camera::CameraBuffer;
 use crate::device::Device;
-use crate::queue::Queue;
+use crate::frame_allocator::{FrameAllocator, DEFAULT_FRAME_ALLOCATOR_SIZE};
+use crate::physical_device::PhysicalDeviceInfo;
+use crate::profiler::GpuProfiler;
+use crate::queue::{Queue, Queues};
+use crate::scene::RenderScene;
+use crate::upload::{UploadManager, DEFAULT_STAGING_SIZE};
+use bevy::tasks::ComputeTaskPool;
 use std::ops::Deref;
 
 pub struct RenderContext {
     pub device: Device,
     pub queue: Queue,
+    pub transfer_queue: Option<Queue>,
+    pub compute_queue: Option<Queue>,
+    pub profiler: GpuProfiler,
+    pub uploads: UploadManager,
+    pub frame_allocator: FrameAllocator,
+    pub camera: CameraBuffer,
+    pub scene: RenderScene,
+    /// Threads recording secondary command buffers.
+    pub task_pool: ComputeTaskPool,
 }
 
 impl Deref for RenderContext {
@@ -16,13 +32,71 @@ impl Deref for RenderContext {
 }
 
 impl RenderContext {
-    pub fn new(device: Device, queue: Queue) -> Self {
-        RenderContext { device, queue }
+    pub fn new(
+        device: Device,
+        queues: Queues,
+        info: &PhysicalDeviceInfo,
+        task_pool: ComputeTaskPool,
+    ) -> Self {
+        let frame_allocator = FrameAllocator::new(&device, info, DEFAULT_FRAME_ALLOCATOR_SIZE);
+
+        RenderContext {
+            profiler: GpuProfiler::new(&device, info),
+            uploads: UploadManager::new(&device, DEFAULT_STAGING_SIZE),
+            camera: CameraBuffer::new(&device, &frame_allocator),
+            frame_allocator,
+            scene: RenderScene::new(&device, info, queues.graphics.timeline()),
+            device,
+            queue: queues.graphics,
+            transfer_queue: queues.transfer,
+            compute_queue: queues.compute,
+            task_pool,
+        }
+    }
+
+    /// The dedicated transfer queue, or the graphics queue without one.
+    pub fn transfer_queue(&mut self) -> &mut Queue {
+        match &mut self.transfer_queue {
+            Some(queue) => queue,
+            None => &mut self.queue,
+        }
+    }
+
+    /// The dedicated compute queue, or the graphics queue without one. Work
+    /// submitted here must be acquired by the graphics queue like uploads are.
+    pub fn compute_queue(&mut self) -> &mut Queue {
+        match &mut self.compute_queue {
+            Some(queue) => queue,
+            None => &mut self.queue,
+        }
+    }
+
+    /// Runs deferred work whose timeline points have been reached.
+    pub fn poll_timelines(&self) {
+        self.queue.timeline().poll();
+        for queue in self.transfer_queue.iter().chain(&self.compute_queue) {
+            queue.timeline().poll();
+        }
     }
 
+    /// Destroys everything created from the device, and the device. A lost
+    /// device is not waited on; every timeline point then reads as reached,
+    /// so deferred work runs right away, and only destroy and free calls are
+    /// made, which Vulkan allows after device loss.
     pub fn destroy_context(&mut self) {
-        self.device.wait_idle();
+        if !self.device.is_lost() {
+            self.device.wait_idle();
+        }
+        self.uploads.cleanup();
+        self.frame_allocator.cleanup();
+        self.scene.cleanup(&self.device);
         self.queue.cleanup(&self.device);
+        if let Some(queue) = &mut self.transfer_queue {
+            queue.cleanup(&self.device);
+        }
+        if let Some(queue) = &mut self.compute_queue {
+            queue.cleanup(&self.device);
+        }
         self.device.cleanup();
     }
 }
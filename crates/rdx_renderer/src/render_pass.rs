@@ -1,3 +1,4 @@
+use crate::image::ImageView;
 use erupt::vk;
 use smallvec::SmallVec;
 
@@ -8,6 +9,8 @@ pub const DEFAULT_SUBPASS_COUNT: usize = 1;
 pub struct RenderPassInfo {
     pub attachments: SmallVec<[AttachmentInfo; DEFAULT_ATTACHMENT_COUNT]>,
     pub subpasses: SmallVec<[Subpass; DEFAULT_SUBPASS_COUNT]>,
+    pub dependencies: SmallVec<[SubpassDependency; DEFAULT_SUBPASS_COUNT]>,
+    pub name: Option<Box<str>>,
 }
 
 #[derive(Clone)]
@@ -20,21 +23,117 @@ pub struct AttachmentInfo {
     pub final_layout: vk::ImageLayout,
 }
 
+/// Attachment indices used by one subpass.
+///
+/// `resolves` is either empty or holds one attachment per entry of `colors`,
+/// which the multisampled color is resolved into at the end of the subpass.
+/// `inputs` are read in the fragment shader with `subpassLoad`.
 #[derive(Clone)]
 pub struct Subpass {
     pub colors: SmallVec<[usize; DEFAULT_ATTACHMENT_COUNT]>,
     pub depth: Option<usize>,
+    pub inputs: SmallVec<[usize; DEFAULT_ATTACHMENT_COUNT]>,
+    pub resolves: SmallVec<[usize; DEFAULT_ATTACHMENT_COUNT]>,
 }
 
+impl Subpass {
+    pub fn new(colors: &[usize], depth: Option<usize>) -> Self {
+        Subpass {
+            colors: colors.iter().copied().collect(),
+            depth,
+            inputs: SmallVec::new(),
+            resolves: SmallVec::new(),
+        }
+    }
+}
+
+/// Execution and memory dependency between two subpasses. `None` stands for
+/// commands outside the render pass.
 #[derive(Clone)]
 pub struct SubpassDependency {
     pub src: Option<usize>,
     pub dst: Option<usize>,
     pub src_stages: vk::PipelineStageFlags,
     pub dst_stages: vk::PipelineStageFlags,
+    pub src_access: vk::AccessFlags,
+    pub dst_access: vk::AccessFlags,
+    /// Limits the dependency to the same framebuffer region, as needed by
+    /// input attachments.
+    pub by_region: bool,
+}
+
+impl SubpassDependency {
+    /// Makes the color and depth writes of `src` visible to input attachment
+    /// reads in `dst`.
+    pub fn input_attachments(src: usize, dst: usize) -> Self {
+        SubpassDependency {
+            src: Some(src),
+            dst: Some(dst),
+            src_stages: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            dst_stages: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_access: vk::AccessFlags::INPUT_ATTACHMENT_READ,
+            by_region: true,
+        }
+    }
+
+    /// Orders the first attachment writes of `dst` after work submitted
+    /// before the render pass, such as the swapchain image acquire and the
+    /// previous frame's depth writes.
+    pub fn external(dst: usize) -> Self {
+        SubpassDependency {
+            src: None,
+            dst: Some(dst),
+            src_stages: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            dst_stages: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+            src_access: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_access: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            by_region: false,
+        }
+    }
 }
 
 pub enum ClearValue {
     Color(f32, f32, f32, f32),
     DepthStencil(f32, u32),
 }
+
+impl From<&ClearValue> for vk::ClearValue {
+    fn from(clear: &ClearValue) -> Self {
+        match *clear {
+            ClearValue::Color(r, g, b, a) => vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [r, g, b, a],
+                },
+            },
+            ClearValue::DepthStencil(depth, stencil) => vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue { depth, stencil },
+            },
+        }
+    }
+}
+
+/// An attachment of a dynamic rendering scope, see `Encoder::begin_rendering`.
+pub struct RenderingAttachment<'a> {
+    pub view: &'a ImageView,
+    pub layout: vk::ImageLayout,
+    pub load_op: vk::AttachmentLoadOp,
+    pub store_op: vk::AttachmentStoreOp,
+    pub clear: ClearValue,
+    /// Single sampled view the attachment is resolved into, and its layout.
+    pub resolve: Option<(&'a ImageView, vk::ImageLayout)>,
+}
+
+/// Attachments rendered to between `begin_rendering` and `end_rendering`.
+/// Layout transitions are not implied and must be recorded with barriers.
+pub struct RenderingInfo<'a> {
+    pub render_area: vk::Rect2D,
+    pub colors: &'a [RenderingAttachment<'a>],
+    pub depth: Option<&'a RenderingAttachment<'a>>,
+}
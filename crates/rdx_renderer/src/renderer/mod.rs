@@ -1,12 +1,18 @@
 pub use self::pass::*;
 
-use crate::debug::DebugMessenger;
+use crate::camera::Camera;
+use crate::debug::{DebugMessenger, ValidationErrors, ValidationSettings};
 use crate::instance;
+use crate::memory::{MemoryReport, MemorySettings};
 use crate::physical_device::PhysicalDevice;
 use crate::pipeline::{Pipeline, RasterPipeline};
+use crate::profiler::GpuTiming;
 use crate::render_context::RenderContext;
+use crate::shader::ShaderWatcher;
 use crate::surface::Surface;
 use crate::swapchain::Swapchain;
+use bevy::tasks::ComputeTaskPool;
+use bevy::transform::components::GlobalTransform;
 use erupt::{vk, EntryLoader, InstanceLoader};
 use parking_lot::Mutex;
 use std::sync::Arc;
@@ -14,6 +20,20 @@ use winit::window::Window;
 
 mod pass;
 
+/// Sent after the renderer recovered from `VK_ERROR_DEVICE_LOST`. Every GPU
+/// resource from before is gone; systems owning GPU copies of scene data must
+/// upload them again.
+#[derive(Clone, Copy, Debug)]
+pub struct DeviceLost {
+    /// Number of devices created so far, including the new one.
+    pub generation: u64,
+}
+
+/// Send to have the device marked lost right before the next frame is drawn,
+/// exercising the same recovery as a real `VK_ERROR_DEVICE_LOST`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InjectDeviceLost;
+
 pub struct Renderer {
     surface: Surface,
     swapchain: Swapchain,
@@ -21,50 +41,108 @@ pub struct Renderer {
     physical_device: PhysicalDevice,
     render_context: RenderContext,
     pipeline: RasterPipeline,
+    view: Option<(Camera, GlobalTransform)>,
+    shader_watcher: Option<ShaderWatcher>,
+    validation_settings: ValidationSettings,
+    memory_settings: MemorySettings,
+    task_pool: ComputeTaskPool,
+    generation: u64,
     instance: Arc<InstanceLoader>,
     entry: EntryLoader,
 }
 
 impl Renderer {
-    pub fn new(window: &Window) -> Self {
-        let entry = EntryLoader::new().unwrap();
-        let instance = Arc::new(instance::create_instance(window, &entry));
-        let debug_messenger = DebugMessenger::new(&instance);
+    pub fn new(
+        window: &Window,
+        validation_settings: ValidationSettings,
+        memory_settings: MemorySettings,
+        task_pool: ComputeTaskPool,
+    ) -> Self {
+        Self::try_new(window, validation_settings, memory_settings, task_pool)
+            .unwrap_or_else(|| panic!("No supported devices found"))
+    }
+
+    /// Like `new`, but `None` without a Vulkan loader or a device meeting the
+    /// renderer's requirements.
+    pub fn try_new(
+        window: &Window,
+        mut validation_settings: ValidationSettings,
+        memory_settings: MemorySettings,
+        task_pool: ComputeTaskPool,
+    ) -> Option<Self> {
+        let entry = EntryLoader::new().ok()?;
+        let instance = Arc::new(instance::create_instance(
+            window,
+            &entry,
+            &mut validation_settings,
+        ));
+        let mut debug_messenger = DebugMessenger::new(&instance, &validation_settings);
         let surface = Surface::new(&instance, window);
 
-        let device_extensions = vec![
-            vk::KHR_SWAPCHAIN_EXTENSION_NAME,
-            vk::KHR_ACCELERATION_STRUCTURE_EXTENSION_NAME,
-            vk::KHR_RAY_TRACING_PIPELINE_EXTENSION_NAME,
-            vk::KHR_BUFFER_DEVICE_ADDRESS_EXTENSION_NAME,
-            vk::KHR_DEFERRED_HOST_OPERATIONS_EXTENSION_NAME,
-        ];
-        let physical_device = PhysicalDevice::select_one(&instance, &surface, &device_extensions);
-        let (device, queue) = physical_device.create_device(instance.clone(), &device_extensions);
-        let mut render_context = RenderContext::new(device, queue);
-
-        let mut swapchain = render_context.create_swapchain(&surface);
-        swapchain.configure(&render_context.device, physical_device.info());
-
-        let pipeline = RasterPipeline::new(
-            &render_context,
-            physical_device.info().surface_format.format,
-            physical_device.info().surface_capabilities.current_extent,
+        let device_objects = create_device_objects(
+            &instance,
+            &surface,
+            &validation_settings,
+            &memory_settings,
+            &task_pool,
         );
+        let (physical_device, render_context, swapchain, pipeline) = match device_objects {
+            Some(device_objects) => device_objects,
+            None => unsafe {
+                instance.destroy_surface_khr(Some(surface.handle()), None);
+                debug_messenger.destroy(&instance);
+                instance.destroy_instance(None);
+                return None;
+            },
+        };
 
-        Renderer {
+        Some(Renderer {
             surface,
             swapchain,
             debug_messenger,
             physical_device,
             render_context,
             pipeline,
+            view: None,
+            shader_watcher: ShaderWatcher::new(),
+            validation_settings,
+            memory_settings,
+            task_pool,
+            generation: 1,
             instance,
             entry,
-        }
+        })
+    }
+
+    /// Number of devices created so far; changes when the device was lost
+    /// and recreated.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Marks the device lost; the next `draw` recreates it.
+    pub fn inject_device_lost(&self) {
+        self.render_context.device.inject_device_lost();
+    }
+
+    /// Sets the view used by the next frames, `None` to draw in clip space.
+    pub fn set_camera(&mut self, view: Option<(&Camera, &GlobalTransform)>) {
+        self.view = view.map(|(camera, transform)| (camera.clone(), *transform));
     }
 
     pub fn draw(&mut self) {
+        if self.render_context.device.is_lost() {
+            self.recover_device();
+            return;
+        }
+
+        self.render_context.poll_timelines();
+
+        if let Some(mut reloads) = self.shader_watcher.as_ref().and_then(ShaderWatcher::poll) {
+            self.pipeline
+                .reload_shaders(&mut reloads, &self.render_context);
+        }
+
         let swapchain_image = loop {
             if let Some(swapchain_image) = self
                 .swapchain
@@ -72,10 +150,46 @@ impl Renderer {
             {
                 break swapchain_image;
             }
+            if self.render_context.device.is_lost() {
+                return;
+            }
             self.swapchain
                 .configure(&self.render_context.device, self.physical_device.info());
         };
 
+        self.render_context
+            .profiler
+            .begin_frame(&self.render_context.device);
+
+        let RenderContext {
+            uploads,
+            queue,
+            transfer_queue,
+            ..
+        } = &mut self.render_context;
+        uploads.flush(queue, transfer_queue.as_mut());
+
+        let extent = swapchain_image.info().image.info().extent.into_2d();
+        let aspect_ratio = extent.width as f32 / extent.height.max(1) as f32;
+        self.render_context.camera.write(
+            &mut self.render_context.frame_allocator,
+            self.view
+                .as_ref()
+                .map(|(camera, transform)| (camera, transform)),
+            aspect_ratio,
+        );
+
+        self.render_context.scene.prepare(
+            &self.render_context.device,
+            &mut self.render_context.frame_allocator,
+            self.view
+                .as_ref()
+                .map(|(_, transform)| transform.translation),
+        );
+
+        // Before the frame's submissions read it.
+        self.render_context.frame_allocator.flush();
+
         self.pipeline.draw(
             swapchain_image.info().image.clone(),
             &swapchain_image.info().wait,
@@ -83,13 +197,59 @@ impl Renderer {
             &mut self.render_context,
         );
 
+        let frame_end = self.render_context.queue.timeline().last_submitted();
+        self.render_context.scene.end_frame(frame_end.clone());
+        self.render_context.frame_allocator.end_frame(frame_end);
+
         self.render_context.queue.present(swapchain_image);
     }
+
+    /// Tears down everything created from the lost device and creates it
+    /// again. The instance, surface and debug messenger are kept.
+    fn recover_device(&mut self) {
+        tracing::warn!("recreating renderer after device loss");
+
+        self.pipeline.cleanup(&self.render_context.device);
+        self.render_context.destroy_context();
+
+        let (physical_device, render_context, swapchain, pipeline) = create_device_objects(
+            &self.instance,
+            &self.surface,
+            &self.validation_settings,
+            &self.memory_settings,
+            &self.task_pool,
+        )
+        .unwrap_or_else(|| panic!("No supported devices found"));
+
+        self.physical_device = physical_device;
+        self.render_context = render_context;
+        self.swapchain = swapchain;
+        self.pipeline = pipeline;
+        self.generation += 1;
+    }
+
+    pub(crate) fn render_context_mut(&mut self) -> &mut RenderContext {
+        &mut self.render_context
+    }
+
+    pub fn validation_errors(&self) -> &ValidationErrors {
+        self.debug_messenger.errors()
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        self.render_context.memory_report()
+    }
+
+    pub fn gpu_timings(&self) -> &[GpuTiming] {
+        self.render_context.profiler.timings()
+    }
 }
 
 impl Drop for Renderer {
     fn drop(&mut self) {
         unsafe {
+            self.render_context.device.wait_idle();
+            self.pipeline.cleanup(&self.render_context.device);
             self.render_context.destroy_context();
             self.instance
                 .destroy_surface_khr(Some(self.surface.handle()), None);
@@ -98,3 +258,145 @@ impl Drop for Renderer {
         }
     }
 }
+
+fn create_device_objects(
+    instance: &Arc<InstanceLoader>,
+    surface: &Surface,
+    validation_settings: &ValidationSettings,
+    memory_settings: &MemorySettings,
+    task_pool: &ComputeTaskPool,
+) -> Option<(PhysicalDevice, RenderContext, Swapchain, RasterPipeline)> {
+    let mut device_extensions = vec![
+        vk::KHR_SWAPCHAIN_EXTENSION_NAME,
+        vk::KHR_ACCELERATION_STRUCTURE_EXTENSION_NAME,
+        vk::KHR_RAY_TRACING_PIPELINE_EXTENSION_NAME,
+        vk::KHR_BUFFER_DEVICE_ADDRESS_EXTENSION_NAME,
+        vk::KHR_DEFERRED_HOST_OPERATIONS_EXTENSION_NAME,
+    ];
+    let physical_device = PhysicalDevice::select_one(instance, surface, &device_extensions)?;
+    if validation_settings.validation && validation_settings.debug_printf {
+        if physical_device.info().shader_non_semantic_info {
+            device_extensions.push(vk::KHR_SHADER_NON_SEMANTIC_INFO_EXTENSION_NAME);
+        } else {
+            tracing::warn!(
+                "VK_KHR_shader_non_semantic_info is not supported, debugPrintfEXT won't work"
+            );
+        }
+    }
+    if physical_device.info().memory_budget {
+        device_extensions.push(vk::EXT_MEMORY_BUDGET_EXTENSION_NAME);
+    }
+    if physical_device.info().dynamic_rendering {
+        device_extensions.push(vk::KHR_DYNAMIC_RENDERING_EXTENSION_NAME);
+    }
+    let (device, queues) = physical_device.create_device(
+        instance.clone(),
+        &device_extensions,
+        validation_settings,
+        memory_settings,
+    );
+    let render_context =
+        RenderContext::new(device, queues, physical_device.info(), task_pool.clone());
+
+    let mut swapchain = render_context.create_swapchain(surface);
+    swapchain.configure(&render_context.device, physical_device.info());
+
+    let pipeline = RasterPipeline::new(
+        &render_context,
+        physical_device.info().surface_format.format,
+        physical_device.info().surface_capabilities.current_extent,
+    );
+
+    Some((physical_device, render_context, swapchain, pipeline))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use bevy::tasks::TaskPool;
+    use winit::event_loop::EventLoop;
+    use winit::platform::unix::EventLoopExtUnix;
+    use winit::window::WindowBuilder;
+
+    /// A renderer on a hidden window, or `None` when there is no display or
+    /// no supported Vulkan device. Bind the tuple so the renderer drops first.
+    fn test_renderer(
+        validation_settings: ValidationSettings,
+    ) -> Option<(EventLoop<()>, Window, Renderer)> {
+        if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+            eprintln!("skipping: no display");
+            return None;
+        }
+
+        let event_loop = EventLoop::<()>::new_any_thread();
+        let window = WindowBuilder::new()
+            .with_visible(false)
+            .build(&event_loop)
+            .unwrap();
+        let renderer = Renderer::try_new(
+            &window,
+            validation_settings,
+            MemorySettings::default(),
+            ComputeTaskPool(TaskPool::new()),
+        );
+        match renderer {
+            Some(renderer) => Some((event_loop, window, renderer)),
+            None => {
+                eprintln!("skipping: no supported Vulkan device");
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn device_loss_recreates_context() {
+        let (_event_loop, _window, mut renderer) = match test_renderer(ValidationSettings::default())
+        {
+            Some(test) => test,
+            None => return,
+        };
+
+        renderer.draw();
+        assert_eq!(renderer.generation(), 1);
+
+        let lost_device = renderer.render_context.device.clone();
+        renderer.inject_device_lost();
+        assert!(lost_device.is_lost());
+
+        // The first draw after the loss only recovers.
+        renderer.draw();
+        assert_eq!(renderer.generation(), 2);
+        assert!(!renderer.render_context.device.is_lost());
+
+        renderer.draw();
+        assert!(!renderer.render_context.device.is_lost());
+        assert!(lost_device.is_lost());
+    }
+
+    #[test]
+    fn frame_has_no_validation_errors() {
+        let validation_settings = ValidationSettings {
+            validation: true,
+            capture_errors: true,
+            ..Default::default()
+        };
+        let (_event_loop, _window, mut renderer) = match test_renderer(validation_settings) {
+            Some(test) => test,
+            None => return,
+        };
+        if !renderer.validation_settings.validation {
+            eprintln!("skipping: validation layer not installed");
+            return;
+        }
+
+        renderer.draw();
+        renderer.render_context.device.wait_idle();
+
+        let errors = renderer.validation_errors().take();
+        assert!(
+            errors.is_empty(),
+            "validation errors:\n{}",
+            errors.join("\n")
+        );
+    }
+}
@@ -1,7 +1,7 @@
 pub mod raster_pass;
 
 use crate::render_context::RenderContext;
-use crate::resources::{Fence, Semaphore};
+use crate::resources::Semaphore;
 use erupt::vk;
 
 pub use raster_pass::*;
@@ -16,7 +16,6 @@ pub trait Pass<'a> {
         frame: u64,
         wait: &[(vk::PipelineStageFlags, Semaphore)],
         signal: &[Semaphore],
-        fence: Option<&Fence>,
         render_context: &mut RenderContext,
     ) -> Self::Output;
 }
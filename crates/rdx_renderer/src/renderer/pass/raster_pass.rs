@@ -1,30 +1,67 @@
+use crate::command_buffer::InheritanceInfo;
 use crate::descriptor::{DescriptorSetInfo, DescriptorSetLayoutBinding, DescriptorSetLayoutInfo};
+use crate::device::Device;
+use crate::encoder::EncoderInner;
 use crate::framebuffer::FramebufferInfo;
-use crate::image::{Image, ImageInfo, ImageViewInfo};
-use crate::pipeline::{GraphicsPipelineInfo, PipelineLayoutInfo, Rasterizer};
+use crate::image::{
+    Image, ImageInfo, ImageMemoryBarrier, ImageSubresourceRange, ImageView, ImageViewInfo,
+};
+use crate::mesh::{GpuMesh, Vertex};
+use crate::pipeline::{
+    GraphicsPipelineInfo, PipelineLayoutInfo, PipelineTarget, PushConstant, Rasterizer,
+    RenderingFormats, VertexInputAttribute, VertexInputBinding,
+};
+use crate::profiler::ProfilerScopeInfo;
+use crate::queue::SubmitInfo;
 use crate::render_context::RenderContext;
-use crate::render_pass::{AttachmentInfo, ClearValue, RenderPassInfo, Subpass};
+use crate::render_pass::{
+    AttachmentInfo, ClearValue, RenderPassInfo, RenderingAttachment, RenderingInfo, Subpass,
+    SubpassDependency,
+};
 use crate::renderer::Pass;
 use crate::resources::{
-    Fence, Framebuffer, GraphicsPipeline, PipelineLayout, RenderPass, Semaphore,
+    DescriptorSet, Framebuffer, GraphicsPipeline, PipelineLayout, RenderPass, Semaphore,
+    ShaderModule,
+};
+use crate::scene::DrawState;
+use crate::shader::{
+    Shader, ShaderLanguage, ShaderModuleInfo, ShaderReloads, ShaderVariantKey, ShaderVariants,
 };
-use crate::shader::{Shader, ShaderLanguage, ShaderModuleInfo};
 use erupt::vk;
 use erupt::vk1_0::{Extent2D, Format, PipelineStageFlags};
 use lru::LruCache;
+use memoffset::offset_of;
 use smallvec::smallvec;
+use std::collections::HashSet;
+use std::mem;
+use std::ops::Range;
+
+const RASTER_SCOPE: ProfilerScopeInfo = ProfilerScopeInfo {
+    name: "raster",
+    diagnostic_id: 0x1e16c2057e9647b2abf99c157c529405,
+};
+
+/// Draws recorded per secondary command buffer.
+const DRAWS_PER_SECONDARY: usize = 256;
 
 pub struct RasterPass {
-    render_pass: RenderPass,
+    /// `None` when rendering with `VK_KHR_dynamic_rendering`.
+    render_pass: Option<RenderPass>,
     pipeline_layout: PipelineLayout,
-    graphics_pipeline: GraphicsPipeline,
+    /// One pipeline per `DrawState`, at `DrawState::index`.
+    graphics_pipelines: Vec<GraphicsPipeline>,
 
-    framebuffers: LruCache<Image, Framebuffer>,
+    color_views: LruCache<Image, ImageView>,
+    framebuffers: LruCache<(Image, Image), Framebuffer>,
 
     depth_image: Image,
+    depth_view: ImageView,
+    /// Whether the pipeline was built for a reverse-Z camera.
+    reverse_z: bool,
 
     vertex_shader: Shader,
-    fragment_shader: Shader,
+    /// `shader.frag` variants, selected by `fragment_variant`.
+    fragment_variants: ShaderVariants,
 }
 
 pub struct Input {
@@ -43,68 +80,228 @@ impl Pass<'_> for RasterPass {
         frame: u64,
         wait: &[(PipelineStageFlags, Semaphore)],
         signal: &[Semaphore],
-        fence: Option<&Fence>,
         render_context: &mut RenderContext,
     ) -> Output {
-        let fb;
-        let framebuffer = match self.framebuffers.get(&input.target) {
+        let extent = input.target.info().extent.into_2d();
+
+        let reverse_z = render_context.camera.reverse_z();
+        if reverse_z != self.reverse_z {
+            for graphics_pipeline in &mut self.graphics_pipelines {
+                let mut info = graphics_pipeline.info().clone();
+                if let Some(rasterizer) = &mut info.rasterizer {
+                    rasterizer.depth_compare = depth_compare(reverse_z);
+                }
+                let previous = mem::replace(
+                    graphics_pipeline,
+                    render_context.create_graphics_pipeline(info),
+                );
+                retire(render_context, move |device| {
+                    device.destroy_graphics_pipeline(&previous)
+                });
+            }
+            self.reverse_z = reverse_z;
+        }
+        let clear_depth = if reverse_z { 0.0 } else { 1.0 };
+
+        let color_view = match self.color_views.get(&input.target) {
+            Some(view) => view.clone(),
             None => {
-                let color_view = render_context.create_image_view(ImageViewInfo::new(
-                    input.target.clone(),
-                    vk::ImageAspectFlags::COLOR,
-                ));
-
-                let depth_view = render_context.create_image_view(ImageViewInfo::new(
-                    self.depth_image.clone(),
-                    vk::ImageAspectFlags::DEPTH,
-                ));
-
-                fb = render_context.create_framebuffer(FramebufferInfo {
-                    render_pass: self.render_pass.clone(),
-                    views: smallvec![color_view, depth_view],
-                    extent: input.target.info().extent,
+                let view = render_context.create_image_view(ImageViewInfo {
+                    name: Some("raster color view".into()),
+                    ..ImageViewInfo::new(input.target.clone(), vk::ImageAspectFlags::COLOR)
                 });
+                if self.color_views.len() == self.color_views.cap() {
+                    if let Some((_, evicted)) = self.color_views.pop_lru() {
+                        retire(render_context, move |device| {
+                            device.destroy_image_view(&evicted)
+                        });
+                    }
+                }
+                self.color_views.put(input.target.clone(), view.clone());
+                view
+            }
+        };
 
-                self.framebuffers.put(input.target, fb.clone());
-                &fb
+        // Keyed by both attachments so a recreated depth image is never
+        // paired with a stale framebuffer.
+        let framebuffer = match &self.render_pass {
+            Some(render_pass) => {
+                let key = (input.target.clone(), self.depth_image.clone());
+                match self.framebuffers.get(&key) {
+                    Some(framebuffer) => Some(framebuffer.clone()),
+                    None => {
+                        let framebuffer = render_context.create_framebuffer(FramebufferInfo {
+                            render_pass: render_pass.clone(),
+                            views: smallvec![color_view.clone(), self.depth_view.clone()],
+                            extent,
+                            name: Some("raster framebuffer".into()),
+                        });
+                        if self.framebuffers.len() == self.framebuffers.cap() {
+                            if let Some((_, evicted)) = self.framebuffers.pop_lru() {
+                                retire(render_context, move |device| {
+                                    device.destroy_framebuffer(&evicted)
+                                });
+                            }
+                        }
+                        self.framebuffers.put(key, framebuffer.clone());
+                        Some(framebuffer)
+                    }
+                }
             }
-            Some(framebuffer) => framebuffer,
+            None => None,
         };
 
-        let mut encoder = render_context.queue.create_enconder();
+        let clears = [
+            ClearValue::Color(0.5, 0.2, 0.2, 0.0),
+            ClearValue::DepthStencil(clear_depth, 0),
+        ];
 
-        encoder.begin_render_pass(
-            &self.render_pass,
-            framebuffer,
-            &[
-                ClearValue::Color(0.5, 0.2, 0.2, 0.0),
-                ClearValue::DepthStencil(1.0, 0),
-            ],
-        );
+        let color_attachments = [RenderingAttachment {
+            view: &color_view,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            clear: ClearValue::Color(0.5, 0.2, 0.2, 0.0),
+            resolve: None,
+        }];
+        let depth_attachment = RenderingAttachment {
+            view: &self.depth_view,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::DONT_CARE,
+            clear: ClearValue::DepthStencil(clear_depth, 0),
+            resolve: None,
+        };
 
-        encoder.bind_graphics_pipeline(&self.graphics_pipeline);
+        let to_attachment = [
+            ImageMemoryBarrier {
+                image: &input.target,
+                old_layout: None,
+                new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                family_transfer: None,
+                subresource: ImageSubresourceRange::new(vk::ImageAspectFlags::COLOR, 0..1, 0..1),
+            },
+            ImageMemoryBarrier {
+                image: &self.depth_image,
+                old_layout: None,
+                new_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                family_transfer: None,
+                subresource: ImageSubresourceRange::new(vk::ImageAspectFlags::DEPTH, 0..1, 0..1),
+            },
+        ];
+        let to_present = [ImageMemoryBarrier {
+            image: &input.target,
+            old_layout: Some(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+            new_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            family_transfer: None,
+            subresource: ImageSubresourceRange::new(vk::ImageAspectFlags::COLOR, 0..1, 0..1),
+        }];
 
-        encoder.set_viewport(vk::Viewport {
-            x: 0.0,
-            y: framebuffer.info().extent.height as f32,
-            width: framebuffer.info().extent.width as f32,
-            height: -(framebuffer.info().extent.height as f32),
-            min_depth: 0.0,
-            max_depth: 1.0,
-        });
+        let camera_sets = [render_context.camera.set().clone()];
+        let camera_offsets = [render_context.camera.dynamic_offset()];
+        let scene_sets = [render_context.scene.set().clone()];
+        let scene_offsets = render_context.scene.dynamic_offsets();
+        let texture_sets = [render_context.scene.texture_set().clone()];
+        let light_count = render_context.scene.light_count();
+        let bindings = DrawBindings {
+            layout: &self.pipeline_layout,
+            camera_sets: &camera_sets,
+            camera_offsets: &camera_offsets,
+            scene_sets: &scene_sets,
+            scene_offsets: &scene_offsets,
+            texture_sets: &texture_sets,
+            light_count: &light_count,
+            extent,
+        };
 
-        encoder.set_scissor(vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: framebuffer.info().extent,
-        });
+        let scene = &render_context.scene;
+        let draws = scene
+            .draws()
+            .iter()
+            .map(|draw| DrawCall {
+                pipeline: &self.graphics_pipelines[draw.state.index()],
+                mesh: scene.mesh(draw.mesh).unwrap(),
+                instances: draw.instances.clone(),
+            })
+            .collect::<Vec<_>>();
 
-        encoder.draw(0..3, 0..1);
+        // Inside a render pass the draws are recorded in parallel. Dynamic
+        // rendering records them inline, secondary buffers would need
+        // `VkCommandBufferInheritanceRenderingInfoKHR` to continue it.
+        let secondaries = match (&self.render_pass, &framebuffer) {
+            (Some(render_pass), Some(framebuffer)) => {
+                let chunks = draws.chunks(DRAWS_PER_SECONDARY).collect::<Vec<_>>();
+                render_context.queue.secondary_pools().record_parallel(
+                    &render_context.task_pool,
+                    &InheritanceInfo {
+                        render_pass,
+                        subpass: 0,
+                        framebuffer: Some(framebuffer),
+                        pipeline_statistics: render_context.profiler.pipeline_statistics(),
+                    },
+                    &chunks,
+                    |encoder, draws| record_draws(encoder, &bindings, draws),
+                )
+            }
+            _ => Vec::new(),
+        };
 
-        encoder.end_render_pass();
+        let mut encoder = render_context.queue.create_enconder();
+        let scope = render_context
+            .profiler
+            .begin_scope(&mut encoder, &RASTER_SCOPE);
 
-        render_context
-            .queue
-            .submit(wait, encoder.finish(&render_context.device), signal, fence);
+        match (&self.render_pass, &framebuffer) {
+            (Some(render_pass), Some(framebuffer)) => {
+                encoder.begin_render_pass_secondary(render_pass, framebuffer, &clears);
+                if !secondaries.is_empty() {
+                    encoder.execute_commands(&secondaries);
+                }
+            }
+            _ => {
+                encoder.pipeline_barrier(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                    &to_attachment,
+                    &[],
+                );
+                encoder.begin_rendering(RenderingInfo {
+                    render_area: vk::Rect2D {
+                        offset: vk::Offset2D { x: 0, y: 0 },
+                        extent,
+                    },
+                    colors: &color_attachments,
+                    depth: Some(&depth_attachment),
+                });
+                record_draws(&mut encoder, &bindings, &draws);
+            }
+        }
+
+        if framebuffer.is_some() {
+            encoder.end_render_pass();
+        } else {
+            encoder.end_rendering();
+            encoder.pipeline_barrier(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                &to_present,
+                &[],
+            );
+        }
+
+        render_context.profiler.end_scope(&mut encoder, scope);
+
+        render_context.queue.submit(
+            encoder.finish(&render_context.device),
+            SubmitInfo {
+                wait,
+                signal,
+                ..Default::default()
+            },
+        );
 
         Output
     }
@@ -124,56 +321,116 @@ impl RasterPass {
             Shader::new(module, vk::ShaderStageFlags::VERTEX)
         };
 
-        let fragment_shader = {
-            let module = render_context.create_shader_module(ShaderModuleInfo::new(
-                "shader.frag.spv",
-                ShaderLanguage::SPIRV,
-            ));
-            Shader::new(module, vk::ShaderStageFlags::FRAGMENT)
-        };
+        let fragment_variants = ShaderVariants::new(
+            "shader.frag.spv",
+            ShaderLanguage::SPIRV,
+            vk::ShaderStageFlags::FRAGMENT,
+        );
+
+        let depth_format = render_context.best_depth_format(false);
 
         let depth_image = render_context.create_image(ImageInfo {
-            extent,
-            format: vk::Format::D32_SFLOAT,
+            extent: extent.into(),
+            format: depth_format,
             mip_levels: 1,
             array_layers: 1,
             samples: vk::SampleCountFlagBits::_1,
             usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            cube_compatible: false,
+            mutable_format: false,
+            name: Some("raster depth".into()),
         });
 
-        let render_pass = render_context.create_render_pass(RenderPassInfo {
-            attachments: smallvec![
-                AttachmentInfo {
-                    format: surface_format,
-                    samples: vk::SampleCountFlags::_1,
-                    load_op: vk::AttachmentLoadOp::CLEAR,
-                    store_op: vk::AttachmentStoreOp::STORE,
-                    initial_layout: None,
-                    final_layout: vk::ImageLayout::PRESENT_SRC_KHR
-                },
-                AttachmentInfo {
-                    format: vk::Format::D32_SFLOAT,
-                    samples: vk::SampleCountFlags::_1,
-                    load_op: vk::AttachmentLoadOp::CLEAR,
-                    store_op: vk::AttachmentStoreOp::DONT_CARE,
-                    initial_layout: None,
-                    final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
-                },
-            ],
-            subpasses: smallvec![Subpass {
-                colors: smallvec![0],
-                depth: Some(1),
-            }],
+        let depth_view = render_context.create_image_view(ImageViewInfo {
+            name: Some("raster depth view".into()),
+            ..ImageViewInfo::new(depth_image.clone(), vk::ImageAspectFlags::DEPTH)
         });
 
+        let (render_pass, target) = if render_context.dynamic_rendering() {
+            let formats = RenderingFormats {
+                colors: smallvec![surface_format],
+                depth: Some(depth_format),
+                samples: vk::SampleCountFlags::_1,
+            };
+            (None, PipelineTarget::Dynamic(formats))
+        } else {
+            let render_pass = render_context.create_render_pass(RenderPassInfo {
+                attachments: smallvec![
+                    AttachmentInfo {
+                        format: surface_format,
+                        samples: vk::SampleCountFlags::_1,
+                        load_op: vk::AttachmentLoadOp::CLEAR,
+                        store_op: vk::AttachmentStoreOp::STORE,
+                        initial_layout: None,
+                        final_layout: vk::ImageLayout::PRESENT_SRC_KHR
+                    },
+                    AttachmentInfo {
+                        format: depth_format,
+                        samples: vk::SampleCountFlags::_1,
+                        load_op: vk::AttachmentLoadOp::CLEAR,
+                        store_op: vk::AttachmentStoreOp::DONT_CARE,
+                        initial_layout: None,
+                        final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+                    },
+                ],
+                subpasses: smallvec![Subpass::new(&[0], Some(1))],
+                dependencies: smallvec![SubpassDependency::external(0)],
+                name: Some("raster".into()),
+            });
+
+            let target = PipelineTarget::RenderPass {
+                render_pass: render_pass.clone(),
+                subpass: 0,
+            };
+            (Some(render_pass), target)
+        };
+
         let pipeline_layout = render_context.create_pipeline_layout(PipelineLayoutInfo {
-            sets: vec![],
-            push_constants: vec![],
+            sets: vec![
+                render_context.camera.layout().clone(),
+                render_context.scene.layout().clone(),
+                render_context.scene.texture_layout().clone(),
+            ],
+            // Number of lights.
+            push_constants: vec![PushConstant {
+                stages: vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: mem::size_of::<u32>() as u32,
+            }],
+            name: Some("raster".into()),
         });
 
-        let graphics_pipeline = render_context.create_graphics_pipeline(GraphicsPipelineInfo {
-            vertex_bindings: vec![],
-            vertex_attributes: vec![],
+        let info = GraphicsPipelineInfo {
+            vertex_bindings: vec![VertexInputBinding {
+                input_rate: vk::VertexInputRate::VERTEX,
+                stride: mem::size_of::<Vertex>() as u32,
+            }],
+            vertex_attributes: vec![
+                VertexInputAttribute {
+                    location: 0,
+                    format: vk::Format::R32G32B32_SFLOAT,
+                    binding: 0,
+                    offset: offset_of!(Vertex, position) as u32,
+                },
+                VertexInputAttribute {
+                    location: 1,
+                    format: vk::Format::R32G32B32_SFLOAT,
+                    binding: 0,
+                    offset: offset_of!(Vertex, normal) as u32,
+                },
+                VertexInputAttribute {
+                    location: 2,
+                    format: vk::Format::R32G32B32A32_SFLOAT,
+                    binding: 0,
+                    offset: offset_of!(Vertex, tangent) as u32,
+                },
+                VertexInputAttribute {
+                    location: 3,
+                    format: vk::Format::R32G32_SFLOAT,
+                    binding: 0,
+                    offset: offset_of!(Vertex, uv) as u32,
+                },
+            ],
             primitive_topology: vk::PrimitiveTopology::TRIANGLE_LIST,
             vertex_shader: vertex_shader.clone(),
             rasterizer: Some(Rasterizer {
@@ -187,23 +444,216 @@ impl RasterPass {
                 },
                 depth_clamp: false,
                 front_face: vk::FrontFace::COUNTER_CLOCKWISE,
-                cull_mode: vk::CullModeFlags::NONE,
+                cull_mode: vk::CullModeFlags::BACK,
                 polygon_mode: vk::PolygonMode::FILL,
-                fragment_shader: Some(fragment_shader.clone()),
+                depth_compare: depth_compare(false),
+                depth_write: true,
+                alpha_blend: false,
+                fragment_shader: None,
             }),
             layout: pipeline_layout.clone(),
-            render_pass: render_pass.clone(),
-            subpass: 0,
-        });
+            target,
+            name: None,
+        };
+
+        let graphics_pipelines = (0..DrawState::COUNT)
+            .map(DrawState::from_index)
+            .map(|state| {
+                let fragment_shader = fragment_variants
+                    .get(&render_context.device, &fragment_variant(state))
+                    .unwrap_or_else(|error| panic!("Shader compilation failed: {}", error));
+
+                let mut info = info.clone();
+                if let Some(rasterizer) = &mut info.rasterizer {
+                    if state.double_sided {
+                        rasterizer.cull_mode = vk::CullModeFlags::NONE;
+                    }
+                    rasterizer.depth_write = !state.blend;
+                    rasterizer.alpha_blend = state.blend;
+                    rasterizer.fragment_shader = Some(fragment_shader);
+                }
+                info.name = Some(format!("raster {:?}", state).into());
+                render_context.create_graphics_pipeline(info)
+            })
+            .collect();
 
         RasterPass {
             render_pass,
             pipeline_layout,
-            graphics_pipeline,
+            graphics_pipelines,
+            color_views: LruCache::new(4),
             framebuffers: LruCache::new(4),
             depth_image,
+            depth_view,
+            reverse_z: false,
             vertex_shader,
-            fragment_shader,
+            fragment_variants,
         }
     }
+
+    /// Recreates the pipelines whose shaders changed. The replaced pipelines
+    /// and the modules no longer used by any pipeline are destroyed once the
+    /// frames using them are done.
+    pub fn reload_shaders(&mut self, reloads: &mut ShaderReloads, render_context: &RenderContext) {
+        let mut retired_modules = Vec::new();
+
+        // Reloads share compiled modules, the pipelines below pick up the
+        // same ones.
+        self.fragment_variants
+            .reload(reloads, &render_context.device);
+
+        for graphics_pipeline in &mut self.graphics_pipelines {
+            if let Some(reloaded) =
+                reloads.reload_graphics_pipeline(render_context, graphics_pipeline)
+            {
+                self.vertex_shader = reloaded.info().vertex_shader.clone();
+
+                let previous = mem::replace(graphics_pipeline, reloaded);
+                retired_modules.extend(shader_modules(&previous));
+                retire(render_context, move |device| {
+                    device.destroy_graphics_pipeline(&previous)
+                });
+            }
+        }
+
+        let live_modules = self
+            .graphics_pipelines
+            .iter()
+            .flat_map(shader_modules)
+            .map(|module| module.handle())
+            .collect::<HashSet<_>>();
+        retired_modules.retain(|module| !live_modules.contains(&module.handle()));
+        if !retired_modules.is_empty() {
+            retire(render_context, move |device| {
+                for module in &retired_modules {
+                    device.destroy_shader_module(module);
+                }
+            });
+        }
+    }
+
+    /// Destroys the pass's resources, once the device is idle.
+    pub fn cleanup(&mut self, device: &Device) {
+        for graphics_pipeline in &self.graphics_pipelines {
+            device.destroy_graphics_pipeline(graphics_pipeline);
+        }
+        device.destroy_shader_module(&self.vertex_shader.module);
+        self.fragment_variants.destroy(device);
+
+        while let Some((_, framebuffer)) = self.framebuffers.pop_lru() {
+            device.destroy_framebuffer(&framebuffer);
+        }
+        while let Some((_, view)) = self.color_views.pop_lru() {
+            device.destroy_image_view(&view);
+        }
+        device.destroy_image_view(&self.depth_view);
+        device.destroy_image(&self.depth_image);
+    }
+}
+
+/// Descriptor sets and dynamic state every draw of the pass uses.
+struct DrawBindings<'a> {
+    layout: &'a PipelineLayout,
+    camera_sets: &'a [DescriptorSet],
+    camera_offsets: &'a [u32],
+    scene_sets: &'a [DescriptorSet],
+    scene_offsets: &'a [u32],
+    texture_sets: &'a [DescriptorSet],
+    light_count: &'a u32,
+    extent: vk::Extent2D,
+}
+
+/// Consecutive instances of one mesh, with the pipeline drawing them.
+struct DrawCall<'a> {
+    pipeline: &'a GraphicsPipeline,
+    mesh: &'a GpuMesh,
+    instances: Range<u32>,
+}
+
+/// Binds `bindings` and records `draws`. Secondary command buffers inherit no
+/// state from the primary, so each of them starts with the bindings too.
+fn record_draws<'a>(
+    encoder: &mut EncoderInner<'a>,
+    bindings: &'a DrawBindings<'a>,
+    draws: &'a [DrawCall<'a>],
+) {
+    encoder.bind_graphics_descriptor_sets(
+        bindings.layout,
+        0,
+        bindings.camera_sets,
+        bindings.camera_offsets,
+    );
+    encoder.bind_graphics_descriptor_sets(
+        bindings.layout,
+        1,
+        bindings.scene_sets,
+        bindings.scene_offsets,
+    );
+    encoder.bind_graphics_descriptor_sets(bindings.layout, 2, bindings.texture_sets, &[]);
+    encoder.push_constants(
+        bindings.layout,
+        vk::ShaderStageFlags::FRAGMENT,
+        0,
+        bindings.light_count,
+    );
+
+    encoder.set_viewport(vk::Viewport {
+        x: 0.0,
+        y: bindings.extent.height as f32,
+        width: bindings.extent.width as f32,
+        height: -(bindings.extent.height as f32),
+        min_depth: 0.0,
+        max_depth: 1.0,
+    });
+
+    encoder.set_scissor(vk::Rect2D {
+        offset: vk::Offset2D { x: 0, y: 0 },
+        extent: bindings.extent,
+    });
+
+    let mut pipeline = None;
+    for draw in draws {
+        if pipeline != Some(draw.pipeline.handle()) {
+            encoder.bind_graphics_pipeline(draw.pipeline);
+            pipeline = Some(draw.pipeline.handle());
+        }
+
+        encoder.bind_vertex_buffers(0, &draw.mesh.vertices);
+        encoder.bind_index_buffer(&draw.mesh.indices, 0, vk::IndexType::UINT32);
+        encoder.draw_indexed(0..draw.mesh.index_count, 0, draw.instances.clone());
+    }
+}
+
+/// Runs `destroy` once the frames submitted so far are done.
+fn retire(render_context: &RenderContext, destroy: impl FnOnce(&Device) + Send + 'static) {
+    let device = render_context.device.clone();
+    let timeline = render_context.queue.timeline();
+    timeline.defer(timeline.last_submitted().value, move || destroy(&device));
+}
+
+fn shader_modules(pipeline: &GraphicsPipeline) -> impl Iterator<Item = ShaderModule> + '_ {
+    let info = pipeline.info();
+    let fragment_shader = info
+        .rasterizer
+        .as_ref()
+        .and_then(|rasterizer| rasterizer.fragment_shader.as_ref());
+
+    std::iter::once(&info.vertex_shader)
+        .chain(fragment_shader)
+        .map(|shader| shader.module.clone())
+}
+
+/// The `shader.frag` variant drawing with `state`.
+fn fragment_variant(state: DrawState) -> ShaderVariantKey {
+    ShaderVariantKey::new()
+        .flag("ALPHA_MASK", state.alpha_mask)
+        .flag("NORMAL_MAP", state.normal_map)
+}
+
+fn depth_compare(reverse_z: bool) -> vk::CompareOp {
+    if reverse_z {
+        vk::CompareOp::GREATER_OR_EQUAL
+    } else {
+        vk::CompareOp::LESS_OR_EQUAL
+    }
 }
@@ -1,13 +1,14 @@
 use crate::acceleration_structures::AccelerationStructureInfo;
-use crate::buffer::BufferInfo;
+use crate::buffer::{BufferInfo, DeviceAddress, GpuPtr, GpuSlice};
 use crate::descriptor::{DescriptorSetInfo, DescriptorSetLayoutInfo, DescriptorSizes};
 use crate::framebuffer::FramebufferInfo;
 use crate::image::{ImageInfo, ImageViewInfo};
 use crate::pipeline::{GraphicsPipelineInfo, PipelineLayoutInfo, RayTracingPipelineInfo};
+use crate::query::QueryPoolInfo;
 use crate::render_pass::RenderPassInfo;
+use crate::sampler::SamplerInfo;
 use crate::shader::ShaderModuleInfo;
 use erupt::vk;
-use erupt::vk::DeviceAddress;
 use gpu_alloc::{MemoryBlock, UsageFlags};
 use std::cell::UnsafeCell;
 use std::hash::{Hash, Hasher};
@@ -22,7 +23,8 @@ struct BufferInner {
     memory_handle: vk::DeviceMemory,
     memory_offset: u64,
     memory_size: u64,
-    memory_block: UnsafeCell<MemoryBlock<vk::DeviceMemory>>,
+    /// Taken by `Device::destroy_buffer`.
+    memory_block: UnsafeCell<Option<MemoryBlock<vk::DeviceMemory>>>,
 }
 
 #[derive(Clone)]
@@ -34,6 +36,59 @@ impl Buffer {
     pub fn info(&self) -> &BufferInfo {
         &self.inner.info
     }
+
+    pub fn handle(&self) -> vk::Buffer {
+        self.inner.handle
+    }
+
+    /// Address of the start of the buffer. The buffer must have been created
+    /// with `UsageFlags::DEVICE_ADDRESS` and `SHADER_DEVICE_ADDRESS` usage.
+    pub fn device_address(&self) -> DeviceAddress {
+        self.inner
+            .device_address
+            .expect("Buffer was not created with a device address")
+    }
+
+    /// Typed pointer to the `T` at byte `offset`.
+    pub fn ptr<T>(&self, offset: u64) -> GpuPtr<T> {
+        let end = offset.checked_add(std::mem::size_of::<T>() as u64);
+        assert!(
+            end.map_or(false, |end| end <= self.info().size),
+            "pointer at offset {} is out of bounds of a {} byte buffer",
+            offset,
+            self.info().size
+        );
+
+        GpuPtr::new(self.device_address().offset(offset))
+    }
+
+    /// Typed slice of `len` elements of `T` starting at byte `offset`.
+    pub fn slice<T>(&self, offset: u64, len: u32) -> GpuSlice<T> {
+        let end = (std::mem::size_of::<T>() as u64)
+            .checked_mul(len as u64)
+            .and_then(|size| size.checked_add(offset));
+        assert!(
+            end.map_or(false, |end| end <= self.info().size),
+            "slice of {} elements at offset {} is out of bounds of a {} byte buffer",
+            len,
+            offset,
+            self.info().size
+        );
+
+        GpuSlice::new(self.device_address().offset(offset), len)
+    }
+
+    /// Index of the buffer in the device's buffer slab.
+    pub fn index(&self) -> usize {
+        self.inner.index
+    }
+
+    /// Takes the buffer's memory to free it, `None` once already taken.
+    ///
+    /// No other clone of the buffer may access its memory concurrently.
+    pub unsafe fn take_memory_block(&self) -> Option<MemoryBlock<vk::DeviceMemory>> {
+        (*self.inner.memory_block.get()).take()
+    }
 }
 
 unsafe impl Send for Buffer {}
@@ -76,7 +131,7 @@ impl MappableBuffer {
                     memory_handle: *memory_block.memory(),
                     memory_offset: memory_block.offset(),
                     memory_size: memory_block.size(),
-                    memory_block: UnsafeCell::new(memory_block),
+                    memory_block: UnsafeCell::new(Some(memory_block)),
                     index,
                 }),
             },
@@ -85,7 +140,9 @@ impl MappableBuffer {
     }
 
     pub unsafe fn memory_block(&mut self) -> &mut MemoryBlock<vk::DeviceMemory> {
-        &mut *self.inner.memory_block.get()
+        (*self.inner.memory_block.get())
+            .as_mut()
+            .expect("Buffer was destroyed")
     }
 }
 
@@ -140,9 +197,24 @@ impl RenderPass {
 
 #[derive(Clone)]
 pub struct Sampler {
+    info: SamplerInfo,
     handle: vk::Sampler,
 }
 
+impl Sampler {
+    pub fn new(info: SamplerInfo, handle: vk::Sampler) -> Self {
+        Sampler { info, handle }
+    }
+
+    pub fn handle(&self) -> vk::Sampler {
+        self.handle
+    }
+
+    pub fn info(&self) -> &SamplerInfo {
+        &self.info
+    }
+}
+
 #[derive(Clone)]
 pub struct Framebuffer {
     info: FramebufferInfo,
@@ -231,6 +303,10 @@ impl DescriptorSet {
     ) -> Self {
         DescriptorSet { info, handle, pool }
     }
+
+    pub fn handle(&self) -> vk::DescriptorSet {
+        self.handle
+    }
 }
 
 #[derive(Clone)]
@@ -275,11 +351,42 @@ impl GraphicsPipeline {
     }
 }
 
+#[derive(Clone)]
+pub struct QueryPool {
+    info: QueryPoolInfo,
+    handle: vk::QueryPool,
+}
+
+impl QueryPool {
+    pub fn new(info: QueryPoolInfo, handle: vk::QueryPool) -> Self {
+        QueryPool { info, handle }
+    }
+
+    pub fn info(&self) -> &QueryPoolInfo {
+        &self.info
+    }
+
+    pub fn handle(&self) -> vk::QueryPool {
+        self.handle
+    }
+}
+
 #[derive(Clone)]
 pub struct AccelerationStructure {
     info: AccelerationStructureInfo,
     handle: vk::AccelerationStructureKHR,
-    address: DeviceAddress,
+    address: vk::DeviceAddress,
+}
+
+impl AccelerationStructure {
+    pub fn handle(&self) -> vk::AccelerationStructureKHR {
+        self.handle
+    }
+
+    /// Address referenced by top level instances.
+    pub fn device_address(&self) -> vk::DeviceAddress {
+        self.address
+    }
 }
 
 #[derive(Clone)]
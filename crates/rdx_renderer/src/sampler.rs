@@ -0,0 +1,23 @@
+use erupt::vk;
+
+#[derive(Clone)]
+pub struct SamplerInfo {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode: vk::SamplerAddressMode,
+    pub name: Option<Box<str>>,
+}
+
+impl Default for SamplerInfo {
+    /// Trilinear filtering, repeating outside `[0, 1]`.
+    fn default() -> Self {
+        SamplerInfo {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode: vk::SamplerAddressMode::REPEAT,
+            name: None,
+        }
+    }
+}
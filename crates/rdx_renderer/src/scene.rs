@@ -0,0 +1,935 @@
+use crate::acceleration_structures::AccelerationStructureInstance;
+use crate::buffer::DeviceAddress;
+use crate::descriptor::{
+    DescriptorSetInfo, DescriptorSetLayoutBinding, DescriptorSetLayoutInfo, Descriptors,
+    WriteDescriptorSet,
+};
+use crate::device::Device;
+use crate::frame_allocator::FrameAllocator;
+use crate::image::ImageView;
+use crate::light::{Light, LightData};
+use crate::material::{AlphaMode, Material, MaterialData};
+use crate::mesh::{GpuMesh, Mesh};
+use crate::physical_device::PhysicalDeviceInfo;
+use crate::renderer::Renderer;
+use crate::resources::{DescriptorSet, DescriptorSetLayout, Sampler};
+use crate::sampler::SamplerInfo;
+use crate::texture::{GpuTexture, Texture};
+use crate::timeline::{GpuTimeline, TimelinePoint};
+use crate::typed_buffer::{std430_array_stride, StorageVec, DEFAULT_VERSIONS};
+use crate::upload::UploadManager;
+use bevy::asset::{AssetEvent, Assets, Handle, HandleId};
+use bevy::math::{Mat4, Vec3};
+use bevy::prelude::*;
+use crevice::internal::bytemuck;
+use crevice::std430::AsStd430;
+use erupt::vk;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::Range;
+
+const DEFAULT_INSTANCE_CAPACITY: usize = 256;
+const DEFAULT_MATERIAL_CAPACITY: usize = 64;
+const DEFAULT_LIGHT_CAPACITY: usize = 16;
+
+/// Size of the texture array, the bound on textures loaded at once.
+pub const MAX_TEXTURES: u32 = 1024;
+
+/// Hides an entity from rendering while `is_visible` is `false`. Entities
+/// without one are visible.
+#[derive(Clone, Copy, Debug)]
+pub struct Visible {
+    pub is_visible: bool,
+}
+
+impl Default for Visible {
+    fn default() -> Self {
+        Visible { is_visible: true }
+    }
+}
+
+/// Per-instance data read by shaders at `gl_InstanceIndex`, and by ray
+/// tracing shaders at the instance custom index, see `tlas_instances`.
+#[derive(AsStd430)]
+pub struct InstanceData {
+    pub model: mint::ColumnMatrix4<f32>,
+    /// Inverse transpose of `model`, for transforming normals.
+    pub normal: mint::ColumnMatrix4<f32>,
+    pub material: u32,
+}
+
+/// Fixed function state and shader variant of a draw, selecting one of the
+/// raster pipelines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DrawState {
+    /// Alpha blended, drawn after everything opaque.
+    pub blend: bool,
+    /// Back faces are not culled.
+    pub double_sided: bool,
+    /// Fragments below the material's alpha cutoff are discarded.
+    pub alpha_mask: bool,
+    /// The material has a normal texture.
+    pub normal_map: bool,
+}
+
+impl DrawState {
+    /// Number of distinct states.
+    pub const COUNT: usize = 16;
+
+    /// The state at `index`, the inverse of `DrawState::index`.
+    pub fn from_index(index: usize) -> Self {
+        DrawState {
+            blend: index & 8 != 0,
+            double_sided: index & 4 != 0,
+            alpha_mask: index & 2 != 0,
+            normal_map: index & 1 != 0,
+        }
+    }
+
+    /// Position in `0..DrawState::COUNT`.
+    pub fn index(self) -> usize {
+        (self.blend as usize) << 3
+            | (self.double_sided as usize) << 2
+            | (self.alpha_mask as usize) << 1
+            | self.normal_map as usize
+    }
+
+    fn from_material(material: &Material) -> Self {
+        DrawState {
+            blend: material.alpha_mode == AlphaMode::Blend,
+            double_sided: material.double_sided,
+            alpha_mask: matches!(material.alpha_mode, AlphaMode::Mask { .. }),
+            normal_map: material.normal_texture.is_some(),
+        }
+    }
+}
+
+/// An entity in draw order, its index being its `InstanceData` index.
+pub struct SceneInstance {
+    pub transform: Mat4,
+    pub mesh: HandleId,
+    pub material: u32,
+    pub state: DrawState,
+}
+
+/// Consecutive instances of one mesh, drawn together.
+pub struct Draw {
+    pub mesh: HandleId,
+    pub instances: Range<u32>,
+    pub state: DrawState,
+}
+
+struct SceneMaterial {
+    index: u32,
+    material: Material,
+}
+
+struct SceneTexture {
+    /// Slot of the linear view.
+    index: u32,
+    /// Slot of the sRGB view, if the texture has one.
+    srgb_index: Option<u32>,
+    gpu: GpuTexture,
+}
+
+impl SceneTexture {
+    fn index(&self, srgb: bool) -> u32 {
+        match self.srgb_index {
+            Some(index) if srgb => index,
+            _ => self.index,
+        }
+    }
+}
+
+struct SceneEntity {
+    mesh: HandleId,
+    material: HandleId,
+    transform: Mat4,
+}
+
+/// GPU copy of the meshes, textures, materials and renderable entities of
+/// the bevy world, kept up to date by the `extract_scene` system.
+///
+/// Instances, materials and lights are bound as set 1 of the raster pass:
+/// binding 0 holds `InstanceData`, binding 1 `MaterialData` and binding 2
+/// `LightData`, all storage buffers with dynamic offsets from
+/// `dynamic_offsets`. Instances are written to the frame allocator every
+/// frame, the other buffers only on frames where something changed. Frames
+/// in flight each bind their own copy of the set, so a grown buffer is only
+/// written to copies the GPU is done with.
+///
+/// Textures are bound as set 2, an array of `MAX_TEXTURES` combined image
+/// samplers indexed by `MaterialData`, with a slot per view of a texture.
+/// Slots are written once when a texture is uploaded, and only reused after
+/// the frames that could sample them are done, so the set never changes
+/// under the GPU.
+///
+/// Replaced or removed meshes, textures and buffers are destroyed once
+/// `timeline`, the timeline of the queue drawing the scene, passes the last
+/// frame that could use them.
+pub struct RenderScene {
+    timeline: GpuTimeline,
+    meshes: HashMap<HandleId, GpuMesh>,
+    textures: HashMap<HandleId, SceneTexture>,
+    free_textures: Vec<u32>,
+    /// Slots of replaced or removed textures, freed at the end of the frame.
+    retired_textures: Vec<u32>,
+    /// Slots of textures the GPU may still sample until the point is reached.
+    pending_textures: Vec<(TimelinePoint, u32)>,
+    texture_count: u32,
+    material_entries: HashMap<HandleId, SceneMaterial>,
+    free_materials: Vec<u32>,
+    materials: StorageVec<MaterialData>,
+    entities: HashMap<Entity, SceneEntity>,
+    instances: Vec<SceneInstance>,
+    /// `InstanceData` converted to std430, each padded to `instance_stride`.
+    instance_data: Vec<u8>,
+    instance_stride: usize,
+    /// Where this frame's instances were written.
+    instance_offset: u32,
+    /// Size of the instance range each set binds.
+    instance_sizes: Vec<u64>,
+    /// Instances of meshes with a BLAS, their custom index being their
+    /// `InstanceData` index.
+    tlas_instances: Vec<AccelerationStructureInstance>,
+    /// Where this frame's `tlas_instances` were written.
+    tlas_instances_address: Option<DeviceAddress>,
+    draws: Vec<Draw>,
+    lights: StorageVec<LightData>,
+    view_position: Vec3,
+    layout: DescriptorSetLayout,
+    /// One set per frame in flight, `sets[current_set]` is this frame's.
+    sets: Vec<DescriptorSet>,
+    /// The frame last binding each set.
+    set_points: Vec<Option<TimelinePoint>>,
+    /// Sets still pointing at replaced buffers.
+    stale_sets: Vec<bool>,
+    current_set: usize,
+    texture_layout: DescriptorSetLayout,
+    texture_set: DescriptorSet,
+    sampler: Sampler,
+    instances_dirty: bool,
+    materials_dirty: bool,
+    lights_dirty: bool,
+}
+
+impl RenderScene {
+    pub fn new(device: &Device, info: &PhysicalDeviceInfo, timeline: &GpuTimeline) -> Self {
+        let instance_stride = std430_array_stride::<InstanceData>();
+        let materials = StorageVec::new(
+            device,
+            info,
+            DEFAULT_MATERIAL_CAPACITY,
+            Some("scene materials".into()),
+        );
+        let lights = StorageVec::new(
+            device,
+            info,
+            DEFAULT_LIGHT_CAPACITY,
+            Some("scene lights".into()),
+        );
+
+        let stages = vk::ShaderStageFlags::VERTEX
+            | vk::ShaderStageFlags::FRAGMENT
+            | vk::ShaderStageFlags::RAYGEN_KHR
+            | vk::ShaderStageFlags::CLOSEST_HIT_KHR;
+        let binding = |binding| DescriptorSetLayoutBinding {
+            binding,
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER_DYNAMIC,
+            count: 1,
+            stages,
+            flags: vk::DescriptorBindingFlags::empty(),
+        };
+        let layout = device.create_descriptor_set_layout(DescriptorSetLayoutInfo {
+            bindings: vec![binding(0), binding(1), binding(2)],
+            flags: vk::DescriptorSetLayoutCreateFlags::empty(),
+            name: Some("scene".into()),
+        });
+
+        let sets = (0..DEFAULT_VERSIONS)
+            .map(|_| {
+                device.create_descriptor_set(DescriptorSetInfo {
+                    layout: layout.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        // Instances are bound by `prepare`.
+        for set in &sets {
+            device.update_descriptor_sets(
+                &[
+                    materials.descriptor_write(set, 1),
+                    lights.descriptor_write(set, 2),
+                ],
+                &[],
+            );
+        }
+
+        let texture_layout = device.create_descriptor_set_layout(DescriptorSetLayoutInfo {
+            bindings: vec![DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                count: MAX_TEXTURES,
+                stages,
+                flags: vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                    | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                    | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING,
+            }],
+            flags: vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+            name: Some("scene textures".into()),
+        });
+        let texture_set = device.create_descriptor_set(DescriptorSetInfo {
+            layout: texture_layout.clone(),
+        });
+        // Textures have a single mip level.
+        let sampler = device.create_sampler(SamplerInfo {
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            name: Some("scene textures".into()),
+            ..Default::default()
+        });
+
+        RenderScene {
+            timeline: timeline.clone(),
+            meshes: HashMap::new(),
+            textures: HashMap::new(),
+            free_textures: Vec::new(),
+            retired_textures: Vec::new(),
+            pending_textures: Vec::new(),
+            texture_count: 0,
+            material_entries: HashMap::new(),
+            free_materials: Vec::new(),
+            materials,
+            entities: HashMap::new(),
+            instances: Vec::new(),
+            instance_data: Vec::with_capacity(DEFAULT_INSTANCE_CAPACITY * instance_stride),
+            instance_stride,
+            instance_offset: 0,
+            instance_sizes: vec![0; sets.len()],
+            tlas_instances: Vec::new(),
+            tlas_instances_address: None,
+            draws: Vec::new(),
+            lights,
+            view_position: Vec3::ZERO,
+            layout,
+            set_points: vec![None; sets.len()],
+            stale_sets: vec![false; sets.len()],
+            current_set: sets.len() - 1,
+            sets,
+            texture_layout,
+            texture_set,
+            sampler,
+            instances_dirty: true,
+            materials_dirty: true,
+            lights_dirty: true,
+        }
+    }
+
+    /// Uploads `mesh`, replacing a previous upload with the same id. Meshes
+    /// without triangles are not drawn.
+    pub fn upload_mesh(
+        &mut self,
+        device: &Device,
+        uploads: &mut UploadManager,
+        id: HandleId,
+        mesh: &Mesh,
+    ) {
+        if mesh.indices.is_empty() {
+            self.remove_mesh(device, id);
+            return;
+        }
+
+        let gpu_mesh = GpuMesh::new(uploads, mesh, &format!("mesh {:?}", id));
+        if let Some(previous) = self.meshes.insert(id, gpu_mesh) {
+            self.retire_mesh(device, previous);
+        }
+        self.instances_dirty = true;
+    }
+
+    pub fn remove_mesh(&mut self, device: &Device, id: HandleId) {
+        if let Some(mesh) = self.meshes.remove(&id) {
+            self.retire_mesh(device, mesh);
+            self.instances_dirty = true;
+        }
+    }
+
+    /// Destroys `mesh` once the frames that could draw it are done.
+    fn retire_mesh(&self, device: &Device, mesh: GpuMesh) {
+        let device = device.clone();
+        self.timeline
+            .defer(self.timeline.last_submitted().value, move || {
+                mesh.destroy(&device)
+            });
+    }
+
+    pub fn mesh(&self, id: HandleId) -> Option<&GpuMesh> {
+        self.meshes.get(&id)
+    }
+
+    /// Uploads `texture` to free slots of the texture array, replacing a
+    /// previous upload with the same id.
+    pub fn upload_texture(
+        &mut self,
+        device: &Device,
+        uploads: &mut UploadManager,
+        id: HandleId,
+        texture: &Texture,
+    ) {
+        let gpu = GpuTexture::new(device, uploads, texture, &format!("texture {:?}", id));
+
+        let index = self.write_texture_slot(device, &gpu.linear_view);
+        let srgb_index = gpu
+            .srgb_view
+            .as_ref()
+            .map(|view| self.write_texture_slot(device, view));
+
+        let entry = SceneTexture {
+            index,
+            srgb_index,
+            gpu,
+        };
+        if let Some(previous) = self.textures.insert(id, entry) {
+            self.retire_texture(device, previous);
+        }
+        self.refresh_materials(id);
+    }
+
+    /// Writes `view` to a free slot of the texture array, returning it.
+    fn write_texture_slot(&mut self, device: &Device, view: &ImageView) -> u32 {
+        let index = match self.free_textures.pop() {
+            Some(index) => index,
+            None => {
+                assert!(
+                    self.texture_count < MAX_TEXTURES,
+                    "More than {} texture views loaded",
+                    MAX_TEXTURES
+                );
+                self.texture_count += 1;
+                self.texture_count - 1
+            }
+        };
+
+        device.update_descriptor_sets(
+            &[WriteDescriptorSet {
+                set: &self.texture_set,
+                binding: 0,
+                element: index,
+                descriptors: Descriptors::CombinedImageSampler(&[(
+                    view.clone(),
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    self.sampler.clone(),
+                )]),
+            }],
+            &[],
+        );
+
+        index
+    }
+
+    pub fn remove_texture(&mut self, device: &Device, id: HandleId) {
+        if let Some(entry) = self.textures.remove(&id) {
+            self.retire_texture(device, entry);
+            self.refresh_materials(id);
+        }
+    }
+
+    /// Frees the slots of `entry` at the end of the frame and destroys its
+    /// image once the frames that could sample it are done.
+    fn retire_texture(&mut self, device: &Device, entry: SceneTexture) {
+        self.retired_textures.push(entry.index);
+        self.retired_textures.extend(entry.srgb_index);
+
+        let device = device.clone();
+        let gpu = entry.gpu;
+        self.timeline
+            .defer(self.timeline.last_submitted().value, move || {
+                gpu.destroy(&device)
+            });
+    }
+
+    /// Loaded textures with the index of their linear view.
+    pub fn textures(&self) -> impl Iterator<Item = (u32, &GpuTexture)> {
+        self.textures
+            .values()
+            .map(|entry| (entry.index, &entry.gpu))
+    }
+
+    /// One more than the highest texture index in use.
+    pub fn texture_count(&self) -> u32 {
+        self.texture_count
+    }
+
+    /// Rewrites the materials referencing `texture`, whose index changed.
+    fn refresh_materials(&mut self, texture: HandleId) {
+        let textures = &self.textures;
+        for entry in self.material_entries.values() {
+            if entry.material.uses_texture(texture) {
+                let data = MaterialData::new(&entry.material, |id, srgb| {
+                    textures.get(&id).map(|texture| texture.index(srgb))
+                });
+                self.materials.set(entry.index as usize, &data);
+                self.materials_dirty = true;
+            }
+        }
+    }
+
+    pub fn set_material(&mut self, id: HandleId, material: &Material) {
+        let textures = &self.textures;
+        let data = MaterialData::new(material, |id, srgb| {
+            textures.get(&id).map(|texture| texture.index(srgb))
+        });
+
+        let index = match self.material_entries.get(&id) {
+            Some(entry) => {
+                self.materials.set(entry.index as usize, &data);
+                entry.index
+            }
+            None => {
+                let index = match self.free_materials.pop() {
+                    Some(index) => {
+                        self.materials.set(index as usize, &data);
+                        index
+                    }
+                    None => self.materials.push(&data),
+                };
+                self.instances_dirty = true;
+                index
+            }
+        };
+        self.material_entries.insert(
+            id,
+            SceneMaterial {
+                index,
+                material: material.clone(),
+            },
+        );
+        self.materials_dirty = true;
+    }
+
+    pub fn remove_material(&mut self, id: HandleId) {
+        if let Some(entry) = self.material_entries.remove(&id) {
+            self.free_materials.push(entry.index);
+            self.instances_dirty = true;
+        }
+    }
+
+    pub fn set_entity(
+        &mut self,
+        entity: Entity,
+        mesh: HandleId,
+        material: HandleId,
+        transform: Mat4,
+    ) {
+        self.entities.insert(
+            entity,
+            SceneEntity {
+                mesh,
+                material,
+                transform,
+            },
+        );
+        self.instances_dirty = true;
+    }
+
+    pub fn remove_entity(&mut self, entity: Entity) {
+        if self.entities.remove(&entity).is_some() {
+            self.instances_dirty = true;
+        }
+    }
+
+    pub fn set_lights<'a>(
+        &mut self,
+        lights: impl IntoIterator<Item = (&'a Light, &'a GlobalTransform)>,
+    ) {
+        self.lights.clear();
+        for (light, transform) in lights {
+            self.lights.push(&LightData::new(light, transform));
+        }
+        self.lights_dirty = true;
+    }
+
+    pub fn light_count(&self) -> u32 {
+        self.lights.len() as u32
+    }
+
+    /// Rebuilds the draw list if anything changed since the last call and
+    /// writes this frame's instances to `frame_allocator`, and the material
+    /// and light buffers if they changed. Blended instances are sorted back to
+    /// front from `view_position`.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        frame_allocator: &mut FrameAllocator,
+        view_position: Option<Vec3>,
+    ) {
+        let free_textures = &mut self.free_textures;
+        self.pending_textures.retain(|(point, index)| {
+            let done = device.semaphore_value(&point.semaphore) >= point.value;
+            if done {
+                free_textures.push(*index);
+            }
+            !done
+        });
+
+        let view_position = view_position.unwrap_or(Vec3::ZERO);
+        if view_position != self.view_position {
+            self.view_position = view_position;
+            self.instances_dirty |= self.draws.iter().any(|draw| draw.state.blend);
+        }
+
+        let mut resized = false;
+
+        if self.instances_dirty {
+            self.rebuild_instances();
+            self.instances_dirty = false;
+        }
+
+        if self.materials_dirty {
+            resized |= self.materials.sync(&self.timeline);
+            self.materials_dirty = false;
+        }
+
+        if self.lights_dirty {
+            resized |= self.lights.sync(&self.timeline);
+            self.lights_dirty = false;
+        }
+
+        if resized {
+            self.stale_sets.iter_mut().for_each(|stale| *stale = true);
+        }
+
+        // The set may only be updated once the frame last binding it is done.
+        self.current_set = (self.current_set + 1) % self.sets.len();
+        if let Some(point) = self.set_points[self.current_set].take() {
+            device.wait_timeline(&[point], !0);
+        }
+
+        let instances = frame_allocator.write_slice(&self.instance_data);
+        self.instance_offset = instances.offset;
+
+        self.tlas_instances_address = if self.tlas_instances.is_empty() {
+            None
+        } else {
+            let allocation = frame_allocator.write_slice(&self.tlas_instances);
+            Some(
+                allocation
+                    .buffer
+                    .device_address()
+                    .offset(allocation.offset as u64),
+            )
+        };
+
+        let set = &self.sets[self.current_set];
+        if self.instance_sizes[self.current_set] != instances.size {
+            device.update_descriptor_sets(
+                &[WriteDescriptorSet {
+                    set,
+                    binding: 0,
+                    element: 0,
+                    descriptors: Descriptors::StorageBufferDynamic(&[(
+                        instances.buffer,
+                        0,
+                        instances.size,
+                    )]),
+                }],
+                &[],
+            );
+            self.instance_sizes[self.current_set] = instances.size;
+        }
+
+        if self.stale_sets[self.current_set] {
+            device.update_descriptor_sets(
+                &[
+                    self.materials.descriptor_write(set, 1),
+                    self.lights.descriptor_write(set, 2),
+                ],
+                &[],
+            );
+            self.stale_sets[self.current_set] = false;
+        }
+    }
+
+    /// Entities whose mesh or material is not available yet are skipped
+    /// until it is. Opaque instances are grouped by state and mesh, blended
+    /// ones follow from the farthest to the nearest.
+    fn rebuild_instances(&mut self) {
+        let meshes = &self.meshes;
+        let material_entries = &self.material_entries;
+        let view_position = self.view_position;
+
+        self.instances.clear();
+        self.instances
+            .extend(self.entities.values().filter_map(|entity| {
+                if !meshes.contains_key(&entity.mesh) {
+                    return None;
+                }
+                material_entries
+                    .get(&entity.material)
+                    .map(|material| SceneInstance {
+                        transform: entity.transform,
+                        mesh: entity.mesh,
+                        material: material.index,
+                        state: DrawState::from_material(&material.material),
+                    })
+            }));
+
+        let distance = |instance: &SceneInstance| {
+            (instance.transform.w_axis.truncate() - view_position).length_squared()
+        };
+        self.instances.sort_by(|a, b| {
+            a.state.blend.cmp(&b.state.blend).then_with(|| {
+                if a.state.blend {
+                    distance(b)
+                        .partial_cmp(&distance(a))
+                        .unwrap_or(Ordering::Equal)
+                } else {
+                    (a.state, a.mesh).cmp(&(b.state, b.mesh))
+                }
+            })
+        });
+
+        self.draws.clear();
+        self.instance_data.clear();
+        self.tlas_instances.clear();
+        for (index, instance) in self.instances.iter().enumerate() {
+            let index = index as u32;
+            match self.draws.last_mut() {
+                Some(draw) if draw.mesh == instance.mesh && draw.state == instance.state => {
+                    draw.instances.end = index + 1
+                }
+                _ => self.draws.push(Draw {
+                    mesh: instance.mesh,
+                    instances: index..index + 1,
+                    state: instance.state,
+                }),
+            }
+
+            let model = instance.transform.to_cols_array_2d();
+            if let Some(blas) = &meshes[&instance.mesh].blas {
+                self.tlas_instances.push(AccelerationStructureInstance::new(
+                    model,
+                    index,
+                    0xff,
+                    tlas_instance_flags(instance.state),
+                    blas,
+                ));
+            }
+
+            let data = InstanceData {
+                model: model.into(),
+                normal: instance
+                    .transform
+                    .inverse()
+                    .transpose()
+                    .to_cols_array_2d()
+                    .into(),
+                material: instance.material,
+            }
+            .as_std430();
+            let start = self.instance_data.len();
+            self.instance_data
+                .extend_from_slice(bytemuck::bytes_of(&data));
+            self.instance_data.resize(start + self.instance_stride, 0);
+        }
+
+        // A storage buffer descriptor can't have an empty range.
+        if self.instance_data.is_empty() {
+            self.instance_data.resize(self.instance_stride, 0);
+        }
+    }
+
+    pub fn draws(&self) -> &[Draw] {
+        &self.draws
+    }
+
+    pub fn instances(&self) -> &[SceneInstance] {
+        &self.instances
+    }
+
+    /// Address and count of this frame's top level instances, the input of a
+    /// TLAS build. `None` when no drawn mesh has a BLAS.
+    pub fn tlas_instances(&self) -> Option<(DeviceAddress, u32)> {
+        self.tlas_instances_address
+            .map(|address| (address, self.tlas_instances.len() as u32))
+    }
+
+    pub fn layout(&self) -> &DescriptorSetLayout {
+        &self.layout
+    }
+
+    /// This frame's set, valid after `prepare`.
+    pub fn set(&self) -> &DescriptorSet {
+        &self.sets[self.current_set]
+    }
+
+    pub fn dynamic_offsets(&self) -> [u32; 3] {
+        [
+            self.instance_offset,
+            self.materials.dynamic_offset(),
+            self.lights.dynamic_offset(),
+        ]
+    }
+
+    pub fn texture_layout(&self) -> &DescriptorSetLayout {
+        &self.texture_layout
+    }
+
+    pub fn texture_set(&self) -> &DescriptorSet {
+        &self.texture_set
+    }
+
+    pub fn end_frame(&mut self, point: TimelinePoint) {
+        for index in self.retired_textures.drain(..) {
+            self.pending_textures.push((point.clone(), index));
+        }
+        self.set_points[self.current_set] = Some(point.clone());
+        self.materials.end_frame(point.clone());
+        self.lights.end_frame(point);
+    }
+
+    /// Destroys everything uploaded, once the device is idle.
+    pub fn cleanup(&mut self, device: &Device) {
+        for (_, mesh) in self.meshes.drain() {
+            mesh.destroy(device);
+        }
+        for (_, entry) in self.textures.drain() {
+            entry.gpu.destroy(device);
+        }
+        device.destroy_sampler(&self.sampler);
+        self.materials.cleanup();
+        self.lights.cleanup();
+    }
+}
+
+/// Ray tracing counterpart of the raster state: culling and any-hit shaders
+/// only apply where the raster pipeline culls or tests alpha.
+fn tlas_instance_flags(state: DrawState) -> vk::GeometryInstanceFlagsKHR {
+    let mut flags = vk::GeometryInstanceFlagsKHR::empty();
+    if state.double_sided {
+        flags |= vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE_KHR;
+    }
+    if !state.blend && !state.alpha_mask {
+        flags |= vk::GeometryInstanceFlagsKHR::FORCE_OPAQUE_KHR;
+    }
+    flags
+}
+
+type SceneItem<'a> = (
+    Entity,
+    &'a Handle<Mesh>,
+    &'a Handle<Material>,
+    &'a GlobalTransform,
+    Option<&'a Visible>,
+);
+
+type SceneChanged = Or<(
+    Changed<GlobalTransform>,
+    Changed<Handle<Mesh>>,
+    Changed<Handle<Material>>,
+    Changed<Visible>,
+)>;
+
+type LightChanged = Or<(Changed<Light>, Changed<GlobalTransform>)>;
+
+/// Mirrors meshes, textures, materials, entities with both handles and lights
+/// into the renderer's `RenderScene`, only touching what changed since the
+/// last run.
+/// After the device is recreated everything is extracted again.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn extract_scene(
+    mut renderer: ResMut<Renderer>,
+    mut generation: Local<u64>,
+    meshes: Res<Assets<Mesh>>,
+    materials: Res<Assets<Material>>,
+    textures: Res<Assets<Texture>>,
+    mut mesh_events: EventReader<AssetEvent<Mesh>>,
+    mut material_events: EventReader<AssetEvent<Material>>,
+    mut texture_events: EventReader<AssetEvent<Texture>>,
+    entities: Query<SceneItem>,
+    changed_entities: Query<SceneItem, SceneChanged>,
+    removed_meshes: RemovedComponents<Handle<Mesh>>,
+    removed_materials: RemovedComponents<Handle<Material>>,
+    lights: Query<(&Light, &GlobalTransform)>,
+    changed_lights: Query<Entity, (With<Light>, LightChanged)>,
+    removed_lights: RemovedComponents<Light>,
+) {
+    let full = *generation != renderer.generation();
+    *generation = renderer.generation();
+
+    let render_context = renderer.render_context_mut();
+    let device = &render_context.device;
+    let uploads = &mut render_context.uploads;
+    let scene = &mut render_context.scene;
+
+    if full {
+        for (id, texture) in textures.iter() {
+            scene.upload_texture(device, uploads, id, texture);
+        }
+        for (id, mesh) in meshes.iter() {
+            scene.upload_mesh(device, uploads, id, mesh);
+        }
+        for (id, material) in materials.iter() {
+            scene.set_material(id, material);
+        }
+    }
+
+    for event in mesh_events.iter().filter(|_| !full) {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                if let Some(mesh) = meshes.get(handle) {
+                    scene.upload_mesh(device, uploads, handle.id, mesh);
+                }
+            }
+            AssetEvent::Removed { handle } => scene.remove_mesh(device, handle.id),
+        }
+    }
+
+    for event in texture_events.iter().filter(|_| !full) {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                if let Some(texture) = textures.get(handle) {
+                    scene.upload_texture(device, uploads, handle.id, texture);
+                }
+            }
+            AssetEvent::Removed { handle } => scene.remove_texture(device, handle.id),
+        }
+    }
+
+    for event in material_events.iter().filter(|_| !full) {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                if let Some(material) = materials.get(handle) {
+                    scene.set_material(handle.id, material);
+                }
+            }
+            AssetEvent::Removed { handle } => scene.remove_material(handle.id),
+        }
+    }
+
+    for entity in removed_meshes.iter().chain(removed_materials.iter()) {
+        scene.remove_entity(entity);
+    }
+
+    if full {
+        for item in entities.iter() {
+            extract_entity(scene, item);
+        }
+    } else {
+        for item in changed_entities.iter() {
+            extract_entity(scene, item);
+        }
+    }
+
+    if full || changed_lights.iter().next().is_some() || removed_lights.iter().next().is_some() {
+        scene.set_lights(lights.iter());
+    }
+}
+
+fn extract_entity(
+    scene: &mut RenderScene,
+    (entity, mesh, material, transform, visible): SceneItem,
+) {
+    if visible.map_or(true, |visible| visible.is_visible) {
+        scene.set_entity(entity, mesh.id, material.id, transform.compute_matrix());
+    } else {
+        scene.remove_entity(entity);
+    }
+}
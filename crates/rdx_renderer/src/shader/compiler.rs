@@ -0,0 +1,680 @@
+use crate::shader::{shader_source_dir, ShaderLanguage, ShaderModuleInfo};
+use naga::back::spv;
+use naga::front::{glsl, wgsl};
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Error produced while turning shader source into SPIR-V.
+///
+/// `line` is 1-based and refers to `file`, which may be an `#include`d file
+/// rather than the module the compilation was started from.
+pub struct ShaderCompileError {
+    pub file: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ShaderCompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file.display(), line, self.message),
+            None => write!(f, "{}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+impl fmt::Debug for ShaderCompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ShaderCompileError {}
+
+/// Compiles GLSL or WGSL source to SPIR-V words.
+///
+/// `#include "file"` directives are resolved first relative to the including
+/// file and then relative to the shader source directory, skipping those in
+/// inactive conditional blocks. Conditionals are evaluated against
+/// `info.defines` and the source's own `#define`s. GLSL keeps them for naga's
+/// preprocessor, WGSL has none so they are stripped here.
+pub fn compile_to_spirv(info: &ShaderModuleInfo) -> Result<Vec<u32>, ShaderCompileError> {
+    let (path, preprocessed) = preprocess(info)?;
+
+    let module = match info.language {
+        ShaderLanguage::GLSL => {
+            let stage = glsl_stage(&info.name).map_err(|message| ShaderCompileError {
+                file: path.clone(),
+                line: None,
+                message,
+            })?;
+
+            let mut options = glsl::Options::from(stage);
+            options.defines.extend(info.defines.clone());
+
+            glsl::Parser::default()
+                .parse(&options, &preprocessed.source)
+                .map_err(|errors| {
+                    let offset = errors
+                        .iter()
+                        .find_map(|error| error.meta.to_range())
+                        .map(|range| range.start);
+                    let message = errors
+                        .iter()
+                        .map(|error| error.to_string())
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    preprocessed.error(&path, offset, message)
+                })?
+        }
+        ShaderLanguage::WGSL => wgsl::parse_str(&preprocessed.source).map_err(|error| {
+            let (line, _) = error.location(&preprocessed.source);
+            let message = error.emit_to_string(&preprocessed.source);
+            preprocessed.error_at_line(&path, line, message)
+        })?,
+        ShaderLanguage::SPIRV => unreachable!("SPIR-V does not need compiling"),
+    };
+
+    let module_info = Validator::new(ValidationFlags::all(), Capabilities::empty())
+        .validate(&module)
+        .map_err(|error| ShaderCompileError {
+            file: path.clone(),
+            line: None,
+            message: error.to_string(),
+        })?;
+
+    // Vulkan clip space is already handled by the negative viewport height,
+    // keep the output equivalent to what glslangValidator produces.
+    let options = spv::Options {
+        flags: spv::WriterFlags::empty(),
+        ..Default::default()
+    };
+
+    spv::write_vec(&module, &module_info, &options, None).map_err(|error| ShaderCompileError {
+        file: path,
+        line: None,
+        message: error.to_string(),
+    })
+}
+
+/// Files the source of `info` is made of: the module itself, then every file
+/// it `#include`s outside inactive conditional blocks.
+pub fn resolve_includes(info: &ShaderModuleInfo) -> Result<Vec<PathBuf>, ShaderCompileError> {
+    let (_, preprocessed) = preprocess(info)?;
+    Ok(preprocessed.files)
+}
+
+fn preprocess(info: &ShaderModuleInfo) -> Result<(PathBuf, Preprocessed), ShaderCompileError> {
+    let path = shader_source_dir().join(&*info.name);
+
+    let source = std::str::from_utf8(&info.code).map_err(|error| ShaderCompileError {
+        file: path.clone(),
+        line: None,
+        message: format!("source is not valid utf-8: {}", error),
+    })?;
+
+    let mut preprocessed = Preprocessed::default();
+    preprocessed.include(
+        &path,
+        source,
+        info.language == ShaderLanguage::WGSL,
+        &mut info.defines.clone(),
+        &mut Vec::new(),
+    )?;
+
+    Ok((path, preprocessed))
+}
+
+fn glsl_stage(name: &str) -> Result<naga::ShaderStage, String> {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+
+    match extension {
+        "vert" => Ok(naga::ShaderStage::Vertex),
+        "frag" => Ok(naga::ShaderStage::Fragment),
+        "comp" => Ok(naga::ShaderStage::Compute),
+        // naga has no ray tracing stages, these have to be loaded as SPIR-V.
+        "rgen" | "rmiss" | "rchit" | "rahit" | "rint" | "rcall" => Err(format!(
+            "ray tracing stage .{} cannot be compiled from GLSL, load the precompiled SPIR-V",
+            extension
+        )),
+        _ => Err("cannot infer shader stage from file extension".to_string()),
+    }
+}
+
+/// State of one `#if` group while preprocessing.
+struct Conditional {
+    /// Whether lines in the current branch are compiled.
+    active: bool,
+    /// Whether an earlier branch of the group was taken, or the whole group is
+    /// inside an inactive block.
+    taken: bool,
+}
+
+fn first_word(text: &str) -> &str {
+    text.split_whitespace().next().unwrap_or_default()
+}
+
+/// Evaluates an `#if` or `#elif` expression. Supports integers, macros with
+/// integer values, `defined`, parentheses, `!`, comparisons, `&&` and `||`;
+/// undefined names are 0 as in the C preprocessor.
+fn evaluate_condition(
+    expression: &str,
+    defines: &BTreeMap<String, String>,
+) -> Result<bool, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let mut end = start;
+            while let Some(&(index, c)) = chars.peek() {
+                if !c.is_ascii_alphanumeric() && c != '_' {
+                    break;
+                }
+                end = index + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(&expression[start..end]);
+        } else {
+            chars.next();
+            let two = expression.get(start..start + 2);
+            match two {
+                Some("&&") | Some("||") | Some("==") | Some("!=") | Some("<=") | Some(">=") => {
+                    chars.next();
+                    tokens.push(two.unwrap());
+                }
+                _ => tokens.push(&expression[start..start + c.len_utf8()]),
+            }
+        }
+    }
+
+    let mut parser = ConditionParser {
+        tokens: &tokens,
+        position: 0,
+        defines,
+    };
+    let value = parser.or()?;
+    match parser.tokens.get(parser.position) {
+        None => Ok(value != 0),
+        Some(token) => Err(format!("unexpected `{}` in #if expression", token)),
+    }
+}
+
+struct ConditionParser<'a> {
+    tokens: &'a [&'a str],
+    position: usize,
+    defines: &'a BTreeMap<String, String>,
+}
+
+impl<'a> ConditionParser<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.tokens.get(self.position).copied();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.tokens.get(self.position) == Some(&token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<i64, String> {
+        let mut value = self.and()?;
+        while self.eat("||") {
+            let rhs = self.and()?;
+            value = (value != 0 || rhs != 0) as i64;
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<i64, String> {
+        let mut value = self.comparison()?;
+        while self.eat("&&") {
+            let rhs = self.comparison()?;
+            value = (value != 0 && rhs != 0) as i64;
+        }
+        Ok(value)
+    }
+
+    fn comparison(&mut self) -> Result<i64, String> {
+        let lhs = self.unary()?;
+        let compare: fn(&i64, &i64) -> bool = match self.tokens.get(self.position) {
+            Some(&"==") => i64::eq,
+            Some(&"!=") => i64::ne,
+            Some(&"<") => i64::lt,
+            Some(&">") => i64::gt,
+            Some(&"<=") => i64::le,
+            Some(&">=") => i64::ge,
+            _ => return Ok(lhs),
+        };
+        self.position += 1;
+        let rhs = self.unary()?;
+        Ok(compare(&lhs, &rhs) as i64)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        match self.next() {
+            Some("!") => Ok((self.unary()? == 0) as i64),
+            Some("(") => {
+                let value = self.or()?;
+                if self.eat(")") {
+                    Ok(value)
+                } else {
+                    Err("missing `)` in #if expression".to_string())
+                }
+            }
+            Some("defined") => {
+                let parenthesized = self.eat("(");
+                let name = self
+                    .next()
+                    .ok_or_else(|| "missing name after `defined`".to_string())?;
+                if parenthesized && !self.eat(")") {
+                    return Err("missing `)` after `defined`".to_string());
+                }
+                Ok(self.defines.contains_key(name) as i64)
+            }
+            Some(token) if token.starts_with(|c: char| c.is_ascii_digit()) => {
+                parse_integer(token).ok_or_else(|| format!("invalid number `{}`", token))
+            }
+            Some(token) if token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') => {
+                match self.defines.get(token) {
+                    Some(value) => parse_integer(value.trim()).ok_or_else(|| {
+                        format!(
+                            "`{}` does not expand to an integer in #if expression",
+                            token
+                        )
+                    }),
+                    None => Ok(0),
+                }
+            }
+            Some(token) => Err(format!("unexpected `{}` in #if expression", token)),
+            None => Err("incomplete #if expression".to_string()),
+        }
+    }
+}
+
+fn parse_integer(text: &str) -> Option<i64> {
+    let text = text.trim_end_matches(|c| c == 'u' || c == 'U');
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Flattened source with every output line mapped back to the file and line it
+/// came from.
+#[derive(Default)]
+struct Preprocessed {
+    source: String,
+    files: Vec<PathBuf>,
+    lines: Vec<(usize, usize)>,
+}
+
+impl Preprocessed {
+    fn include(
+        &mut self,
+        path: &Path,
+        source: &str,
+        evaluate_conditionals: bool,
+        defines: &mut BTreeMap<String, String>,
+        include_stack: &mut Vec<PathBuf>,
+    ) -> Result<(), ShaderCompileError> {
+        if include_stack.iter().any(|included| included == path) {
+            return Err(ShaderCompileError {
+                file: path.to_path_buf(),
+                line: None,
+                message: "recursive #include".to_string(),
+            });
+        }
+        include_stack.push(path.to_path_buf());
+
+        let file_index = self.files.len();
+        self.files.push(path.to_path_buf());
+
+        let mut conditionals = Vec::<Conditional>::new();
+
+        for (line_index, line) in source.lines().enumerate() {
+            let line_number = line_index + 1;
+            let directive = line.trim_start();
+            let error = |message: String| ShaderCompileError {
+                file: path.to_path_buf(),
+                line: Some(line_number),
+                message,
+            };
+
+            let enclosing_active = conditionals.iter().all(|conditional| conditional.active);
+            let (keyword, rest) = match directive.strip_prefix('#') {
+                Some(directive) => {
+                    let directive = directive.trim_start();
+                    let end = directive
+                        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                        .unwrap_or_else(|| directive.len());
+                    (&directive[..end], directive[end..].trim())
+                }
+                None => ("", ""),
+            };
+
+            let handled = match keyword {
+                "if" | "ifdef" | "ifndef" => {
+                    let condition = enclosing_active
+                        && match keyword {
+                            "ifdef" => defines.contains_key(first_word(rest)),
+                            "ifndef" => !defines.contains_key(first_word(rest)),
+                            _ => evaluate_condition(rest, defines).map_err(error)?,
+                        };
+                    conditionals.push(Conditional {
+                        active: condition,
+                        taken: condition || !enclosing_active,
+                    });
+                    true
+                }
+                "elif" => {
+                    let conditional = conditionals
+                        .last_mut()
+                        .ok_or_else(|| error("#elif without #if".to_string()))?;
+                    conditional.active =
+                        !conditional.taken && evaluate_condition(rest, defines).map_err(error)?;
+                    conditional.taken |= conditional.active;
+                    true
+                }
+                "else" => {
+                    let conditional = conditionals
+                        .last_mut()
+                        .ok_or_else(|| error("#else without #if".to_string()))?;
+                    conditional.active = !conditional.taken;
+                    conditional.taken = true;
+                    true
+                }
+                "endif" => {
+                    conditionals
+                        .pop()
+                        .ok_or_else(|| error("#endif without #if".to_string()))?;
+                    true
+                }
+                _ => false,
+            };
+
+            let active = conditionals.iter().all(|conditional| conditional.active);
+
+            if handled || !active {
+                // GLSL conditionals stay for naga, WGSL cannot parse them and
+                // naga does not know `#include`.
+                let kept = if evaluate_conditionals || keyword == "include" {
+                    ""
+                } else {
+                    line
+                };
+                self.push_line(file_index, line_number, kept);
+                continue;
+            }
+
+            match keyword {
+                "define" => {
+                    let end = rest
+                        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                        .unwrap_or_else(|| rest.len());
+                    let name = &rest[..end];
+                    let value = rest[name.len()..].trim();
+                    defines.insert(name.to_string(), value.to_string());
+                }
+                "undef" => {
+                    defines.remove(first_word(rest));
+                }
+                "include" => {
+                    let target = rest.trim_matches(|c| c == '"' || c == '<' || c == '>');
+
+                    let include_path = path
+                        .parent()
+                        .map(|dir| dir.join(target))
+                        .filter(|candidate| candidate.is_file())
+                        .unwrap_or_else(|| shader_source_dir().join(target));
+
+                    let include_source = fs::read_to_string(&include_path).map_err(|io_error| {
+                        error(format!(
+                            "failed to include {:?}: {}",
+                            include_path.as_os_str(),
+                            io_error
+                        ))
+                    })?;
+
+                    self.include(
+                        &include_path,
+                        &include_source,
+                        evaluate_conditionals,
+                        defines,
+                        include_stack,
+                    )?;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let kept = match keyword {
+                "define" | "undef" if evaluate_conditionals => "",
+                _ => line,
+            };
+            self.push_line(file_index, line_number, kept);
+        }
+
+        if !conditionals.is_empty() {
+            return Err(ShaderCompileError {
+                file: path.to_path_buf(),
+                line: None,
+                message: "unterminated #if".to_string(),
+            });
+        }
+
+        include_stack.pop();
+        Ok(())
+    }
+
+    fn push_line(&mut self, file_index: usize, line_number: usize, line: &str) {
+        self.source.push_str(line);
+        self.source.push('\n');
+        self.lines.push((file_index, line_number));
+    }
+
+    fn error(&self, root: &Path, offset: Option<usize>, message: String) -> ShaderCompileError {
+        match offset {
+            Some(offset) => {
+                let offset = offset.min(self.source.len());
+                let line = self.source[..offset].matches('\n').count() + 1;
+                self.error_at_line(root, line, message)
+            }
+            None => ShaderCompileError {
+                file: root.to_path_buf(),
+                line: None,
+                message,
+            },
+        }
+    }
+
+    fn error_at_line(&self, root: &Path, line: usize, message: String) -> ShaderCompileError {
+        match line.checked_sub(1).and_then(|index| self.lines.get(index)) {
+            Some(&(file_index, line)) => ShaderCompileError {
+                file: self.files[file_index].clone(),
+                line: Some(line),
+                message,
+            },
+            None => ShaderCompileError {
+                file: root.to_path_buf(),
+                line: None,
+                message,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for the files of one test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "rdx_shader_compiler_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn defines(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn preprocess(
+        path: &Path,
+        defines: &mut BTreeMap<String, String>,
+    ) -> Result<Preprocessed, ShaderCompileError> {
+        let source = fs::read_to_string(path).unwrap();
+        let mut preprocessed = Preprocessed::default();
+        preprocessed.include(path, &source, true, defines, &mut Vec::new())?;
+        Ok(preprocessed)
+    }
+
+    #[test]
+    fn condition_precedence() {
+        let none = BTreeMap::new();
+        assert!(evaluate_condition("1 || 0 && 0", &none).unwrap());
+        assert!(!evaluate_condition("(1 || 0) && 0", &none).unwrap());
+        assert!(evaluate_condition("!0 && 2 > 1", &none).unwrap());
+        assert!(!evaluate_condition("!(1 == 1) || 3 <= 2", &none).unwrap());
+        assert!(evaluate_condition("1 == 1 && 2 != 3", &none).unwrap());
+        assert!(evaluate_condition("0x10 == 16", &none).unwrap());
+    }
+
+    #[test]
+    fn condition_defined_and_macros() {
+        let defines = defines(&[("A", "1"), ("LEVEL", "3"), ("EMPTY", "")]);
+        assert!(evaluate_condition("defined(A)", &defines).unwrap());
+        assert!(evaluate_condition("defined EMPTY", &defines).unwrap());
+        assert!(!evaluate_condition("defined(B)", &defines).unwrap());
+        assert!(evaluate_condition("!defined(B) && LEVEL >= 2", &defines).unwrap());
+        // Undefined names are 0.
+        assert!(evaluate_condition("B == 0", &defines).unwrap());
+        assert!(evaluate_condition("EMPTY", &defines).is_err());
+        assert!(evaluate_condition("(A", &defines).is_err());
+        assert!(evaluate_condition("A A", &defines).is_err());
+    }
+
+    #[test]
+    fn conditional_branches() {
+        let dir = test_dir("branches");
+        let path = dir.join("main.wgsl");
+        fs::write(
+            &path,
+            "#if LEVEL > 2\nhigh\n#elif LEVEL > 1\nmiddle\n#else\nlow\n#endif\n#ifndef LEVEL\nunset\n#endif\n",
+        )
+        .unwrap();
+
+        let lines = |level: Option<&str>| {
+            let mut defines =
+                level.map_or_else(BTreeMap::new, |level| defines(&[("LEVEL", level)]));
+            preprocess(&path, &mut defines)
+                .unwrap()
+                .source
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(lines(Some("3")), ["high"]);
+        assert_eq!(lines(Some("2")), ["middle"]);
+        assert_eq!(lines(None), ["low", "unset"]);
+    }
+
+    #[test]
+    fn inactive_includes_are_not_followed() {
+        let dir = test_dir("inactive");
+        let path = dir.join("main.wgsl");
+        fs::write(
+            &path,
+            "#ifdef MISSING\n#include \"missing.wgsl\"\n#else\n#include \"present.wgsl\"\n#endif\n",
+        )
+        .unwrap();
+        fs::write(dir.join("present.wgsl"), "present\n").unwrap();
+
+        let preprocessed = preprocess(&path, &mut BTreeMap::new()).unwrap();
+        assert_eq!(preprocessed.files, [path, dir.join("present.wgsl")]);
+        assert!(preprocessed.source.contains("present"));
+
+        let error = preprocess(&dir.join("main.wgsl"), &mut defines(&[("MISSING", "1")]))
+            .err()
+            .unwrap();
+        assert_eq!(error.line, Some(2));
+        assert!(error.message.contains("missing.wgsl"));
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let dir = test_dir("cycle");
+        fs::write(dir.join("a.wgsl"), "#include \"b.wgsl\"\n").unwrap();
+        fs::write(dir.join("b.wgsl"), "#include \"a.wgsl\"\n").unwrap();
+
+        let error = preprocess(&dir.join("a.wgsl"), &mut BTreeMap::new())
+            .err()
+            .unwrap();
+        assert_eq!(error.file, dir.join("a.wgsl"));
+        assert!(error.message.contains("recursive"));
+    }
+
+    #[test]
+    fn included_lines_map_back() {
+        let dir = test_dir("lines");
+        let path = dir.join("main.wgsl");
+        fs::write(&path, "first\n#include \"common.wgsl\"\nlast\n").unwrap();
+        fs::write(dir.join("common.wgsl"), "one\ntwo\n").unwrap();
+
+        let preprocessed = preprocess(&path, &mut BTreeMap::new()).unwrap();
+        // The `#include` line itself is replaced by the included lines.
+        let error = preprocessed.error_at_line(&path, 3, String::new());
+        assert_eq!(error.file, dir.join("common.wgsl"));
+        assert_eq!(error.line, Some(2));
+
+        let error = preprocessed.error_at_line(&path, 4, String::new());
+        assert_eq!(error.file, path);
+        assert_eq!(error.line, Some(3));
+    }
+
+    #[test]
+    fn naga_errors_in_includes_map_back() {
+        let dir = test_dir("naga");
+        let path = dir.join("main.frag");
+        let source = "#version 450\n#include \"common.glsl\"\nlayout(location = 0) out vec4 color;\nvoid main() {\n    color = vec4(broken());\n}\n";
+        fs::write(&path, source).unwrap();
+        fs::write(
+            dir.join("common.glsl"),
+            "// Shared helpers.\nfloat broken() {\n    return missing_value;\n}\n",
+        )
+        .unwrap();
+
+        // An absolute name replaces the shader source directory.
+        let info = ShaderModuleInfo {
+            name: path.to_str().unwrap().into(),
+            code: source.as_bytes().into(),
+            language: ShaderLanguage::GLSL,
+            defines: BTreeMap::new(),
+        };
+        let error = compile_to_spirv(&info).err().unwrap();
+        assert_eq!(error.file, dir.join("common.glsl"));
+        assert_eq!(error.line, Some(3));
+    }
+}
@@ -0,0 +1,82 @@
+pub use self::compiler::*;
+pub use self::variant::*;
+pub use self::watcher::*;
+
+use crate::assets::{self, AssetError};
+use crate::resources::ShaderModule;
+use erupt::vk;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+mod compiler;
+mod variant;
+mod watcher;
+
+#[derive(Clone)]
+pub struct Shader {
+    pub module: ShaderModule,
+    pub entry: Box<str>,
+    pub stage: vk::ShaderStageFlags,
+}
+
+impl Shader {
+    pub fn new(module: ShaderModule, stage: vk::ShaderStageFlags) -> Self {
+        Shader {
+            module,
+            entry: "main".into(),
+            stage,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ShaderModuleInfo {
+    pub name: Box<str>,
+    pub code: Box<[u8]>,
+    pub language: ShaderLanguage,
+    pub defines: BTreeMap<String, String>,
+}
+
+impl ShaderModuleInfo {
+    pub fn new(file: &str, language: ShaderLanguage) -> Self {
+        Self::load(file, language).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn load(file: &str, language: ShaderLanguage) -> Result<Self, AssetError> {
+        let path = Path::new("shaders").join(file);
+        tracing::debug!("reading shader {:?}", path);
+        let bytes = assets::read(path)?;
+
+        Ok(ShaderModuleInfo {
+            name: file.into(),
+            code: bytes.into_owned().into(),
+            language,
+            defines: BTreeMap::new(),
+        })
+    }
+
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.defines.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn with_variant(mut self, key: &ShaderVariantKey) -> Self {
+        self.defines.extend(
+            key.defines()
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ShaderLanguage {
+    GLSL,
+    WGSL,
+    SPIRV,
+}
+
+pub fn shader_source_dir() -> PathBuf {
+    assets::asset_path("shaders")
+}
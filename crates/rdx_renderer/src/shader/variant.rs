@@ -0,0 +1,171 @@
+use crate::device::Device;
+use crate::resources::ShaderModule;
+use crate::shader::{
+    shader_source_dir, Shader, ShaderCompileError, ShaderLanguage, ShaderModuleInfo, ShaderReloads,
+};
+use erupt::vk;
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
+
+/// Set of preprocessor defines selecting one permutation of a shader.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ShaderVariantKey {
+    defines: BTreeMap<String, String>,
+}
+
+impl ShaderVariantKey {
+    pub fn new() -> Self {
+        ShaderVariantKey::default()
+    }
+
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.defines.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Defines `name` as `1` when `enabled`, leaves it undefined otherwise so
+    /// both `#ifdef` and `#if` work in the shader.
+    pub fn flag(self, name: &str, enabled: bool) -> Self {
+        if enabled {
+            self.define(name, "1")
+        } else {
+            self
+        }
+    }
+
+    pub fn defines(&self) -> &BTreeMap<String, String> {
+        &self.defines
+    }
+}
+
+impl From<BTreeMap<String, String>> for ShaderVariantKey {
+    fn from(defines: BTreeMap<String, String>) -> Self {
+        ShaderVariantKey { defines }
+    }
+}
+
+/// Every permutation of a single shader source, compiled on first use.
+///
+/// SPIR-V variants are precompiled by the build script, each flag appended to
+/// the file name: `shader.frag.spv` with `ALPHA_MASK` is loaded from
+/// `shader.frag.ALPHA_MASK.spv`.
+pub struct ShaderVariants {
+    name: Box<str>,
+    language: ShaderLanguage,
+    stage: vk::ShaderStageFlags,
+    modules: Mutex<HashMap<ShaderVariantKey, ShaderModule>>,
+}
+
+impl ShaderVariants {
+    pub fn new(name: &str, language: ShaderLanguage, stage: vk::ShaderStageFlags) -> Self {
+        ShaderVariants {
+            name: name.into(),
+            language,
+            stage,
+            modules: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get(
+        &self,
+        device: &Device,
+        key: &ShaderVariantKey,
+    ) -> Result<Shader, ShaderCompileError> {
+        if let Some(module) = self.modules.lock().get(key) {
+            return Ok(Shader::new(module.clone(), self.stage));
+        }
+
+        // Compiled without holding the lock, a variant compiled concurrently
+        // by another thread wins and ours is dropped.
+        let compiled = self.compile(device, key)?;
+        let module = self
+            .modules
+            .lock()
+            .entry(key.clone())
+            .or_insert_with(|| compiled.clone())
+            .clone();
+        if module.handle() != compiled.handle() {
+            device.destroy_shader_module(&compiled);
+        }
+
+        Ok(Shader::new(module, self.stage))
+    }
+
+    /// Replaces the cached variants affected by `reloads`; variants that fail
+    /// to recompile keep their previous module.
+    pub fn reload(&self, reloads: &mut ShaderReloads, device: &Device) {
+        let cached = self.modules.lock().clone();
+
+        for (key, module) in cached {
+            if let Some(shader) = reloads.reload(device, &Shader::new(module, self.stage)) {
+                self.modules.lock().insert(key, shader.module);
+            }
+        }
+    }
+
+    /// Destroys every cached variant.
+    pub fn destroy(&self, device: &Device) {
+        for (_, module) in self.modules.lock().drain() {
+            device.destroy_shader_module(&module);
+        }
+    }
+
+    fn compile(
+        &self,
+        device: &Device,
+        key: &ShaderVariantKey,
+    ) -> Result<ShaderModule, ShaderCompileError> {
+        let error = |message: String| ShaderCompileError {
+            file: shader_source_dir().join(&*self.name),
+            line: None,
+            message,
+        };
+
+        let file = match self.language {
+            ShaderLanguage::SPIRV => spirv_variant_file(&self.name, key).map_err(error)?,
+            ShaderLanguage::GLSL | ShaderLanguage::WGSL => self.name.to_string(),
+        };
+
+        // Named after the base module so reloading finds its source.
+        let info = ShaderModuleInfo {
+            name: self.name.clone(),
+            ..ShaderModuleInfo::load(&file, self.language)
+                .map_err(|asset_error| error(asset_error.to_string()))?
+        }
+        .with_variant(key);
+
+        tracing::debug!("compiling shader {} variant {:?}", self.name, key.defines);
+        device.try_create_shader_module(info)
+    }
+}
+
+/// File the build script compiles the `key` variant of `name` to.
+fn spirv_variant_file(name: &str, key: &ShaderVariantKey) -> Result<String, String> {
+    let stem = name.strip_suffix(".spv").unwrap_or(name);
+    let mut file = stem.to_string();
+
+    for (define, value) in &key.defines {
+        if value != "1" {
+            return Err(format!(
+                "precompiled SPIR-V variants only support flags, {} is {:?}",
+                define, value
+            ));
+        }
+        file.push('.');
+        file.push_str(define);
+    }
+
+    file.push_str(".spv");
+    Ok(file)
+}
+
+impl Shader {
+    /// The defines this shader's module was compiled with.
+    pub fn variant(&self) -> ShaderVariantKey {
+        self.module.info().defines.clone().into()
+    }
+}
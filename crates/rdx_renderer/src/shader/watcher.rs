@@ -0,0 +1,311 @@
+use crate::assets::AssetError;
+use crate::device::Device;
+use crate::pipeline::RayTracingPipelineInfo;
+use crate::resources::{GraphicsPipeline, ShaderModule};
+use crate::shader::{
+    compile_to_spirv, resolve_includes, shader_source_dir, Shader, ShaderCompileError,
+    ShaderLanguage, ShaderModuleInfo,
+};
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+const RAY_TRACING_EXTENSIONS: &[&str] = &["rgen", "rchit", "rmiss", "rahit", "rint", "rcall"];
+
+/// Watches the shader source directory for changes.
+pub struct ShaderWatcher {
+    root: PathBuf,
+    events: Receiver<DebouncedEvent>,
+    _watcher: RecommendedWatcher,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> Option<Self> {
+        let root = shader_source_dir();
+        let root = root.canonicalize().unwrap_or(root);
+
+        let (sender, events) = channel();
+        let watcher =
+            notify::watcher(sender, Duration::from_millis(200)).and_then(|mut watcher| {
+                watcher.watch(&root, RecursiveMode::Recursive)?;
+                Ok(watcher)
+            });
+
+        match watcher {
+            Ok(watcher) => Some(ShaderWatcher {
+                root,
+                events,
+                _watcher: watcher,
+            }),
+            Err(error) => {
+                tracing::warn!("shader hot reloading disabled: {}", error);
+                None
+            }
+        }
+    }
+
+    /// Returns the shader sources changed since the last poll, if any.
+    pub fn poll(&self) -> Option<ShaderReloads> {
+        let changed = self
+            .events
+            .try_iter()
+            .filter_map(|event| match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Rename(_, path) => Some(path),
+                _ => None,
+            })
+            .filter(|path| path.extension() != Some(OsStr::new("spv")))
+            .filter_map(|path| path.strip_prefix(&self.root).map(Path::to_path_buf).ok())
+            .collect::<HashSet<_>>();
+
+        if changed.is_empty() {
+            None
+        } else {
+            tracing::info!("shader sources changed: {:?}", changed);
+            Some(ShaderReloads {
+                root: self.root.clone(),
+                changed,
+                modules: HashMap::new(),
+            })
+        }
+    }
+}
+
+/// A batch of changed shader sources.
+///
+/// Every module is recompiled at most once per batch, so pipelines sharing a
+/// module end up sharing the reloaded one too. When compilation fails the error
+/// is logged and callers keep using their current module and pipeline.
+pub struct ShaderReloads {
+    root: PathBuf,
+    changed: HashSet<PathBuf>,
+    modules: HashMap<(Box<str>, ShaderLanguage, BTreeMap<String, String>), Option<ShaderModule>>,
+}
+
+impl ShaderReloads {
+    pub fn reload(&mut self, device: &Device, shader: &Shader) -> Option<Shader> {
+        let info = shader.module.info();
+        if !self.affects(info) {
+            return None;
+        }
+
+        let module = self
+            .modules
+            .entry((info.name.clone(), info.language, info.defines.clone()))
+            .or_insert_with(|| match recompile(device, info) {
+                Ok(module) => {
+                    tracing::info!("reloaded shader {}", info.name);
+                    Some(module)
+                }
+                Err(error) => {
+                    tracing::error!(
+                        "failed to reload shader {}, keeping the previous version: {}",
+                        info.name,
+                        error
+                    );
+                    None
+                }
+            })
+            .clone()?;
+
+        Some(Shader {
+            module,
+            entry: shader.entry.clone(),
+            stage: shader.stage,
+        })
+    }
+
+    /// Recreates `pipeline` if any of its shaders changed and recompiled.
+    pub fn reload_graphics_pipeline(
+        &mut self,
+        device: &Device,
+        pipeline: &GraphicsPipeline,
+    ) -> Option<GraphicsPipeline> {
+        let mut info = pipeline.info().clone();
+        let mut reloaded = false;
+
+        if let Some(shader) = self.reload(device, &info.vertex_shader) {
+            info.vertex_shader = shader;
+            reloaded = true;
+        }
+
+        if let Some(fragment_shader) = info
+            .rasterizer
+            .as_mut()
+            .and_then(|rasterizer| rasterizer.fragment_shader.as_mut())
+        {
+            if let Some(shader) = self.reload(device, fragment_shader) {
+                *fragment_shader = shader;
+                reloaded = true;
+            }
+        }
+
+        if reloaded {
+            Some(device.create_graphics_pipeline(info))
+        } else {
+            None
+        }
+    }
+
+    /// Returns `info` with its changed shaders replaced, for the owner of the
+    /// ray tracing pipeline to recreate it from.
+    pub fn reload_ray_tracing_pipeline(
+        &mut self,
+        device: &Device,
+        info: &RayTracingPipelineInfo,
+    ) -> Option<RayTracingPipelineInfo> {
+        let mut info = info.clone();
+        let mut reloaded = false;
+
+        for shader in &mut info.shaders {
+            if let Some(reloaded_shader) = self.reload(device, shader) {
+                *shader = reloaded_shader;
+                reloaded = true;
+            }
+        }
+
+        if reloaded {
+            Some(info)
+        } else {
+            None
+        }
+    }
+
+    fn affects(&self, info: &ShaderModuleInfo) -> bool {
+        if self.changed.contains(Path::new(source_name(info))) {
+            return true;
+        }
+
+        // Includes are resolved against the current sources, so a module that
+        // fails to preprocess is recompiled to report why.
+        let source = match glsl_source(info) {
+            Ok(source) => source,
+            Err(_) => return true,
+        };
+
+        match resolve_includes(&source) {
+            Ok(includes) => includes.iter().any(|include| {
+                let include = include.canonicalize().unwrap_or_else(|_| include.clone());
+                include
+                    .strip_prefix(&self.root)
+                    .map_or(false, |relative| self.changed.contains(relative))
+            }),
+            Err(_) => true,
+        }
+    }
+}
+
+fn recompile(device: &Device, info: &ShaderModuleInfo) -> Result<ShaderModule, ShaderCompileError> {
+    let load_error = |error: AssetError| ShaderCompileError {
+        file: shader_source_dir().join(source_name(info)),
+        line: None,
+        message: error.to_string(),
+    };
+
+    let reloaded_info = match info.language {
+        // Precompiled modules are rebuilt from their GLSL source in memory,
+        // the SPIR-V on disk is left to the build script.
+        ShaderLanguage::SPIRV => {
+            let source = glsl_source(info).map_err(load_error)?;
+            let code = if is_ray_tracing_stage(Path::new(&*source.name)) {
+                compile_with_glslang(&source)?
+            } else {
+                compile_to_spirv(&source)?
+                    .iter()
+                    .flat_map(|word| word.to_le_bytes())
+                    .collect()
+            };
+
+            ShaderModuleInfo {
+                name: info.name.clone(),
+                code,
+                language: ShaderLanguage::SPIRV,
+                defines: info.defines.clone(),
+            }
+        }
+        ShaderLanguage::GLSL | ShaderLanguage::WGSL => ShaderModuleInfo {
+            defines: info.defines.clone(),
+            ..ShaderModuleInfo::load(&info.name, info.language).map_err(load_error)?
+        },
+    };
+
+    device.try_create_shader_module(reloaded_info)
+}
+
+/// The source `info` is compiled from, GLSL for precompiled SPIR-V modules.
+fn glsl_source(info: &ShaderModuleInfo) -> Result<ShaderModuleInfo, AssetError> {
+    match info.language {
+        ShaderLanguage::SPIRV => Ok(ShaderModuleInfo {
+            defines: info.defines.clone(),
+            ..ShaderModuleInfo::load(source_name(info), ShaderLanguage::GLSL)?
+        }),
+        ShaderLanguage::GLSL | ShaderLanguage::WGSL => Ok(info.clone()),
+    }
+}
+
+/// naga has no ray tracing stages, these go through glslangValidator like in
+/// the build script. Its output is read back from a temporary file so the
+/// asset directory is left alone.
+fn compile_with_glslang(info: &ShaderModuleInfo) -> Result<Box<[u8]>, ShaderCompileError> {
+    let source = shader_source_dir().join(&*info.name);
+    let error = |message: String| ShaderCompileError {
+        file: source.clone(),
+        line: None,
+        message,
+    };
+
+    let output_path = std::env::temp_dir().join(format!(
+        "rdx-{}-{}.spv",
+        std::process::id(),
+        info.name.replace(|c: char| c == '/' || c == '\\', "_")
+    ));
+
+    let output = Command::new("glslangValidator")
+        .arg("-V")
+        .arg(&source)
+        .args(
+            info.defines
+                .iter()
+                .map(|(name, value)| format!("-D{}={}", name, value)),
+        )
+        .arg("-o")
+        .arg(&output_path)
+        .arg("--target-env")
+        .arg("spirv1.5")
+        .output()
+        .map_err(|io_error| error(format!("failed to run glslangValidator: {}", io_error)))?;
+
+    if !output.status.success() {
+        return Err(error(String::from_utf8_lossy(&output.stdout).into_owned()));
+    }
+
+    let code = fs::read(&output_path).map_err(|io_error| {
+        error(format!(
+            "failed to read glslangValidator output: {}",
+            io_error
+        ))
+    });
+    let _ = fs::remove_file(&output_path);
+    Ok(code?.into())
+}
+
+fn source_name(info: &ShaderModuleInfo) -> &str {
+    match info.language {
+        ShaderLanguage::SPIRV => info.name.strip_suffix(".spv").unwrap_or(&info.name),
+        ShaderLanguage::GLSL | ShaderLanguage::WGSL => &info.name,
+    }
+}
+
+fn is_ray_tracing_stage(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .map_or(false, |extension| {
+            RAY_TRACING_EXTENSIONS.contains(&extension)
+        })
+}
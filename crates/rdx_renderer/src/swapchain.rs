@@ -115,6 +115,14 @@ impl Swapchain {
                 .unwrap()
         };
 
+        for (i, image) in images.iter().enumerate() {
+            device.set_object_name(
+                vk::ObjectType::IMAGE,
+                image.0,
+                &format!("swapchain image {}", i),
+            );
+        }
+
         let semaphores = (0..images.len())
             .map(|_| {
                 (
@@ -138,12 +146,15 @@ impl Swapchain {
             .map(|(image, (acquire, release))| SwapchainImageAndSemaphores {
                 image: Image::new(
                     ImageInfo {
-                        extent: info.surface_capabilities.current_extent,
+                        extent: info.surface_capabilities.current_extent.into(),
                         format: info.surface_format.format,
                         mip_levels: 1,
                         array_layers: 1,
                         samples: vk::SampleCountFlagBits::_1,
                         usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                        cube_compatible: false,
+                        mutable_format: false,
+                        name: None,
                     },
                     image,
                     None,
@@ -168,12 +179,12 @@ impl Swapchain {
         if let Some(inner) = self.inner.as_mut() {
             let wait = self.free_semaphore.clone();
 
-            let index = unsafe {
+            let result = unsafe {
                 device
                     .handle()
                     .acquire_next_image_khr(inner.handle, !0, Some(wait.handle()), None)
-                    .unwrap()
             };
+            let index = device.check_lost(result)?;
 
             let image_and_semaphores = &mut inner.images[index as usize];
 
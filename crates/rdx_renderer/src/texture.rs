@@ -0,0 +1,128 @@
+use crate::device::Device;
+use crate::format;
+use crate::image::{Image, ImageInfo, ImageSubresourceLayers, ImageView, ImageViewInfo};
+use crate::upload::UploadManager;
+use bevy::reflect::TypeUuid;
+use erupt::vk;
+
+/// A 2D image sampled by materials, with tightly packed texels.
+///
+/// Materials decode base color and emissive textures as sRGB and read the
+/// others as linear data, whichever encoding of a format with an sRGB pair
+/// `format` is.
+#[derive(Clone, Debug, TypeUuid)]
+#[uuid = "c4e8a2d1-95b3-4f07-b6de-3a7f1e0c9d52"]
+pub struct Texture {
+    pub width: u32,
+    pub height: u32,
+    pub format: vk::Format,
+    pub data: Vec<u8>,
+}
+
+impl Texture {
+    pub fn new(width: u32, height: u32, format: vk::Format, data: Vec<u8>) -> Self {
+        let description = format::describe(format).expect("Unsupported texture format");
+        assert_eq!(
+            data.len() as u64,
+            description.region_size(width, height),
+            "Texture data does not match its extent"
+        );
+
+        Texture {
+            width,
+            height,
+            format,
+            data,
+        }
+    }
+
+    /// RGBA8 texels, in the sRGB or the linear encoding.
+    pub fn from_rgba8(width: u32, height: u32, data: Vec<u8>, srgb: bool) -> Self {
+        let format = if srgb {
+            vk::Format::R8G8B8A8_SRGB
+        } else {
+            vk::Format::R8G8B8A8_UNORM
+        };
+        Texture::new(width, height, format, data)
+    }
+}
+
+/// A texture uploaded to a sampled device local image.
+///
+/// Formats with an `srgb_pair` get a view in each encoding, so materials pick
+/// the color space of a texture by the slot it is used in.
+pub struct GpuTexture {
+    pub image: Image,
+    /// Reads texels as stored.
+    pub linear_view: ImageView,
+    /// Decodes sRGB texels, for formats with an sRGB encoding.
+    pub srgb_view: Option<ImageView>,
+}
+
+impl GpuTexture {
+    pub fn new(
+        device: &Device,
+        uploads: &mut UploadManager,
+        texture: &Texture,
+        name: &str,
+    ) -> Self {
+        let description = format::describe(texture.format).unwrap();
+        let (linear_format, srgb_format) = match description.srgb_pair {
+            Some(pair) if description.srgb => (pair, Some(texture.format)),
+            pair => (texture.format, pair),
+        };
+
+        let image = device.create_image(ImageInfo {
+            extent: vk::Extent2D {
+                width: texture.width,
+                height: texture.height,
+            }
+            .into(),
+            format: texture.format,
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlagBits::_1,
+            usage: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            cube_compatible: false,
+            mutable_format: srgb_format.is_some(),
+            name: Some(name.into()),
+        });
+
+        uploads.upload_image(
+            &image,
+            ImageSubresourceLayers::new(vk::ImageAspectFlags::COLOR, 0, 0..1),
+            vk::Offset3D { x: 0, y: 0, z: 0 },
+            vk::Extent3D {
+                width: texture.width,
+                height: texture.height,
+                depth: 1,
+            },
+            &texture.data,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+
+        let view = |format, suffix| {
+            device.create_image_view(ImageViewInfo {
+                format,
+                name: Some(format!("{} {} view", name, suffix).into()),
+                ..ImageViewInfo::new(image.clone(), vk::ImageAspectFlags::COLOR)
+            })
+        };
+        let linear_view = view(linear_format, "linear");
+        let srgb_view = srgb_format.map(|format| view(format, "sRGB"));
+
+        GpuTexture {
+            image,
+            linear_view,
+            srgb_view,
+        }
+    }
+
+    pub fn destroy(&self, device: &Device) {
+        device.destroy_image_view(&self.linear_view);
+        if let Some(view) = &self.srgb_view {
+            device.destroy_image_view(view);
+        }
+        device.destroy_image(&self.image);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::device::Device;
+use crate::resources::Semaphore;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A value of a timeline semaphore. The GPU work signalling it is finished
+/// once the semaphore counter reaches `value`.
+#[derive(Clone)]
+pub struct TimelinePoint {
+    pub semaphore: Semaphore,
+    pub value: u64,
+}
+
+struct GpuTimelineInner {
+    device: Device,
+    semaphore: Semaphore,
+    next: AtomicU64,
+    completed: AtomicU64,
+    deferred: Mutex<Vec<(u64, Box<dyn FnOnce() + Send>)>>,
+}
+
+/// Monotonically increasing timeline backed by a timeline semaphore.
+///
+/// Every submission reserves the next point. Work that must wait for the GPU,
+/// such as destroying resources still in use or reading back results, is
+/// registered with [`GpuTimeline::defer`] and runs from [`GpuTimeline::poll`]
+/// once its point is reached.
+#[derive(Clone)]
+pub struct GpuTimeline {
+    inner: Arc<GpuTimelineInner>,
+}
+
+impl GpuTimeline {
+    pub fn new(device: &Device) -> Self {
+        GpuTimeline {
+            inner: Arc::new(GpuTimelineInner {
+                device: device.clone(),
+                semaphore: device.create_timeline_semaphore(0),
+                next: AtomicU64::new(1),
+                completed: AtomicU64::new(0),
+                deferred: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn semaphore(&self) -> &Semaphore {
+        &self.inner.semaphore
+    }
+
+    pub fn point(&self, value: u64) -> TimelinePoint {
+        TimelinePoint {
+            semaphore: self.inner.semaphore.clone(),
+            value,
+        }
+    }
+
+    /// Reserves the point the next submission signals.
+    pub fn next_point(&self) -> TimelinePoint {
+        self.point(self.inner.next.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// The last reserved point; reached once all work submitted so far is done.
+    pub fn last_submitted(&self) -> TimelinePoint {
+        self.point(self.inner.next.load(Ordering::Relaxed) - 1)
+    }
+
+    /// Queries the semaphore and runs the deferred work that became ready.
+    pub fn poll(&self) -> u64 {
+        let completed = self.inner.device.semaphore_value(&self.inner.semaphore);
+        self.inner.completed.fetch_max(completed, Ordering::Relaxed);
+        self.run_deferred(completed);
+        completed
+    }
+
+    pub fn is_complete(&self, value: u64) -> bool {
+        value <= self.inner.completed.load(Ordering::Relaxed) || value <= self.poll()
+    }
+
+    /// Blocks until `value` is reached.
+    pub fn wait(&self, value: u64) {
+        if self.is_complete(value) {
+            return;
+        }
+
+        self.inner.device.wait_timeline(&[self.point(value)], !0);
+        self.poll();
+    }
+
+    pub fn wait_idle(&self) {
+        self.wait(self.last_submitted().value);
+    }
+
+    /// Runs `f` once the GPU reaches `value`, or right away when it already has.
+    pub fn defer(&self, value: u64, f: impl FnOnce() + Send + 'static) {
+        if value <= self.inner.completed.load(Ordering::Relaxed) {
+            f();
+        } else {
+            self.inner.deferred.lock().push((value, Box::new(f)));
+        }
+    }
+
+    fn run_deferred(&self, completed: u64) {
+        let ready = {
+            let mut deferred = self.inner.deferred.lock();
+            let (ready, pending) = deferred
+                .drain(..)
+                .partition::<Vec<_>, _>(|(value, _)| *value <= completed);
+            *deferred = pending;
+            ready
+        };
+
+        for (_, f) in ready {
+            f();
+        }
+    }
+}
@@ -0,0 +1,494 @@
+use crate::buffer::BufferInfo;
+use crate::descriptor::{Descriptors, WriteDescriptorSet};
+use crate::device::Device;
+use crate::physical_device::PhysicalDeviceInfo;
+use crate::resources::{Buffer, DescriptorSet, MappableBuffer};
+use crate::timeline::{GpuTimeline, TimelinePoint};
+use crate::util::align_up;
+use crevice::internal::bytemuck;
+use crevice::std140::AsStd140;
+use crevice::std430::{AsStd430, Std430};
+use erupt::vk;
+use gpu_alloc::{MemoryPropertyFlags, UsageFlags};
+use gpu_alloc_erupt::EruptMemoryDevice;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr::NonNull;
+
+/// Number of copies kept by the typed buffers, one per frame in flight.
+pub const DEFAULT_VERSIONS: u32 = 2;
+
+/// A persistently mapped buffer split into equally sized versions.
+///
+/// Each write goes to the next version, first waiting for the GPU to finish
+/// the frame that last read it. Shaders see the current version through a
+/// dynamic descriptor covering one version and `dynamic_offset`.
+struct VersionedBuffer {
+    device: Device,
+    buffer: MappableBuffer,
+    mapping: NonNull<u8>,
+    coherent: bool,
+    stride: u64,
+    version_size: u64,
+    current: u32,
+    in_flight: Vec<Option<TimelinePoint>>,
+    descriptor: [(Buffer, u64, u64); 1],
+}
+
+impl VersionedBuffer {
+    fn new(
+        device: &Device,
+        align_mask: u64,
+        usage_flags: vk::BufferUsageFlags,
+        version_size: u64,
+        versions: u32,
+        name: Option<Box<str>>,
+    ) -> Self {
+        let version_size = version_size.max(1);
+        let stride = align_up(align_mask, version_size).unwrap();
+        let size = stride * versions as u64;
+
+        let mut buffer = device.create_buffer(
+            BufferInfo {
+                align: align_mask,
+                size,
+                usage_flags,
+                allocation_flags: UsageFlags::UPLOAD,
+                name,
+            },
+            UsageFlags::UPLOAD | UsageFlags::HOST_ACCESS,
+        );
+
+        let (mapping, coherent) = unsafe {
+            let memory_block = buffer.memory_block();
+            let coherent = memory_block
+                .props()
+                .contains(MemoryPropertyFlags::HOST_COHERENT);
+            let mapping = memory_block
+                .map(EruptMemoryDevice::wrap(device.handle()), 0, size as usize)
+                .expect("Mapping typed buffer failed");
+            (mapping, coherent)
+        };
+
+        let descriptor = [((*buffer).clone(), 0, version_size)];
+
+        VersionedBuffer {
+            device: device.clone(),
+            buffer,
+            mapping,
+            coherent,
+            stride,
+            version_size,
+            current: versions - 1,
+            in_flight: vec![None; versions as usize],
+            descriptor,
+        }
+    }
+
+    /// Moves to the next version and copies `bytes` to its start.
+    fn write(&mut self, bytes: &[u8]) {
+        debug_assert!(bytes.len() as u64 <= self.version_size);
+
+        self.current = (self.current + 1) % self.in_flight.len() as u32;
+        if let Some(point) = self.in_flight[self.current as usize].take() {
+            self.device.wait_timeline(&[point], !0);
+        }
+
+        let offset = self.dynamic_offset() as u64;
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                self.mapping.as_ptr().add(offset as usize),
+                bytes.len(),
+            );
+        }
+
+        if !self.coherent {
+            unsafe {
+                self.device.flush_mapped_range(
+                    self.buffer.memory_block(),
+                    offset..offset + bytes.len() as u64,
+                );
+            }
+        }
+    }
+
+    fn end_frame(&mut self, point: TimelinePoint) {
+        self.in_flight[self.current as usize] = Some(point);
+    }
+
+    fn dynamic_offset(&self) -> u32 {
+        (self.current as u64 * self.stride) as u32
+    }
+
+    fn versions(&self) -> u32 {
+        self.in_flight.len() as u32
+    }
+
+    /// Waits for every version to be released and destroys the buffer.
+    fn cleanup(&mut self) {
+        let points = self
+            .in_flight
+            .iter_mut()
+            .filter_map(Option::take)
+            .collect::<Vec<_>>();
+        if !points.is_empty() {
+            self.device.wait_timeline(&points, !0);
+        }
+
+        self.unmap();
+        self.device.destroy_buffer(&self.buffer);
+    }
+
+    /// Destroys the buffer once `timeline` reaches the last submitted point,
+    /// for a buffer replaced while frames in flight may still read it.
+    fn retire(mut self, timeline: &GpuTimeline) {
+        self.unmap();
+
+        let device = self.device.clone();
+        let buffer = (*self.buffer).clone();
+        timeline.defer(timeline.last_submitted().value, move || {
+            device.destroy_buffer(&buffer)
+        });
+    }
+
+    fn unmap(&mut self) {
+        unsafe {
+            self.buffer
+                .memory_block()
+                .unmap(EruptMemoryDevice::wrap(self.device.handle()));
+        }
+    }
+}
+
+unsafe impl Send for VersionedBuffer {}
+unsafe impl Sync for VersionedBuffer {}
+
+/// A `T` stored with std140 layout, bound as `UNIFORM_BUFFER_DYNAMIC`.
+///
+/// Write it once per frame, pass `dynamic_offset` when binding the
+/// descriptor set and call `end_frame` with the frame's timeline point.
+pub struct UniformBuffer<T> {
+    inner: VersionedBuffer,
+    marker: PhantomData<fn(&T)>,
+}
+
+impl<T> UniformBuffer<T>
+where
+    T: AsStd140,
+{
+    pub fn new(
+        device: &Device,
+        info: &PhysicalDeviceInfo,
+        versions: u32,
+        name: Option<Box<str>>,
+    ) -> Self {
+        UniformBuffer {
+            inner: VersionedBuffer::new(
+                device,
+                info.device_properties
+                    .limits
+                    .min_uniform_buffer_offset_alignment
+                    - 1,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                mem::size_of::<T::Std140Type>() as u64,
+                versions,
+                name,
+            ),
+            marker: PhantomData,
+        }
+    }
+
+    /// Converts `value` to std140 and writes it to the next version.
+    pub fn write(&mut self, value: &T) {
+        self.inner.write(bytemuck::bytes_of(&value.as_std140()));
+    }
+
+    /// Marks the current version as read by the frame completing at `point`.
+    pub fn end_frame(&mut self, point: TimelinePoint) {
+        self.inner.end_frame(point);
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.inner.buffer
+    }
+
+    pub fn dynamic_offset(&self) -> u32 {
+        self.inner.dynamic_offset()
+    }
+
+    pub fn versions(&self) -> u32 {
+        self.inner.versions()
+    }
+
+    pub fn descriptor_write<'a>(
+        &'a self,
+        set: &'a DescriptorSet,
+        binding: u32,
+    ) -> WriteDescriptorSet<'a> {
+        WriteDescriptorSet {
+            set,
+            binding,
+            element: 0,
+            descriptors: Descriptors::UniformBufferDynamic(&self.inner.descriptor),
+        }
+    }
+
+    pub fn cleanup(&mut self) {
+        self.inner.cleanup();
+    }
+}
+
+/// A `T` stored with std430 layout, bound as `STORAGE_BUFFER_DYNAMIC`.
+pub struct StorageBuffer<T> {
+    inner: VersionedBuffer,
+    marker: PhantomData<fn(&T)>,
+}
+
+impl<T> StorageBuffer<T>
+where
+    T: AsStd430,
+{
+    pub fn new(
+        device: &Device,
+        info: &PhysicalDeviceInfo,
+        versions: u32,
+        name: Option<Box<str>>,
+    ) -> Self {
+        StorageBuffer {
+            inner: VersionedBuffer::new(
+                device,
+                info.device_properties
+                    .limits
+                    .min_storage_buffer_offset_alignment
+                    - 1,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                mem::size_of::<T::Std430Type>() as u64,
+                versions,
+                name,
+            ),
+            marker: PhantomData,
+        }
+    }
+
+    /// Converts `value` to std430 and writes it to the next version.
+    pub fn write(&mut self, value: &T) {
+        self.inner.write(bytemuck::bytes_of(&value.as_std430()));
+    }
+
+    pub fn end_frame(&mut self, point: TimelinePoint) {
+        self.inner.end_frame(point);
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.inner.buffer
+    }
+
+    pub fn dynamic_offset(&self) -> u32 {
+        self.inner.dynamic_offset()
+    }
+
+    pub fn versions(&self) -> u32 {
+        self.inner.versions()
+    }
+
+    pub fn descriptor_write<'a>(
+        &'a self,
+        set: &'a DescriptorSet,
+        binding: u32,
+    ) -> WriteDescriptorSet<'a> {
+        WriteDescriptorSet {
+            set,
+            binding,
+            element: 0,
+            descriptors: Descriptors::StorageBufferDynamic(&self.inner.descriptor),
+        }
+    }
+
+    pub fn cleanup(&mut self) {
+        self.inner.cleanup();
+    }
+}
+
+/// Distance between consecutive `T`s in a std430 array: the size of `T`
+/// rounded up to its alignment.
+pub fn std430_array_stride<T>() -> usize
+where
+    T: AsStd430,
+{
+    let align_mask = <T::Std430Type as Std430>::ALIGNMENT - 1;
+    align_up(align_mask, mem::size_of::<T::Std430Type>()).unwrap()
+}
+
+/// A growable array of `T` with std430 layout, read in shaders as a runtime
+/// sized array bound as `STORAGE_BUFFER_DYNAMIC`.
+///
+/// Elements are edited on the CPU and uploaded by `sync`. When they outgrow
+/// the buffer it is replaced by one twice the size, and `sync` returns `true`
+/// so descriptors pointing at the old buffer can be rewritten.
+pub struct StorageVec<T>
+where
+    T: AsStd430,
+{
+    device: Device,
+    align_mask: u64,
+    name: Option<Box<str>>,
+    /// The elements converted to std430, each padded to `stride` bytes.
+    bytes: Vec<u8>,
+    stride: usize,
+    capacity: usize,
+    inner: VersionedBuffer,
+    marker: PhantomData<fn(&T)>,
+}
+
+impl<T> StorageVec<T>
+where
+    T: AsStd430,
+{
+    pub fn new(
+        device: &Device,
+        info: &PhysicalDeviceInfo,
+        capacity: usize,
+        name: Option<Box<str>>,
+    ) -> Self {
+        let capacity = capacity.max(1);
+        let align_mask = info
+            .device_properties
+            .limits
+            .min_storage_buffer_offset_alignment
+            - 1;
+
+        let stride = std430_array_stride::<T>();
+
+        StorageVec {
+            device: device.clone(),
+            align_mask,
+            name: name.clone(),
+            bytes: Vec::with_capacity(capacity * stride),
+            stride,
+            capacity,
+            inner: Self::create_buffer(device, align_mask, capacity * stride, name),
+            marker: PhantomData,
+        }
+    }
+
+    fn create_buffer(
+        device: &Device,
+        align_mask: u64,
+        size: usize,
+        name: Option<Box<str>>,
+    ) -> VersionedBuffer {
+        VersionedBuffer::new(
+            device,
+            align_mask,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            size as u64,
+            DEFAULT_VERSIONS,
+            name,
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len() / self.stride
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn push(&mut self, value: &T) -> u32 {
+        let index = self.len();
+        self.bytes.resize(self.bytes.len() + self.stride, 0);
+        self.set(index, value);
+        index as u32
+    }
+
+    pub fn set(&mut self, index: usize, value: &T) {
+        let value = value.as_std430();
+        let bytes = bytemuck::bytes_of(&value);
+        let start = index * self.stride;
+        self.bytes[start..start + self.stride][..bytes.len()].copy_from_slice(bytes);
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.bytes.truncate(len * self.stride);
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+    }
+
+    /// Uploads the elements to the next version, growing the buffer when
+    /// needed. Returns `true` when the buffer was replaced; the old one is
+    /// destroyed once `timeline`, the timeline of the queues reading it, is
+    /// done with it.
+    pub fn sync(&mut self, timeline: &GpuTimeline) -> bool {
+        let mut resized = false;
+
+        if self.len() > self.capacity {
+            let capacity = self.len().next_power_of_two();
+            let buffer = Self::create_buffer(
+                &self.device,
+                self.align_mask,
+                capacity * self.stride,
+                self.name.clone(),
+            );
+            mem::replace(&mut self.inner, buffer).retire(timeline);
+            self.capacity = capacity;
+            resized = true;
+        }
+
+        self.inner.write(&self.bytes);
+        resized
+    }
+
+    pub fn end_frame(&mut self, point: TimelinePoint) {
+        self.inner.end_frame(point);
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.inner.buffer
+    }
+
+    pub fn dynamic_offset(&self) -> u32 {
+        self.inner.dynamic_offset()
+    }
+
+    pub fn descriptor_write<'a>(
+        &'a self,
+        set: &'a DescriptorSet,
+        binding: u32,
+    ) -> WriteDescriptorSet<'a> {
+        WriteDescriptorSet {
+            set,
+            binding,
+            element: 0,
+            descriptors: Descriptors::StorageBufferDynamic(&self.inner.descriptor),
+        }
+    }
+
+    pub fn cleanup(&mut self) {
+        self.inner.cleanup();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::LightData;
+    use crate::material::MaterialData;
+    use crate::scene::InstanceData;
+
+    /// Strides of the `Instance`, `Material` and `Light` arrays in
+    /// `shader.vert` and `shader.frag`.
+    #[test]
+    fn std430_array_strides_match_shaders() {
+        assert_eq!(std430_array_stride::<InstanceData>(), 144);
+        assert_eq!(std430_array_stride::<MaterialData>(), 64);
+        assert_eq!(std430_array_stride::<LightData>(), 64);
+    }
+}
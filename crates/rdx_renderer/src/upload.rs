@@ -0,0 +1,551 @@
+use crate::buffer::{BufferInfo, BufferMemoryBarrier};
+use crate::device::Device;
+use crate::format;
+use crate::image::{Image, ImageMemoryBarrier, ImageSubresourceLayers, ImageSubresourceRange};
+use crate::queue::{Queue, SubmitInfo};
+use crate::resources::{Buffer, MappableBuffer};
+use crate::timeline::TimelinePoint;
+use crate::util::align_up;
+use crevice::internal::bytemuck::{self, Pod};
+use erupt::vk;
+use gpu_alloc::{MemoryPropertyFlags, UsageFlags};
+use gpu_alloc_erupt::EruptMemoryDevice;
+use smallvec::SmallVec;
+use std::collections::VecDeque;
+use std::ptr::NonNull;
+
+pub const DEFAULT_STAGING_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Offsets into the ring honour `optimalBufferCopyOffsetAlignment` on every
+/// common implementation.
+const STAGING_ALIGN_MASK: u64 = 255;
+
+/// Identifies the batch an upload was recorded in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UploadId(u64);
+
+enum PendingUpload {
+    Buffer {
+        staging: Buffer,
+        staging_offset: u64,
+        dst: Buffer,
+        dst_offset: u64,
+        size: u64,
+    },
+    Image {
+        staging: Buffer,
+        staging_offset: u64,
+        dst: Image,
+        subresource: ImageSubresourceLayers,
+        offset: vk::Offset3D,
+        extent: vk::Extent3D,
+        final_layout: vk::ImageLayout,
+    },
+}
+
+struct InFlightBatch {
+    id: u64,
+    point: TimelinePoint,
+    ring_end: u64,
+    dedicated: Vec<Buffer>,
+}
+
+/// Streams data into device-local resources through a persistently mapped
+/// staging ring.
+///
+/// Uploads are copied into the ring right away and recorded into a single
+/// transfer command buffer by [`UploadManager::flush`], once per frame, on the
+/// dedicated transfer queue when there is one. Ring space is reclaimed when
+/// the GPU reaches the batch's timeline point.
+///
+/// Uploads larger than the ring, or made while unflushed uploads fill it, get
+/// a dedicated staging buffer destroyed with their batch.
+pub struct UploadManager {
+    device: Device,
+    staging: MappableBuffer,
+    mapping: NonNull<u8>,
+    coherent: bool,
+    size: u64,
+    head: u64,
+    tail: u64,
+    pending: Vec<PendingUpload>,
+    /// Dedicated staging buffers of the pending uploads.
+    dedicated: Vec<Buffer>,
+    in_flight: VecDeque<InFlightBatch>,
+    next_batch: u64,
+    completed_batch: Option<u64>,
+}
+
+unsafe impl Send for UploadManager {}
+unsafe impl Sync for UploadManager {}
+
+impl UploadManager {
+    pub fn new(device: &Device, size: u64) -> Self {
+        let mut staging = device.create_buffer(
+            BufferInfo {
+                align: STAGING_ALIGN_MASK,
+                size,
+                usage_flags: vk::BufferUsageFlags::TRANSFER_SRC,
+                allocation_flags: UsageFlags::UPLOAD,
+                name: Some("staging ring".into()),
+            },
+            UsageFlags::UPLOAD | UsageFlags::HOST_ACCESS,
+        );
+
+        let (mapping, coherent) = unsafe {
+            let memory_block = staging.memory_block();
+            let coherent = memory_block
+                .props()
+                .contains(MemoryPropertyFlags::HOST_COHERENT);
+            let mapping = memory_block
+                .map(EruptMemoryDevice::wrap(device.handle()), 0, size as usize)
+                .expect("Mapping staging ring failed");
+            (mapping, coherent)
+        };
+
+        UploadManager {
+            device: device.clone(),
+            staging,
+            mapping,
+            coherent,
+            size,
+            head: 0,
+            tail: 0,
+            pending: Vec::new(),
+            dedicated: Vec::new(),
+            in_flight: VecDeque::new(),
+            next_batch: 0,
+            completed_batch: None,
+        }
+    }
+
+    /// Creates a `DEVICE_ONLY` buffer and schedules `data` to be copied into it.
+    pub fn create_buffer_with_data<T>(&mut self, mut info: BufferInfo, data: &[T]) -> Buffer
+    where
+        T: Pod,
+    {
+        info.usage_flags |= vk::BufferUsageFlags::TRANSFER_DST;
+        let buffer: Buffer = self.device.create_buffer(info, UsageFlags::empty()).into();
+
+        self.upload_buffer(&buffer, 0, data);
+        buffer
+    }
+
+    pub fn upload_buffer<T>(&mut self, dst: &Buffer, dst_offset: u64, data: &[T]) -> UploadId
+    where
+        T: Pod,
+    {
+        let bytes = bytemuck::cast_slice(data);
+        let (staging, staging_offset) = self.write(bytes);
+
+        self.pending.push(PendingUpload::Buffer {
+            staging,
+            staging_offset,
+            dst: dst.clone(),
+            dst_offset,
+            size: bytes.len() as u64,
+        });
+
+        UploadId(self.next_batch)
+    }
+
+    /// Uploads tightly packed texels to one mip level of `dst`, leaving the
+    /// image in `final_layout`. Previous contents of the region are discarded.
+    pub fn upload_image(
+        &mut self,
+        dst: &Image,
+        subresource: ImageSubresourceLayers,
+        offset: vk::Offset3D,
+        extent: vk::Extent3D,
+        data: &[u8],
+        final_layout: vk::ImageLayout,
+    ) -> UploadId {
+        if let Some(description) = format::describe(dst.info().format) {
+            assert_eq!(
+                data.len() as u64,
+                description.region_size(extent.width, extent.height)
+                    * extent.depth as u64
+                    * subresource.layer_count as u64,
+                "texel data does not match the upload extent"
+            );
+        }
+
+        let (staging, staging_offset) = self.write(data);
+
+        self.pending.push(PendingUpload::Image {
+            staging,
+            staging_offset,
+            dst: dst.clone(),
+            subresource,
+            offset,
+            extent,
+            final_layout,
+        });
+
+        UploadId(self.next_batch)
+    }
+
+    pub fn is_complete(&mut self, id: UploadId) -> bool {
+        self.release_completed();
+        self.completed_batch
+            .map_or(false, |completed| id.0 <= completed)
+    }
+
+    /// Records every pending upload into one command buffer and submits it
+    /// to `transfer`, or to `queue` without one. Returns the point of `queue`
+    /// the uploads are complete at, or `None` when nothing was pending.
+    ///
+    /// The copies are made visible to all later commands on `queue`. From
+    /// another family, `transfer` releases the written resources and a second
+    /// submission to `queue` acquires them.
+    pub fn flush(
+        &mut self,
+        queue: &mut Queue,
+        transfer: Option<&mut Queue>,
+    ) -> Option<TimelinePoint> {
+        self.release_completed();
+
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        if !self.coherent {
+            unsafe {
+                self.device
+                    .flush_mapped_range(self.staging.memory_block(), 0..self.size);
+            }
+        }
+
+        let mut buffer_copies = Vec::new();
+        let mut image_copies = Vec::new();
+        for upload in &self.pending {
+            match upload {
+                PendingUpload::Buffer {
+                    staging,
+                    staging_offset,
+                    dst,
+                    dst_offset,
+                    size,
+                } => buffer_copies.push((
+                    staging,
+                    dst,
+                    [vk::BufferCopy {
+                        src_offset: *staging_offset,
+                        dst_offset: *dst_offset,
+                        size: *size,
+                    }],
+                )),
+                PendingUpload::Image {
+                    staging,
+                    staging_offset,
+                    dst,
+                    subresource,
+                    offset,
+                    extent,
+                    final_layout,
+                } => image_copies.push((
+                    staging,
+                    dst,
+                    ImageSubresourceRange::new(
+                        subresource.aspect,
+                        subresource.level..subresource.level + 1,
+                        subresource.first_layer..subresource.first_layer + subresource.layer_count,
+                    ),
+                    [vk::BufferImageCopy {
+                        buffer_offset: *staging_offset,
+                        buffer_row_length: 0,
+                        buffer_image_height: 0,
+                        image_subresource: vk::ImageSubresourceLayers {
+                            aspect_mask: subresource.aspect,
+                            mip_level: subresource.level,
+                            base_array_layer: subresource.first_layer,
+                            layer_count: subresource.layer_count,
+                        },
+                        image_offset: *offset,
+                        image_extent: *extent,
+                    }],
+                    *final_layout,
+                )),
+            }
+        }
+
+        let to_transfer = image_copies
+            .iter()
+            .map(|(_, image, range, _, _)| ImageMemoryBarrier {
+                image,
+                old_layout: None,
+                new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                family_transfer: None,
+                subresource: range.clone(),
+            })
+            .collect::<SmallVec<[_; 8]>>();
+
+        let transfer = transfer.filter(|transfer| transfer.family_index() != queue.family_index());
+        let family_transfer = transfer
+            .as_ref()
+            .map(|transfer| transfer.family_index()..queue.family_index());
+
+        let to_final = image_copies
+            .iter()
+            .map(|(_, image, range, _, final_layout)| ImageMemoryBarrier {
+                image,
+                old_layout: Some(vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+                new_layout: *final_layout,
+                family_transfer: family_transfer.clone(),
+                subresource: range.clone(),
+            })
+            .collect::<SmallVec<[_; 8]>>();
+
+        let buffers_to_write = buffer_copies
+            .iter()
+            .filter(|_| family_transfer.is_none())
+            .map(|(_, buffer, copy)| BufferMemoryBarrier {
+                buffer,
+                offset: copy[0].dst_offset,
+                size: copy[0].size,
+                family_transfer: None,
+            })
+            .collect::<SmallVec<[_; 8]>>();
+
+        let buffers_written = buffer_copies
+            .iter()
+            .map(|(_, buffer, copy)| BufferMemoryBarrier {
+                buffer,
+                offset: copy[0].dst_offset,
+                size: copy[0].size,
+                family_transfer: family_transfer.clone(),
+            })
+            .collect::<SmallVec<[_; 8]>>();
+
+        // Destinations may be overwritten while earlier frames still read
+        // them, so the copies wait for the last submission that used them.
+        let previous = [(
+            vk::PipelineStageFlags::TRANSFER,
+            queue.timeline().last_submitted(),
+        )];
+
+        // Transfer queues don't support the stages that read the destinations,
+        // there the timeline wait alone orders the copies after those reads.
+        let last_use = if family_transfer.is_some() {
+            vk::PipelineStageFlags::TOP_OF_PIPE
+        } else {
+            vk::PipelineStageFlags::VERTEX_INPUT
+                | vk::PipelineStageFlags::VERTEX_SHADER
+                | vk::PipelineStageFlags::FRAGMENT_SHADER
+        };
+
+        let copy_queue = match transfer {
+            Some(transfer) => transfer,
+            None => &mut *queue,
+        };
+        let mut encoder = copy_queue.create_enconder();
+        encoder.push_label("uploads");
+
+        if !to_transfer.is_empty() || !buffers_to_write.is_empty() {
+            encoder.pipeline_barrier(
+                last_use,
+                vk::PipelineStageFlags::TRANSFER,
+                &to_transfer,
+                &buffers_to_write,
+            );
+        }
+
+        for (staging, dst, copy) in &buffer_copies {
+            encoder.copy_buffer(staging, dst, copy);
+        }
+
+        for (staging, dst, _, copy, _) in &image_copies {
+            encoder.copy_buffer_to_image(staging, dst, vk::ImageLayout::TRANSFER_DST_OPTIMAL, copy);
+        }
+
+        // A release ignores the destination stages, the acquire on `queue`
+        // makes the copies visible.
+        encoder.pipeline_barrier(
+            vk::PipelineStageFlags::TRANSFER,
+            if family_transfer.is_some() {
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE
+            } else {
+                vk::PipelineStageFlags::ALL_COMMANDS
+            },
+            &to_final,
+            &buffers_written,
+        );
+
+        encoder.pop_label();
+
+        let mut point = copy_queue.submit(
+            encoder.finish(&self.device),
+            SubmitInfo {
+                timeline_wait: &previous,
+                ..SubmitInfo::default()
+            },
+        );
+
+        if family_transfer.is_some() {
+            let mut encoder = queue.create_enconder();
+            encoder.push_label("upload acquire");
+            encoder.pipeline_barrier(
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                &to_final,
+                &buffers_written,
+            );
+            encoder.pop_label();
+
+            point = queue.submit(
+                encoder.finish(&self.device),
+                SubmitInfo {
+                    timeline_wait: &[(vk::PipelineStageFlags::ALL_COMMANDS, point)],
+                    ..SubmitInfo::default()
+                },
+            );
+        }
+
+        self.in_flight.push_back(InFlightBatch {
+            id: self.next_batch,
+            point: point.clone(),
+            ring_end: self.head,
+            dedicated: std::mem::take(&mut self.dedicated),
+        });
+        self.pending.clear();
+        self.next_batch += 1;
+
+        Some(point)
+    }
+
+    /// Copies `bytes` into the ring, waiting for in-flight batches to free
+    /// space when it is full, and returns where they were copied to.
+    fn write(&mut self, bytes: &[u8]) -> (Buffer, u64) {
+        let size = bytes.len() as u64;
+
+        let offset = loop {
+            if size > self.size {
+                break None;
+            }
+
+            self.release_completed();
+
+            if let Some(offset) = self.allocate(size) {
+                break Some(offset);
+            }
+
+            match self.in_flight.front() {
+                Some(batch) => {
+                    self.device.wait_timeline(&[batch.point.clone()], !0);
+                }
+                None => break None,
+            }
+        };
+
+        let offset = match offset {
+            Some(offset) => offset,
+            None => return (self.write_dedicated(bytes), 0),
+        };
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                self.mapping.as_ptr().add(offset as usize),
+                bytes.len(),
+            );
+        }
+
+        ((*self.staging).clone(), offset)
+    }
+
+    /// Copies `bytes` into a new staging buffer of their own, destroyed once
+    /// the batch they are flushed in completes.
+    fn write_dedicated(&mut self, bytes: &[u8]) -> Buffer {
+        let mut staging = self.device.create_buffer(
+            BufferInfo {
+                align: STAGING_ALIGN_MASK,
+                size: bytes.len() as u64,
+                usage_flags: vk::BufferUsageFlags::TRANSFER_SRC,
+                allocation_flags: UsageFlags::UPLOAD,
+                name: Some("dedicated staging".into()),
+            },
+            UsageFlags::UPLOAD | UsageFlags::HOST_ACCESS,
+        );
+
+        unsafe {
+            let memory_device = EruptMemoryDevice::wrap(self.device.handle());
+            let memory_block = staging.memory_block();
+            let mapping = memory_block
+                .map(memory_device, 0, bytes.len())
+                .expect("Mapping dedicated staging buffer failed");
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), mapping.as_ptr(), bytes.len());
+
+            if !memory_block
+                .props()
+                .contains(MemoryPropertyFlags::HOST_COHERENT)
+            {
+                self.device
+                    .flush_mapped_range(memory_block, 0..bytes.len() as u64);
+            }
+            memory_block.unmap(memory_device);
+        }
+
+        let staging = Buffer::from(staging);
+        self.dedicated.push(staging.clone());
+        staging
+    }
+
+    fn allocate(&mut self, size: u64) -> Option<u64> {
+        if self.pending.is_empty() && self.in_flight.is_empty() {
+            self.head = 0;
+            self.tail = 0;
+        }
+
+        let start = align_up(STAGING_ALIGN_MASK, self.head)?;
+        let offset = if self.head >= self.tail {
+            if start + size <= self.size {
+                start
+            } else if size < self.tail {
+                0
+            } else {
+                return None;
+            }
+        } else if start + size < self.tail {
+            start
+        } else {
+            return None;
+        };
+
+        self.head = offset + size;
+        Some(offset)
+    }
+
+    fn release_completed(&mut self) {
+        while let Some(batch) = self.in_flight.front() {
+            if self.device.semaphore_value(&batch.point.semaphore) < batch.point.value {
+                break;
+            }
+
+            self.tail = batch.ring_end;
+            self.completed_batch = Some(batch.id);
+            for staging in &batch.dedicated {
+                self.device.destroy_buffer(staging);
+            }
+            self.in_flight.pop_front();
+        }
+    }
+
+    /// Destroys the staging buffers, once the device is idle.
+    pub fn cleanup(&mut self) {
+        unsafe {
+            self.staging
+                .memory_block()
+                .unmap(EruptMemoryDevice::wrap(self.device.handle()));
+        }
+        self.device.destroy_buffer(&self.staging);
+
+        let dedicated = self
+            .in_flight
+            .drain(..)
+            .flat_map(|batch| batch.dedicated)
+            .chain(self.dedicated.drain(..));
+        for staging in dedicated {
+            self.device.destroy_buffer(&staging);
+        }
+    }
+}
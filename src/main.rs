@@ -1,6 +1,15 @@
 use bevy::prelude::*;
 
+#[cfg(feature = "embed-shaders")]
+include!(concat!(env!("OUT_DIR"), "/embedded_shaders.rs"));
+
 fn main() {
+    let asset_settings = rdx_renderer::AssetSettings {
+        #[cfg(feature = "embed-shaders")]
+        embedded: EMBEDDED_SHADERS,
+        ..Default::default()
+    };
+
     App::build()
         .insert_resource(bevy::log::LogSettings {
             level: bevy::utils::tracing::Level::DEBUG,
@@ -12,6 +21,11 @@ fn main() {
             title: "tracer".to_string(),
             ..Default::default()
         })
+        .insert_resource(bevy::asset::AssetServerSettings {
+            watch_for_changes: true,
+            ..asset_settings.asset_server_settings()
+        })
+        .insert_resource(asset_settings)
         .add_plugin(bevy::log::LogPlugin::default())
         .add_plugin(bevy::core::CorePlugin::default())
         .add_plugin(bevy::transform::TransformPlugin::default())
@@ -23,5 +37,46 @@ fn main() {
         .add_plugin(bevy::asset::AssetPlugin::default())
         .add_plugin(bevy::scene::ScenePlugin::default())
         .add_plugin(rdx_renderer::RenderPlugin::default())
+        .add_plugin(rdx_renderer::CameraControllerPlugin::default())
+        .add_startup_system(setup_scene.system())
         .run()
 }
+
+fn setup_scene(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut scene_spawner: ResMut<SceneSpawner>,
+) {
+    scene_spawner.spawn(asset_server.load("models/FlightHelmet/FlightHelmet.gltf"));
+
+    commands
+        .spawn()
+        .insert(rdx_renderer::Light::Directional {
+            color: [1.0, 0.95, 0.9],
+            intensity: 3.0,
+        })
+        .insert(Transform::from_rotation(Quat::from_rotation_ypr(
+            0.6, -0.8, 0.0,
+        )))
+        .insert(GlobalTransform::default());
+
+    commands
+        .spawn()
+        .insert(rdx_renderer::Light::Point {
+            color: [0.4, 0.6, 1.0],
+            intensity: 2.0,
+            range: 10.0,
+        })
+        .insert(Transform::from_xyz(-1.0, 1.0, 1.0))
+        .insert(GlobalTransform::default());
+
+    commands
+        .spawn()
+        .insert(rdx_renderer::Camera::default())
+        .insert(rdx_renderer::OrbitController {
+            distance: 2.0,
+            ..Default::default()
+        })
+        .insert(Transform::default())
+        .insert(GlobalTransform::default());
+}